@@ -0,0 +1,33 @@
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Captures the git commit and build time for the `/version` endpoint.
+fn main() {
+    // a GIT_COMMIT set by the build environment wins, e.g. for Docker builds without git
+    let commit = std::env::var("GIT_COMMIT").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short=12", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+    });
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=GIT_COMMIT={}", commit.trim());
+    }
+
+    // honour SOURCE_DATE_EPOCH so reproducible builds get a fixed timestamp
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH").unwrap_or_else(|_| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default()
+            .to_string()
+    });
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={timestamp}");
+
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
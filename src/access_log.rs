@@ -0,0 +1,322 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::Instant;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderName, Method, Uri, Version},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use serde_json::json;
+
+use crate::config::{AccessLogFormat, LoggingSettings};
+
+/// The format of the timestamp in a combined log line.
+const COMBINED_TIME_FORMAT: &str = "%d/%b/%Y:%H:%M:%S %z";
+
+/// The access log's format, the header carrying each request's id, and where its lines are
+/// written.
+#[derive(Clone)]
+pub struct AccessLog {
+    format: AccessLogFormat,
+    request_id_header: HeaderName,
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl AccessLog {
+    #[must_use]
+    pub fn new(
+        format: AccessLogFormat,
+        request_id_header: HeaderName,
+        writer: impl Write + Send + 'static,
+    ) -> Self {
+        Self {
+            format,
+            request_id_header,
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+        }
+    }
+
+    /// Builds the access log from the logging settings, appending its lines to
+    /// `access_log_path`, or writing them to stderr when it is empty, apart from the
+    /// application's logs on stdout.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the access log file cannot be opened.
+    pub fn from_settings(
+        settings: &LoggingSettings,
+        request_id_header: HeaderName,
+    ) -> io::Result<Self> {
+        let writer: Box<dyn Write + Send> =
+            if settings.access_log == AccessLogFormat::Off || settings.access_log_path.is_empty() {
+                Box::new(io::stderr())
+            } else {
+                Box::new(
+                    OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&settings.access_log_path)?,
+                )
+            };
+        Ok(Self::new(settings.access_log, request_id_header, writer))
+    }
+
+    /// Writes a line to the access log.
+    fn write(&self, line: &str) {
+        let mut writer = self.writer.lock().expect("Access log lock poisoned");
+        if let Err(err) = writeln!(writer, "{line}").and_then(|()| writer.flush()) {
+            tracing::warn!("Failed to write to the access log: {err}");
+        }
+    }
+}
+
+/// Middleware writing an access log line for each request, when enabled, once its response body
+/// has been sent, so the line carries the number of body bytes actually sent.
+pub async fn access_log(State(log): State<AccessLog>, req: Request, next: Next) -> Response {
+    if log.format == AccessLogFormat::Off {
+        return next.run(req).await;
+    }
+    let started = Instant::now();
+    let client_ip = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or_else(
+            || "-".to_string(),
+            |ConnectInfo(addr)| addr.ip().to_string(),
+        );
+    let mut entry = Entry {
+        started,
+        client_ip,
+        method: req.method().clone(),
+        uri: req.uri().clone(),
+        version: req.version(),
+        request_id: header_or_dash(req.headers(), &log.request_id_header),
+        referer: header_or_dash(req.headers(), &header::REFERER),
+        user_agent: header_or_dash(req.headers(), &header::USER_AGENT),
+        status: 0,
+        log,
+    };
+
+    let response = next.run(req).await;
+    entry.status = response.status().as_u16();
+    response.map(|body| {
+        Body::new(LoggedBody {
+            inner: body,
+            bytes: 0,
+            entry: Some(entry),
+        })
+    })
+}
+
+/// What is known of a request, and the status of its response, until its line is written.
+struct Entry {
+    log: AccessLog,
+    started: Instant,
+    client_ip: String,
+    method: Method,
+    uri: Uri,
+    version: Version,
+    request_id: String,
+    referer: String,
+    user_agent: String,
+    status: u16,
+}
+
+impl Entry {
+    /// Writes the line for the request, with the number of body bytes sent.
+    fn write(self, bytes: u64) {
+        let Self {
+            log,
+            started,
+            client_ip,
+            method,
+            uri,
+            version,
+            request_id,
+            referer,
+            user_agent,
+            status,
+        } = self;
+        let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+        let line = match log.format {
+            AccessLogFormat::Combined => {
+                // as in Apache logs, an empty body is logged as `-`
+                let bytes = if bytes == 0 {
+                    "-".to_string()
+                } else {
+                    bytes.to_string()
+                };
+                format!(
+                    "{client_ip} - - [{}] \"{method} {} {version:?}\" {status} {bytes} \
+                     \"{}\" \"{}\" {latency_ms:.3}ms {request_id}",
+                    Utc::now().format(COMBINED_TIME_FORMAT),
+                    escape(&uri.to_string()),
+                    escape(&referer),
+                    escape(&user_agent),
+                )
+            }
+            _ => json!({
+                "time": Utc::now().to_rfc3339(),
+                "client_ip": client_ip,
+                "method": method.as_str(),
+                "path": uri.path(),
+                "query": uri.query(),
+                "status": status,
+                "bytes": bytes,
+                "latency_ms": latency_ms,
+                "request_id": request_id,
+                "referer": referer,
+                "user_agent": user_agent,
+            })
+            .to_string(),
+        };
+        log.write(&line);
+    }
+}
+
+/// A response body counting the bytes sent, which writes the access log line once it is done
+/// with: sent in full, or dropped early because the client went away.
+struct LoggedBody {
+    inner: Body,
+    bytes: u64,
+    entry: Option<Entry>,
+}
+
+impl HttpBody for LoggedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        let frame = ready!(Pin::new(&mut this.inner).poll_frame(cx));
+        if let Some(data) = frame
+            .as_ref()
+            .and_then(|frame| frame.as_ref().ok())
+            .and_then(Frame::data_ref)
+        {
+            this.bytes += data.len() as u64;
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            entry.write(self.bytes);
+        }
+    }
+}
+
+/// The header's value, or `-` when it is missing or not text, as in Apache logs.
+fn header_or_dash(headers: &HeaderMap, name: &HeaderName) -> String {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("-")
+        .to_string()
+}
+
+/// Escapes `"` and `\` with a backslash, as Apache does, so a quoted field can't end early.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{to_bytes, Body},
+        http::{HeaderName, Request, StatusCode},
+        middleware,
+        routing::get,
+        Router,
+    };
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use super::{access_log, AccessLog};
+    use crate::config::AccessLogFormat;
+    use crate::util::testing::LogBuffer;
+
+    /// Sends a request through the access log, reads its response and returns what was logged.
+    async fn logged(format: AccessLogFormat, user_agent: &str) -> String {
+        let logs = LogBuffer::default();
+        let log = AccessLog::new(
+            format,
+            HeaderName::from_static("x-request-id"),
+            logs.clone(),
+        );
+        let app = Router::new()
+            .route(
+                "/fact",
+                get(|| async { (StatusCode::NOT_FOUND, "no fact") }),
+            )
+            .layer(middleware::from_fn_with_state(log, access_log));
+
+        let req = Request::builder()
+            .uri("/fact?animal=cat")
+            .header("x-request-id", "abc-123")
+            .header("user-agent", user_agent)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(req).await.unwrap();
+        assert!(logs.contents().is_empty());
+        to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        logs.contents()
+    }
+
+    #[tokio::test]
+    async fn test_combined_access_log() {
+        let output = logged(AccessLogFormat::Combined, "curl/8.0").await;
+
+        assert!(output.contains("\"GET /fact?animal=cat HTTP/1.1\" 404 7 "));
+        assert!(output.contains("\"curl/8.0\""));
+        assert!(output.contains("abc-123"));
+        assert!(output.ends_with('\n'));
+    }
+
+    #[tokio::test]
+    async fn test_combined_access_log_escapes_quotes() {
+        let output = logged(AccessLogFormat::Combined, r#"evil" "agent\"#).await;
+
+        assert!(output.contains(r#" "evil\" \"agent\\" "#));
+    }
+
+    #[tokio::test]
+    async fn test_json_access_log() {
+        let output = logged(AccessLogFormat::Json, "curl/8.0").await;
+
+        let line: Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!("GET", line["method"]);
+        assert_eq!("/fact", line["path"]);
+        assert_eq!(404, line["status"]);
+        assert_eq!(7, line["bytes"]);
+        assert_eq!("abc-123", line["request_id"]);
+    }
+
+    #[tokio::test]
+    async fn test_access_log_off() {
+        assert!(logged(AccessLogFormat::Off, "curl/8.0").await.is_empty());
+    }
+}
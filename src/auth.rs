@@ -0,0 +1,108 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use subtle::{Choice, ConstantTimeEq};
+
+use crate::request_id::with_request_id;
+use crate::state::AppState;
+
+/// The header clients send their API key in.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Middleware rejecting requests without a configured API key. Auth is disabled when no keys are
+/// configured.
+pub async fn require_api_key(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if state.api_keys.is_empty() {
+        return next.run(req).await;
+    }
+    match req.headers().get(API_KEY_HEADER) {
+        None => reject(
+            StatusCode::UNAUTHORIZED,
+            "missing_api_key",
+            "An API key is required.",
+        ),
+        Some(key) if key.to_str().is_ok_and(|key| state.api_keys.contains(key)) => {
+            next.run(req).await
+        }
+        Some(_) => reject(
+            StatusCode::FORBIDDEN,
+            "invalid_api_key",
+            "The API key is not valid.",
+        ),
+    }
+}
+
+/// Middleware rejecting requests without a configured API key, even when no keys are configured,
+/// for routes that change what the service serves.
+pub async fn require_configured_api_key(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if state.api_keys.is_empty() {
+        return reject(
+            StatusCode::FORBIDDEN,
+            "api_keys_disabled",
+            "This endpoint needs API keys to be configured.",
+        );
+    }
+    require_api_key(State(state), req, next).await
+}
+
+/// Middleware rejecting requests without a configured admin API key. Admin routes are disabled
+/// when no admin keys are configured.
+pub async fn require_admin_key(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if state.admin_api_keys.is_empty() {
+        return reject(
+            StatusCode::FORBIDDEN,
+            "admin_disabled",
+            "Admin endpoints are disabled.",
+        );
+    }
+    match req.headers().get(API_KEY_HEADER) {
+        None => reject(
+            StatusCode::UNAUTHORIZED,
+            "missing_api_key",
+            "An admin API key is required.",
+        ),
+        Some(key)
+            if key
+                .to_str()
+                .is_ok_and(|key| contains_key(&state.admin_api_keys, key)) =>
+        {
+            next.run(req).await
+        }
+        Some(_) => reject(
+            StatusCode::FORBIDDEN,
+            "invalid_api_key",
+            "The admin API key is not valid.",
+        ),
+    }
+}
+
+/// Checks whether the key is one of `keys`, comparing it with each of them in constant time so
+/// the time taken doesn't reveal how much of a key was guessed.
+fn contains_key(keys: &HashSet<String>, key: &str) -> bool {
+    keys.iter()
+        .fold(Choice::from(0), |found, k| {
+            found | k.as_bytes().ct_eq(key.as_bytes())
+        })
+        .into()
+}
+
+fn reject(status: StatusCode, code: &str, message: &str) -> Response {
+    let value = json!({ "error": { "code": code, "message": message } });
+    tracing::warn!("Rejected request: {value}");
+    (status, Json(with_request_id(value))).into_response()
+}
@@ -0,0 +1,141 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http_body::Body as _;
+use tracing::Level;
+
+use crate::config::LoggingSettings;
+use crate::util::truncate;
+
+/// The largest response body buffered to be logged, in bytes.
+const MAX_BUFFERED_BODY_BYTES: usize = 64 * 1024;
+
+/// Middleware logging each request's query and response body at debug level, when enabled and
+/// debug logs are on.
+///
+/// Headers are never logged, so neither is the API key. Bodies are cut to `max_body_len`
+/// characters. Bodies larger than `MAX_BUFFERED_BODY_BYTES`, or of unknown length such as event
+/// streams, are passed on without being buffered or logged.
+pub async fn log_bodies(
+    State(settings): State<LoggingSettings>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if !settings.capture_bodies || !tracing::enabled!(Level::DEBUG) {
+        return next.run(req).await;
+    }
+    tracing::debug!("Request query: {}", req.uri().query().unwrap_or_default());
+    let response = next.run(req).await;
+    let len = response.body().size_hint().upper();
+    if len.is_none_or(|len| len > MAX_BUFFERED_BODY_BYTES as u64) {
+        tracing::debug!("Response body not logged, as it is streamed or too large");
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, MAX_BUFFERED_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            tracing::warn!("Failed to read response body to log: {err}");
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    tracing::debug!(
+        "Response body: {}",
+        truncate(&String::from_utf8_lossy(&bytes), settings.max_body_len)
+    );
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{to_bytes, Body},
+        http::Request,
+        middleware,
+        routing::get,
+        Json, Router,
+    };
+    use serde_json::json;
+    use tower::ServiceExt;
+    use tracing::Level;
+
+    use super::{log_bodies, MAX_BUFFERED_BODY_BYTES};
+    use crate::config::LoggingSettings;
+    use crate::util::testing::LogBuffer;
+
+    #[tokio::test]
+    async fn test_log_bodies_logs_query_and_response_at_debug() {
+        let settings = LoggingSettings {
+            capture_bodies: true,
+            ..LoggingSettings::default()
+        };
+        let app = Router::new()
+            .route(
+                "/fact",
+                get(|| async { Json(json!({ "fact": "Cats sleep for 16 hours a day." })) }),
+            )
+            .layer(middleware::from_fn_with_state(settings, log_bodies));
+
+        let logs = LogBuffer::default();
+        let sub = tracing_subscriber::fmt()
+            .with_max_level(Level::DEBUG)
+            .with_writer(logs.clone())
+            .finish();
+        let guard = tracing::subscriber::set_default(sub);
+        let req = Request::builder()
+            .uri("/fact?animal=cat")
+            .header("x-api-key", "secret")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        drop(guard);
+
+        assert!(res.status().is_success());
+        let output = logs.contents();
+        assert!(output.contains("DEBUG"));
+        assert!(output.contains("animal=cat"));
+        assert!(output.contains("Cats sleep for 16 hours a day."));
+        assert!(!output.contains("secret"));
+    }
+
+    /// Sends a request for a body of `len` bytes, logging at `level`, and returns what was
+    /// logged along with the body.
+    async fn logged(level: Level, len: usize) -> (String, usize) {
+        let settings = LoggingSettings {
+            capture_bodies: true,
+            ..LoggingSettings::default()
+        };
+        let app = Router::new()
+            .route("/fact", get(move || async move { "a".repeat(len) }))
+            .layer(middleware::from_fn_with_state(settings, log_bodies));
+
+        let logs = LogBuffer::default();
+        let sub = tracing_subscriber::fmt()
+            .with_max_level(level)
+            .with_writer(logs.clone())
+            .finish();
+        let guard = tracing::subscriber::set_default(sub);
+        let req = Request::builder().uri("/fact").body(Body::empty()).unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        drop(guard);
+
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        (logs.contents(), body.len())
+    }
+
+    #[tokio::test]
+    async fn test_log_bodies_skips_large_bodies_and_disabled_debug_logs() {
+        let (output, len) = logged(Level::DEBUG, MAX_BUFFERED_BODY_BYTES + 1).await;
+        assert!(output.contains("not logged"));
+        assert!(!output.contains("Response body: "));
+        assert_eq!(MAX_BUFFERED_BODY_BYTES + 1, len);
+
+        let (output, len) = logged(Level::INFO, 10).await;
+        assert!(output.is_empty());
+        assert_eq!(10, len);
+    }
+}
@@ -0,0 +1,500 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, Utc};
+use rand::{rngs::StdRng, seq::IteratorRandom, Rng};
+
+use crate::metrics::GaugeGuard;
+
+#[cfg(feature = "redis")]
+pub use redis_store::RedisStore;
+
+#[cfg(feature = "redis")]
+mod redis_store;
+
+/// Where fetched facts and facts of the day are kept, either in this process or shared between
+/// replicas.
+///
+/// Lookups never fail: a store that can't be reached behaves as if it were empty.
+#[async_trait]
+pub trait FactStore<T>: Send + Sync {
+    /// Returns a random cached fact for the animal, if there is one to serve.
+    async fn get_fact(&self, animal: &str, rng: &mut StdRng) -> Option<CachedFact<T>>;
+
+    /// Caches a freshly fetched fact for the animal.
+    async fn insert_fact(&self, animal: &str, fact: T);
+
+    /// The number of facts cached across all animals, if the store can count them cheaply.
+    async fn size(&self) -> Option<usize>;
+
+    /// The number of facts cached for the animal that will still be fresh in `within`.
+    async fn fresh_count(&self, animal: &str, within: Duration) -> usize;
+
+    /// Checks whether the store can be reached.
+    async fn healthy(&self) -> bool;
+
+    /// Returns the animal's fact for the day, if one was stored.
+    async fn get_daily(&self, date: NaiveDate, animal: &'static str) -> Option<String>;
+
+    /// Stores the animal's fact for the day unless one already exists, returning the stored fact.
+    async fn insert_daily(&self, date: NaiveDate, animal: &'static str, fact: String) -> String;
+
+    /// Evicts the cached facts and facts of the day for the animal, or for every animal when
+    /// `None`, returning the number of entries evicted.
+    async fn flush(&self, animal: Option<&str>) -> usize;
+}
+
+/// A fact served from the cache.
+#[derive(Debug, PartialEq)]
+pub struct CachedFact<T> {
+    pub fact: T,
+    /// Whether the fact is past its TTL and should be refreshed in the background.
+    pub stale: bool,
+    /// How long until the fact is past its TTL, zero once it is.
+    pub expires_in: Duration,
+}
+
+/// The in-memory fact store, local to this process.
+pub struct MemoryStore<T> {
+    facts: FactCache<T>,
+    daily: DailyFacts,
+}
+
+impl<T: Clone> MemoryStore<T> {
+    #[must_use]
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            facts: FactCache::new(ttl, capacity),
+            daily: DailyFacts::default(),
+        }
+    }
+
+    /// Serves facts for `stale_ttl` after they expire, while they are refreshed.
+    #[must_use]
+    pub fn with_stale_ttl(mut self, stale_ttl: Duration) -> Self {
+        self.facts = self.facts.with_stale_ttl(stale_ttl);
+        self
+    }
+}
+
+#[async_trait]
+impl<T: Clone + Send + Sync> FactStore<T> for MemoryStore<T> {
+    async fn get_fact(&self, animal: &str, rng: &mut StdRng) -> Option<CachedFact<T>> {
+        self.facts.get(animal, rng)
+    }
+
+    async fn insert_fact(&self, animal: &str, fact: T) {
+        self.facts.insert(animal, fact);
+    }
+
+    async fn size(&self) -> Option<usize> {
+        Some(self.facts.len())
+    }
+
+    async fn fresh_count(&self, animal: &str, within: Duration) -> usize {
+        self.facts.fresh_count(animal, within)
+    }
+
+    async fn healthy(&self) -> bool {
+        true
+    }
+
+    async fn get_daily(&self, date: NaiveDate, animal: &'static str) -> Option<String> {
+        self.daily.get(date, animal)
+    }
+
+    async fn insert_daily(&self, date: NaiveDate, animal: &'static str, fact: String) -> String {
+        self.daily.insert(date, animal, fact)
+    }
+
+    async fn flush(&self, animal: Option<&str>) -> usize {
+        self.facts.remove(animal) + self.daily.remove(animal)
+    }
+}
+
+/// A cached fact, the time it was stored and whether a refresh has been asked for.
+struct Entry<T> {
+    fact: T,
+    stored_at: Instant,
+    refreshing: bool,
+}
+
+/// An in-memory, per-animal cache holding up to `capacity` recently fetched facts for `ttl`,
+/// and serving them as stale for a further `stale_ttl`.
+pub struct FactCache<T = String> {
+    ttl: Duration,
+    stale_ttl: Duration,
+    capacity: usize,
+    entries: RwLock<HashMap<String, VecDeque<Entry<T>>>>,
+}
+
+impl<T: Clone> FactCache<T> {
+    #[must_use]
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            stale_ttl: Duration::ZERO,
+            capacity,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Serves facts for `stale_ttl` after they expire, while they are refreshed.
+    #[must_use]
+    pub fn with_stale_ttl(mut self, stale_ttl: Duration) -> Self {
+        self.stale_ttl = stale_ttl;
+        self
+    }
+
+    /// Returns a random fact for the animal that is fresh or still within the stale window, if
+    /// there is one.
+    ///
+    /// A stale fact is only reported as stale the first time it is served, so one refresh is
+    /// asked for per expired fact.
+    pub fn get(&self, animal: &str, rng: &mut impl Rng) -> Option<CachedFact<T>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let max_age = self.ttl.saturating_add(self.stale_ttl);
+        let (fact, stored_at, refreshing) = {
+            let entries = self.entries.read().expect("Fact cache lock poisoned");
+            let entry = entries
+                .get(animal)?
+                .iter()
+                .filter(|entry| entry.stored_at.elapsed() < max_age)
+                .choose(rng)?;
+            (entry.fact.clone(), entry.stored_at, entry.refreshing)
+        };
+        let age = stored_at.elapsed();
+        let stale = age >= self.ttl && !refreshing && self.mark_refreshing(animal, stored_at);
+        Some(CachedFact {
+            fact,
+            stale,
+            expires_in: self.ttl.saturating_sub(age),
+        })
+    }
+
+    /// Marks the animal's fact stored at `stored_at` as being refreshed, returning whether it
+    /// wasn't already.
+    fn mark_refreshing(&self, animal: &str, stored_at: Instant) -> bool {
+        let mut entries = self.entries.write().expect("Fact cache lock poisoned");
+        entries
+            .get_mut(animal)
+            .and_then(|facts| facts.iter_mut().find(|entry| entry.stored_at == stored_at))
+            .is_some_and(|entry| !std::mem::replace(&mut entry.refreshing, true))
+    }
+
+    /// The number of facts held across all animals, including expired ones not yet evicted.
+    pub fn len(&self) -> usize {
+        let entries = self.entries.read().expect("Fact cache lock poisoned");
+        entries.values().map(VecDeque::len).sum()
+    }
+
+    /// The number of facts held for the animal that will still be fresh in `within`.
+    pub fn fresh_count(&self, animal: &str, within: Duration) -> usize {
+        let entries = self.entries.read().expect("Fact cache lock poisoned");
+        entries.get(animal).map_or(0, |facts| {
+            facts
+                .iter()
+                .filter(|entry| entry.stored_at.elapsed().saturating_add(within) < self.ttl)
+                .count()
+        })
+    }
+
+    /// Checks whether no facts are held.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stores a fact for the animal, evicting its expired facts, then the oldest one when at
+    /// capacity.
+    pub fn insert(&self, animal: &str, fact: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        let max_age = self.ttl.saturating_add(self.stale_ttl);
+        let mut entries = self.entries.write().expect("Fact cache lock poisoned");
+        let facts = entries.entry(animal.to_string()).or_default();
+        facts.retain(|entry| entry.stored_at.elapsed() < max_age);
+        if facts.len() >= self.capacity {
+            facts.pop_front();
+        }
+        facts.push_back(Entry {
+            fact,
+            stored_at: Instant::now(),
+            refreshing: false,
+        });
+    }
+
+    /// Evicts the facts held for the animal, or for every animal when `None`, returning how many
+    /// were held.
+    pub fn remove(&self, animal: Option<&str>) -> usize {
+        let mut entries = self.entries.write().expect("Fact cache lock poisoned");
+        match animal {
+            Some(animal) => entries.remove(animal).map_or(0, |facts| facts.len()),
+            None => entries.drain().map(|(_, facts)| facts.len()).sum(),
+        }
+    }
+}
+
+/// A rejected animal string, with when it was stored and last looked up. It is counted as a
+/// distinct unsupported animal until it is removed.
+struct Rejection {
+    stored_at: Instant,
+    used_at: Instant,
+    _counted: GaugeGuard,
+}
+
+/// A short-lived cache of animal strings known to be unsupported, so repeated bad requests can
+/// be rejected without resolving them again.
+///
+/// Entries expire after `ttl`, and the least recently used one is evicted when at capacity.
+pub struct RejectedAnimals {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<String, Rejection>>,
+}
+
+impl RejectedAnimals {
+    #[must_use]
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Checks whether the animal was recently rejected.
+    pub fn contains(&self, animal: &str) -> bool {
+        let mut entries = self.entries.lock().expect("Rejected animals lock poisoned");
+        let key = animal.to_lowercase();
+        match entries.get_mut(&key) {
+            Some(entry) if entry.stored_at.elapsed() < self.ttl => {
+                entry.used_at = Instant::now();
+                true
+            }
+            Some(_) => {
+                entries.remove(&key);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Stores a rejected animal, counted by the guard for as long as it is held.
+    pub fn insert(&self, animal: &str, counted: GaugeGuard) {
+        let mut entries = self.entries.lock().expect("Rejected animals lock poisoned");
+        if self.capacity == 0 {
+            return;
+        }
+        entries.retain(|_, entry| entry.stored_at.elapsed() < self.ttl);
+        if entries.len() >= self.capacity {
+            let lru = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used_at)
+                .map(|(key, _)| key.clone());
+            if let Some(lru) = lru {
+                entries.remove(&lru);
+            }
+        }
+        let now = Instant::now();
+        entries.insert(
+            animal.to_lowercase(),
+            Rejection {
+                stored_at: now,
+                used_at: now,
+                _counted: counted,
+            },
+        );
+    }
+}
+
+/// The fact of the day for each animal.
+///
+/// Only days adjacent to today (UTC) are kept, along with the day being stored, so the store
+/// stays small while requests around midnight UTC still see a stable fact, and a request for a
+/// far-off day can't evict today's.
+#[derive(Default)]
+pub struct DailyFacts {
+    facts: Mutex<HashMap<(NaiveDate, &'static str), String>>,
+}
+
+impl DailyFacts {
+    /// Returns the animal's fact for the day, if one was stored.
+    pub fn get(&self, date: NaiveDate, animal: &'static str) -> Option<String> {
+        let facts = self.facts.lock().expect("Daily facts lock poisoned");
+        facts.get(&(date, animal)).cloned()
+    }
+
+    /// Stores the animal's fact for the day unless one already exists, returning the stored fact.
+    pub fn insert(&self, date: NaiveDate, animal: &'static str, fact: String) -> String {
+        let mut facts = self.facts.lock().expect("Daily facts lock poisoned");
+        let today = Utc::now().date_naive();
+        facts.retain(|(day, _), _| *day == date || (*day - today).num_days().abs() <= 1);
+        facts.entry((date, animal)).or_insert(fact).clone()
+    }
+
+    /// Evicts the facts of the day for the animal, or for every animal when `None`, returning how
+    /// many were held.
+    pub fn remove(&self, animal: Option<&str>) -> usize {
+        let mut facts = self.facts.lock().expect("Daily facts lock poisoned");
+        let held = facts.len();
+        facts.retain(|(_, stored), _| animal.is_some_and(|animal| animal != *stored));
+        held - facts.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{Days, NaiveDate, Utc};
+    use prometheus::IntGauge;
+
+    use super::{CachedFact, DailyFacts, FactCache, FactStore, MemoryStore, RejectedAnimals};
+    use crate::metrics::GaugeGuard;
+
+    #[test]
+    fn test_cache_hits_any_fresh_entry() {
+        let cache: FactCache = FactCache::new(Duration::from_secs(30), 2);
+        assert_eq!(None, cache.get("cat", &mut rand::thread_rng()));
+
+        cache.insert("cat", "fact one".into());
+        let cached = cache
+            .get("cat", &mut rand::thread_rng())
+            .expect("Expected a cache hit.");
+        assert_eq!("fact one", cached.fact);
+
+        cache.insert("cat", "fact two".into());
+        assert_eq!(2, cache.len());
+        let cached = cache
+            .get("cat", &mut rand::thread_rng())
+            .expect("Expected a cache hit.");
+        assert!(cached.fact == "fact one" || cached.fact == "fact two");
+        assert!(!cached.stale);
+        assert!(cached.expires_in > Duration::ZERO);
+        assert_eq!(None, cache.get("dog", &mut rand::thread_rng()));
+    }
+
+    #[test]
+    fn test_cache_counts_facts_fresh_for_a_while() {
+        let cache: FactCache = FactCache::new(Duration::from_secs(30), 2);
+        cache.insert("cat", "fact".into());
+
+        assert_eq!(1, cache.fresh_count("cat", Duration::from_secs(10)));
+        assert_eq!(0, cache.fresh_count("cat", Duration::from_secs(30)));
+        assert_eq!(0, cache.fresh_count("dog", Duration::ZERO));
+    }
+
+    #[test]
+    fn test_cache_expires_entries() {
+        let cache: FactCache = FactCache::new(Duration::ZERO, 1);
+
+        cache.insert("cat", "fact".into());
+        assert_eq!(None, cache.get("cat", &mut rand::thread_rng()));
+    }
+
+    #[test]
+    fn test_cache_serves_expired_entries_as_stale_once() {
+        let cache: FactCache =
+            FactCache::new(Duration::ZERO, 1).with_stale_ttl(Duration::from_secs(30));
+
+        cache.insert("cat", "fact".into());
+        let stale = CachedFact {
+            fact: "fact".to_string(),
+            stale: true,
+            expires_in: Duration::ZERO,
+        };
+        assert_eq!(Some(stale), cache.get("cat", &mut rand::thread_rng()));
+        // the refresh has already been asked for
+        let cached = cache.get("cat", &mut rand::thread_rng()).unwrap();
+        assert!(!cached.stale);
+    }
+
+    #[test]
+    fn test_daily_facts_keep_the_first_fact_of_the_day() {
+        let daily = DailyFacts::default();
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+        assert_eq!("first", daily.insert(date, "cat", "first".into()));
+        assert_eq!("first", daily.insert(date, "cat", "second".into()));
+        assert_eq!(Some("first".into()), daily.get(date, "cat"));
+        assert_eq!(None, daily.get(date, "dog"));
+    }
+
+    #[test]
+    fn test_daily_facts_keep_today_when_a_far_off_day_is_stored() {
+        let daily = DailyFacts::default();
+        let today = Utc::now().date_naive();
+        let yesterday = today.checked_sub_days(Days::new(1)).unwrap();
+
+        daily.insert(yesterday, "cat", "yesterday".into());
+        daily.insert(today, "cat", "today".into());
+        let far_off = NaiveDate::from_ymd_opt(2001, 1, 1).unwrap();
+        assert_eq!("long ago", daily.insert(far_off, "cat", "long ago".into()));
+
+        assert_eq!(Some("today".into()), daily.get(today, "cat"));
+        assert_eq!(Some("yesterday".into()), daily.get(yesterday, "cat"));
+        assert_eq!("today", daily.insert(today, "cat", "another".into()));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_flushes_an_animal_or_everything() {
+        let store = MemoryStore::new(Duration::from_secs(30), 2);
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        store.insert_fact("cat", "cat fact".to_string()).await;
+        store
+            .insert_fact("cat", "another cat fact".to_string())
+            .await;
+        store.insert_fact("dog", "dog fact".to_string()).await;
+        store
+            .insert_daily(date, "cat", "daily cat fact".into())
+            .await;
+        store
+            .insert_daily(date, "dog", "daily dog fact".into())
+            .await;
+
+        assert_eq!(3, store.flush(Some("cat")).await);
+        assert_eq!(None, store.get_daily(date, "cat").await);
+        assert_eq!(Some(1), store.size().await);
+
+        assert_eq!(2, store.flush(None).await);
+        assert_eq!(Some(0), store.size().await);
+        assert_eq!(None, store.get_daily(date, "dog").await);
+    }
+
+    #[test]
+    fn test_rejected_animals_evict_least_recently_used() {
+        let rejected = RejectedAnimals::new(Duration::from_secs(30), 2);
+        let gauge = IntGauge::new("unsupported_animals", "test").unwrap();
+        let counted = || GaugeGuard::new(gauge.clone());
+
+        rejected.insert("dragon", counted());
+        rejected.insert("unicorn", counted());
+        assert_eq!(2, gauge.get());
+        assert!(rejected.contains("DRAGON"));
+        rejected.insert("griffin", counted());
+        assert_eq!(2, gauge.get());
+
+        assert!(rejected.contains("dragon"));
+        assert!(!rejected.contains("unicorn"));
+        assert!(rejected.contains("griffin"));
+    }
+
+    #[test]
+    fn test_rejected_animals_stop_being_counted_once_expired() {
+        let rejected = RejectedAnimals::new(Duration::ZERO, 2);
+        let gauge = IntGauge::new("unsupported_animals", "test").unwrap();
+
+        rejected.insert("dragon", GaugeGuard::new(gauge.clone()));
+        assert!(!rejected.contains("dragon"));
+        assert_eq!(0, gauge.get());
+
+        let disabled = RejectedAnimals::new(Duration::from_secs(30), 0);
+        disabled.insert("dragon", GaugeGuard::new(gauge.clone()));
+        assert_eq!(0, gauge.get());
+    }
+}
@@ -0,0 +1,408 @@
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use rand::{prelude::SliceRandom, rngs::StdRng};
+use redis::{aio::MultiplexedConnection, AsyncCommands, Client, RedisError};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CachedFact, FactStore};
+use crate::config::RedisSettings;
+use crate::single_flight::SingleFlight;
+
+/// How long to wait after failing to connect before trying again, so an unavailable Redis
+/// doesn't slow every request down.
+const RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// How long a fact of the day is kept, covering the day either side of it.
+const DAILY_TTL_SECS: u64 = 2 * 24 * 60 * 60;
+
+/// The connection to Redis, or when connecting last failed.
+enum Connection {
+    Connected(MultiplexedConnection),
+    Failed(Instant),
+    Idle,
+}
+
+/// A fact store in Redis, shared by every replica and kept across restarts.
+///
+/// Each animal's facts are a list trimmed to `capacity` that expires `ttl` after the last fact
+/// was added, or `ttl + stale_ttl` when stale facts are served, in which case the list is stale
+/// once less than `stale_ttl` of its life is left. Any Redis error is logged and treated as a
+/// miss, so the service keeps working, more slowly, while Redis is down.
+pub struct RedisStore<T> {
+    client: Client,
+    connection: Arc<Mutex<Connection>>,
+    connecting: SingleFlight<(), Option<MultiplexedConnection>>,
+    ttl: Duration,
+    stale_ttl: Duration,
+    capacity: usize,
+    timeout: Duration,
+    key_prefix: String,
+    facts: PhantomData<fn() -> T>,
+}
+
+impl<T> RedisStore<T> {
+    /// Builds the store without connecting; the connection is made on first use.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the Redis URL is invalid.
+    pub fn new(
+        settings: &RedisSettings,
+        ttl: Duration,
+        capacity: usize,
+    ) -> Result<Self, RedisError> {
+        Ok(Self {
+            client: Client::open(settings.url.as_str())?,
+            connection: Arc::new(Mutex::new(Connection::Idle)),
+            connecting: SingleFlight::default(),
+            ttl,
+            stale_ttl: Duration::ZERO,
+            capacity,
+            timeout: Duration::from_millis(settings.timeout_ms),
+            key_prefix: settings.key_prefix.clone(),
+            facts: PhantomData,
+        })
+    }
+
+    /// Serves facts for `stale_ttl` after they expire, while they are refreshed.
+    #[must_use]
+    pub fn with_stale_ttl(mut self, stale_ttl: Duration) -> Self {
+        self.stale_ttl = stale_ttl;
+        self
+    }
+
+    fn fact_key(&self, animal: &str) -> String {
+        format!("{}fact:{animal}", self.key_prefix)
+    }
+
+    fn daily_key(&self, date: NaiveDate, animal: &str) -> String {
+        format!("{}daily:{date}:{animal}", self.key_prefix)
+    }
+
+    fn daily_key_pattern(&self, animal: &str) -> String {
+        format!("{}daily:*:{animal}", self.key_prefix)
+    }
+
+    /// Returns a connection, connecting if there is none and the last attempt wasn't too recent.
+    ///
+    /// Concurrent callers share one attempt to connect, which is made without holding the lock
+    /// on the connection.
+    async fn connection(&self) -> Option<MultiplexedConnection> {
+        match &*self
+            .connection
+            .lock()
+            .expect("Redis connection lock poisoned")
+        {
+            Connection::Connected(conn) => return Some(conn.clone()),
+            Connection::Failed(at) if at.elapsed() < RECONNECT_BACKOFF => return None,
+            Connection::Failed(_) | Connection::Idle => {}
+        }
+        let client = self.client.clone();
+        let connection = self.connection.clone();
+        let timeout = self.timeout;
+        self.connecting
+            .run((), move || async move {
+                let res = client
+                    .get_multiplexed_async_connection_with_timeouts(timeout, timeout)
+                    .await;
+                let mut connection = connection.lock().expect("Redis connection lock poisoned");
+                match res {
+                    Ok(conn) => {
+                        tracing::info!("Connected to Redis");
+                        *connection = Connection::Connected(conn.clone());
+                        Some(conn)
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            "Failed to connect to Redis, treating the cache as empty: {err}"
+                        );
+                        *connection = Connection::Failed(Instant::now());
+                        None
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Logs a failed command, dropping the connection so the next command reconnects.
+    fn failed(&self, err: &RedisError) {
+        tracing::warn!("Redis command failed, treating the cache as empty: {err}");
+        *self
+            .connection
+            .lock()
+            .expect("Redis connection lock poisoned") = Connection::Failed(Instant::now());
+    }
+}
+
+#[async_trait]
+impl<T: Serialize + DeserializeOwned + Send + Sync> FactStore<T> for RedisStore<T> {
+    async fn get_fact(&self, animal: &str, rng: &mut StdRng) -> Option<CachedFact<T>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let mut conn = self.connection().await?;
+        let key = self.fact_key(animal);
+        let res: Result<(Vec<String>, i64), _> = redis::pipe()
+            .lrange(&key, 0, -1)
+            .pttl(&key)
+            .query_async(&mut conn)
+            .await;
+        let (facts, pttl) = match res {
+            Ok(res) => res,
+            Err(err) => {
+                self.failed(&err);
+                return None;
+            }
+        };
+        let fact = facts.choose(rng)?;
+        let stale = u128::try_from(pttl).is_ok_and(|pttl| pttl < self.stale_ttl.as_millis());
+        let expires_in = u64::try_from(pttl)
+            .map(|pttl| Duration::from_millis(pttl).saturating_sub(self.stale_ttl))
+            .unwrap_or_default();
+        serde_json::from_str(fact)
+            .inspect_err(|err| tracing::warn!("Ignoring malformed cached fact: {err}"))
+            .ok()
+            .map(|fact| CachedFact {
+                fact,
+                stale,
+                expires_in,
+            })
+    }
+
+    async fn insert_fact(&self, animal: &str, fact: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        let Ok(fact) = serde_json::to_string(&fact) else {
+            return;
+        };
+        let Some(mut conn) = self.connection().await else {
+            return;
+        };
+        let key = self.fact_key(animal);
+        let res: Result<(), _> = redis::pipe()
+            .atomic()
+            .rpush(&key, fact)
+            .ignore()
+            .ltrim(
+                &key,
+                -isize::try_from(self.capacity).unwrap_or(isize::MAX),
+                -1,
+            )
+            .ignore()
+            .expire(
+                &key,
+                i64::try_from(self.ttl.saturating_add(self.stale_ttl).as_secs())
+                    .unwrap_or(i64::MAX),
+            )
+            .ignore()
+            .query_async(&mut conn)
+            .await;
+        if let Err(err) = res {
+            self.failed(&err);
+        }
+    }
+
+    // counting every animal's list would need a scan of the keyspace
+    async fn size(&self) -> Option<usize> {
+        None
+    }
+
+    async fn fresh_count(&self, animal: &str, within: Duration) -> usize {
+        let Some(mut conn) = self.connection().await else {
+            return 0;
+        };
+        let key = self.fact_key(animal);
+        let res: Result<(usize, i64), _> = redis::pipe()
+            .llen(&key)
+            .pttl(&key)
+            .query_async(&mut conn)
+            .await;
+        match res {
+            // the whole list expires at once
+            Ok((len, pttl)) => {
+                let fresh_for = u64::try_from(pttl)
+                    .map(|pttl| Duration::from_millis(pttl).saturating_sub(self.stale_ttl))
+                    .unwrap_or_default();
+                if fresh_for > within {
+                    len
+                } else {
+                    0
+                }
+            }
+            Err(err) => {
+                self.failed(&err);
+                0
+            }
+        }
+    }
+
+    async fn healthy(&self) -> bool {
+        let Some(mut conn) = self.connection().await else {
+            return false;
+        };
+        let res: Result<String, _> = redis::cmd("PING").query_async(&mut conn).await;
+        match res {
+            Ok(_) => true,
+            Err(err) => {
+                self.failed(&err);
+                false
+            }
+        }
+    }
+
+    async fn get_daily(&self, date: NaiveDate, animal: &'static str) -> Option<String> {
+        let mut conn = self.connection().await?;
+        match conn.get(self.daily_key(date, animal)).await {
+            Ok(fact) => fact,
+            Err(err) => {
+                self.failed(&err);
+                None
+            }
+        }
+    }
+
+    async fn flush(&self, animal: Option<&str>) -> usize {
+        let Some(mut conn) = self.connection().await else {
+            return 0;
+        };
+        let animal = animal.unwrap_or("*");
+        // fact lists count each of their facts, facts of the day count once
+        let patterns = [
+            (self.fact_key(animal), true),
+            (self.daily_key_pattern(animal), false),
+        ];
+        let mut keys: Vec<(String, bool)> = vec![];
+        for (pattern, is_list) in patterns {
+            match conn.scan_match::<_, String>(&pattern).await {
+                Ok(mut iter) => {
+                    while let Some(key) = iter.next_item().await {
+                        keys.push((key, is_list));
+                    }
+                }
+                Err(err) => {
+                    self.failed(&err);
+                    return 0;
+                }
+            }
+        }
+        let mut evicted = 0;
+        for (key, is_list) in keys {
+            let res: Result<(usize,), _> = if is_list {
+                redis::pipe()
+                    .atomic()
+                    .llen(&key)
+                    .del(&key)
+                    .ignore()
+                    .query_async(&mut conn)
+                    .await
+            } else {
+                redis::pipe().del(&key).query_async(&mut conn).await
+            };
+            match res {
+                Ok((len,)) => evicted += len,
+                Err(err) => {
+                    self.failed(&err);
+                    break;
+                }
+            }
+        }
+        evicted
+    }
+
+    async fn insert_daily(&self, date: NaiveDate, animal: &'static str, fact: String) -> String {
+        let Some(mut conn) = self.connection().await else {
+            return fact;
+        };
+        let key = self.daily_key(date, animal);
+        // keep whichever replica's fact was stored first
+        let res: Result<(Option<String>,), _> = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&key)
+            .arg(&fact)
+            .arg("NX")
+            .arg("EX")
+            .arg(DAILY_TTL_SECS)
+            .ignore()
+            .get(&key)
+            .query_async(&mut conn)
+            .await;
+        match res {
+            Ok((stored,)) => stored.unwrap_or(fact),
+            Err(err) => {
+                self.failed(&err);
+                fact
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::NaiveDate;
+    use rand::{rngs::StdRng, SeedableRng};
+    use uuid::Uuid;
+
+    use super::RedisStore;
+    use crate::cache::FactStore;
+    use crate::config::RedisSettings;
+
+    #[tokio::test]
+    async fn test_unreachable_redis_store_is_unhealthy() {
+        let settings = RedisSettings {
+            url: "redis://127.0.0.1:1".into(),
+            ..RedisSettings::default()
+        };
+        let store = RedisStore::<String>::new(&settings, Duration::from_mins(1), 10).unwrap();
+
+        assert!(!store.healthy().await);
+    }
+
+    #[tokio::test]
+    async fn test_redis_store_round_trip() {
+        let settings = RedisSettings {
+            key_prefix: format!("test-{}:", Uuid::new_v4()),
+            ..RedisSettings::default()
+        };
+        let store = RedisStore::new(&settings, Duration::from_mins(1), 10).unwrap();
+        if !store.healthy().await {
+            eprintln!(
+                "Skipping the Redis round trip, as Redis isn't available at {}",
+                settings.url
+            );
+            return;
+        }
+        let mut rng = StdRng::seed_from_u64(0);
+
+        assert_eq!(None, store.get_fact("cat", &mut rng).await);
+        store.insert_fact("cat", "Cats purr.".to_string()).await;
+        let cached = store.get_fact("cat", &mut rng).await.unwrap();
+        assert_eq!("Cats purr.", cached.fact);
+        assert!(!cached.stale);
+        assert_eq!(1, store.fresh_count("cat", Duration::from_secs(10)).await);
+        assert_eq!(0, store.fresh_count("cat", Duration::from_mins(1)).await);
+
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        assert_eq!(None, store.get_daily(date, "cat").await);
+        assert_eq!(
+            "first",
+            store.insert_daily(date, "cat", "first".into()).await
+        );
+        assert_eq!(
+            "first",
+            store.insert_daily(date, "cat", "second".into()).await
+        );
+        assert_eq!(Some("first".into()), store.get_daily(date, "cat").await);
+
+        assert_eq!(2, store.flush(None).await);
+        assert_eq!(None, store.get_fact("cat", &mut rng).await);
+        assert_eq!(None, store.get_daily(date, "cat").await);
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::Url;
+
+/// The state of a circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Circuit {
+    /// Calls go through, counting consecutive failures.
+    Closed { failures: u32 },
+    /// Calls fail fast until the cooldown ends.
+    Open { until: Instant },
+    /// A single trial call is in flight, deciding whether to close or reopen the circuit.
+    HalfOpen,
+}
+
+/// A call let through by a circuit breaker.
+///
+/// If the trial call of a half-open circuit is dropped without its outcome being recorded, as
+/// when its request is cancelled, the circuit lets the next call through as a new trial instead
+/// of waiting on it forever.
+#[must_use]
+pub struct Permit<'a> {
+    trial: Option<&'a CircuitBreaker>,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if let Some(breaker) = self.trial {
+            let mut circuit = breaker
+                .circuit
+                .lock()
+                .expect("Circuit breaker lock poisoned");
+            if *circuit == Circuit::HalfOpen {
+                *circuit = Circuit::Open {
+                    until: Instant::now(),
+                };
+            }
+        }
+    }
+}
+
+/// A circuit breaker opening after `threshold` consecutive failures, failing calls fast for
+/// `cooldown` and then letting a single trial call through.
+///
+/// A threshold of 0 disables the breaker.
+pub struct CircuitBreaker {
+    name: String,
+    threshold: u32,
+    cooldown: Duration,
+    circuit: Mutex<Circuit>,
+}
+
+impl CircuitBreaker {
+    #[must_use]
+    pub fn new(name: String, threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            name,
+            threshold,
+            cooldown,
+            circuit: Mutex::new(Circuit::Closed { failures: 0 }),
+        }
+    }
+
+    /// Allows a call, or returns how long until the circuit lets calls through again. The
+    /// permit should be held until the call's outcome is recorded.
+    pub fn try_acquire(&self) -> Result<Permit<'_>, Duration> {
+        let permit = Permit { trial: None };
+        if self.threshold == 0 {
+            return Ok(permit);
+        }
+        let mut circuit = self.circuit.lock().expect("Circuit breaker lock poisoned");
+        match *circuit {
+            Circuit::Closed { .. } => Ok(permit),
+            Circuit::Open { until } => {
+                let now = Instant::now();
+                if now < until {
+                    return Err(until - now);
+                }
+                tracing::info!("Circuit for {} half-open, trying a call", self.name);
+                *circuit = Circuit::HalfOpen;
+                Ok(Permit { trial: Some(self) })
+            }
+            // only the trial call may go through until it completes
+            Circuit::HalfOpen => Err(self.cooldown),
+        }
+    }
+
+    /// The name of the circuit's current state: `closed`, `open` or `half_open`.
+    #[must_use]
+    pub fn state(&self) -> &'static str {
+        match *self.circuit.lock().expect("Circuit breaker lock poisoned") {
+            Circuit::Closed { .. } => "closed",
+            Circuit::Open { .. } => "open",
+            Circuit::HalfOpen => "half_open",
+        }
+    }
+
+    /// Records a successful call, closing the circuit.
+    pub fn record_success(&self) {
+        let mut circuit = self.circuit.lock().expect("Circuit breaker lock poisoned");
+        if *circuit == Circuit::HalfOpen {
+            tracing::info!("Circuit for {} closed", self.name);
+        }
+        *circuit = Circuit::Closed { failures: 0 };
+    }
+
+    /// Records a failed call, opening the circuit once the threshold is reached or when the
+    /// trial call fails.
+    pub fn record_failure(&self) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut circuit = self.circuit.lock().expect("Circuit breaker lock poisoned");
+        let failures = match *circuit {
+            Circuit::Closed { failures } => failures + 1,
+            Circuit::HalfOpen => self.threshold,
+            Circuit::Open { .. } => return,
+        };
+        if failures >= self.threshold {
+            tracing::warn!(
+                "Circuit for {} opened after {failures} failures, cooling down for {:?}",
+                self.name,
+                self.cooldown
+            );
+            *circuit = Circuit::Open {
+                until: Instant::now() + self.cooldown,
+            };
+        } else {
+            *circuit = Circuit::Closed { failures };
+        }
+    }
+}
+
+/// The circuit breakers for each upstream, identified by its URL without the query string.
+pub struct CircuitBreakers {
+    threshold: u32,
+    cooldown: Duration,
+    breakers: Mutex<HashMap<String, Arc<CircuitBreaker>>>,
+}
+
+impl CircuitBreakers {
+    #[must_use]
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the breaker for the upstream serving `url`, creating it on first use.
+    pub fn get(&self, url: &str) -> Arc<CircuitBreaker> {
+        let upstream = upstream(url);
+        let mut breakers = self
+            .breakers
+            .lock()
+            .expect("Circuit breakers lock poisoned");
+        breakers
+            .entry(upstream.clone())
+            .or_insert_with(|| {
+                Arc::new(CircuitBreaker::new(upstream, self.threshold, self.cooldown))
+            })
+            .clone()
+    }
+
+    /// Returns the state of each upstream's breaker, by upstream.
+    #[must_use]
+    pub fn states(&self) -> BTreeMap<String, &'static str> {
+        let breakers = self
+            .breakers
+            .lock()
+            .expect("Circuit breakers lock poisoned");
+        breakers
+            .iter()
+            .map(|(upstream, breaker)| (upstream.clone(), breaker.state()))
+            .collect()
+    }
+}
+
+/// Identifies the upstream serving `url` by the URL without its query string.
+pub(crate) fn upstream(url: &str) -> String {
+    Url::parse(url).map_or_else(
+        |_| url.to_string(),
+        |mut url| {
+            url.set_query(None);
+            url.to_string()
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{CircuitBreaker, CircuitBreakers};
+
+    #[test]
+    fn test_breaker_opens_after_threshold_and_half_opens_after_cooldown() {
+        let breaker = CircuitBreaker::new("test".into(), 2, Duration::ZERO);
+
+        breaker.record_failure();
+        assert!(breaker.try_acquire().is_ok());
+        breaker.record_failure();
+
+        // the cooldown is over straight away, so a single trial call is let through
+        let trial = breaker.try_acquire().expect("Expected a trial call.");
+        assert!(breaker.try_acquire().is_err());
+        assert_eq!("half_open", breaker.state());
+        breaker.record_success();
+        drop(trial);
+        assert!(breaker.try_acquire().is_ok());
+        assert_eq!("closed", breaker.state());
+    }
+
+    #[test]
+    fn test_dropped_trial_call_lets_another_through() {
+        let breaker = CircuitBreaker::new("test".into(), 1, Duration::ZERO);
+        breaker.record_failure();
+
+        let trial = breaker.try_acquire().expect("Expected a trial call.");
+        assert!(breaker.try_acquire().is_err());
+        drop(trial);
+
+        let trial = breaker.try_acquire().expect("Expected another trial call.");
+        assert_eq!("half_open", breaker.state());
+        breaker.record_failure();
+        drop(trial);
+        assert_eq!("open", breaker.state());
+    }
+
+    #[test]
+    fn test_open_breaker_fails_fast() {
+        let breaker = CircuitBreaker::new("test".into(), 1, Duration::from_secs(30));
+
+        breaker.record_failure();
+        let Err(wait) = breaker.try_acquire() else {
+            panic!("Expected the circuit to be open.");
+        };
+        assert!(wait <= Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_breakers_are_shared_per_upstream() {
+        let breakers = CircuitBreakers::new(1, Duration::from_secs(30));
+
+        let with_query = breakers.get("http://localhost/facts?number=2");
+        assert!(Arc::ptr_eq(
+            &with_query,
+            &breakers.get("http://localhost/facts")
+        ));
+        assert!(!Arc::ptr_eq(
+            &with_query,
+            &breakers.get("http://localhost/other")
+        ));
+    }
+}
@@ -1,16 +1,963 @@
+use std::collections::BTreeMap;
+
 use config::ConfigError;
 use serde_aux::field_attributes::deserialize_number_from_string;
 
-#[derive(serde::Deserialize)]
+const CAT_API_URL: &str = "https://cat-fact.herokuapp.com/facts/random?animal_type=cat";
+const CAT_FALLBACK_API_URL: &str = "https://catfact.ninja/fact";
+const DOG_API_URL: &str = "http://dog-api.kinduff.com/api/facts";
+const DOG_FALLBACK_API_URL: &str = "https://dogapi.dog/api/v2/facts";
+const DOG_FALLBACK_API_ORIGIN: &str = "https://dogapi.dog";
+const BIRD_API_URL: &str = "https://some-random-api.com/animal/bird";
+const API_TIMEOUT_MS: u64 = 5000;
+const API_CONNECT_TIMEOUT_MS: u64 = 2000;
+const POOL_MAX_IDLE_PER_HOST: usize = 32;
+const POOL_IDLE_TIMEOUT_SECS: u64 = 90;
+const CLIENT_REBUILD_THRESHOLD: u32 = 10;
+const LATENCY_SMOOTHING: f64 = 0.3;
+const TRACE_SAMPLE_RATIO: f64 = 1.0;
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+const MAX_RETRIES: u32 = 2;
+const RETRY_BASE_DELAY_MS: u64 = 100;
+const MAX_RETRY_AFTER_MS: u64 = 10_000;
+const CACHE_TTL_SECS: u64 = 60;
+const CACHE_CAPACITY: usize = 10;
+const REJECTED_TTL_SECS: u64 = 300;
+const REJECTED_CAPACITY: usize = 256;
+const REDIS_URL: &str = "redis://127.0.0.1:6379";
+const REDIS_TIMEOUT_MS: u64 = 250;
+const REDIS_KEY_PREFIX: &str = "animal-facts:";
+const SHUTDOWN_GRACE_SECS: u64 = 30;
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const READINESS_TIMEOUT_MS: u64 = 2000;
+const HEALTH_CACHE_SECS: u64 = 5;
+const RATE_LIMIT_PER_SECOND: u32 = 50;
+const RATE_LIMIT_BURST: u32 = 100;
+const CLIENT_RATE_LIMIT_PER_SECOND: u32 = 5;
+const CLIENT_RATE_LIMIT_BURST: u32 = 10;
+const CLIENT_RATE_LIMIT_KEY_HEADER: &str = "x-forwarded-for";
+const CLIENT_RATE_LIMIT_TRUSTED_PROXY_HOPS: usize = 1;
+const CLIENT_RATE_LIMIT_MAX_CLIENTS: usize = 10_000;
+const COMPRESSION_MIN_SIZE_BYTES: u16 = 1024;
+const LOG_MAX_BODY_LEN: usize = 1024;
+const USER_FACT_MAX_LEN: usize = 500;
+const USER_FACT_CAPACITY: usize = 1000;
+const USER_FACT_SHARE: f64 = 0.1;
+const WEBHOOK_ANIMAL: &str = "any";
+const WEBHOOK_TIME: &str = "09:00";
+const WEBHOOK_MAX_RETRIES: u32 = 3;
+const WEBHOOK_RETRY_DELAY_MS: u64 = 1000;
+const WARMER_INTERVAL_SECS: u64 = 60;
+const TRANSLATION_API_URL: &str = "https://libretranslate.com/translate";
+const FILTER_MAX_ATTEMPTS: u32 = 5;
+const BATCH_CONCURRENCY: usize = 4;
+const MAX_BATCH_SIZE: usize = 20;
+const FEED_ITEMS: u8 = 10;
+const FEED_BASE_URL: &str = "http://127.0.0.1:8080";
+const FACT_POINTER: &str = "/fact";
+const KEEP_ALIVE_TIMEOUT_SECS: u64 = 20;
+const IDEMPOTENCY_TTL_SECS: u64 = 24 * 60 * 60;
+const IDEMPOTENCY_CAPACITY: usize = 10_000;
+const HISTORY_CAPACITY: usize = 20;
+const MAX_CONCURRENT_REQUESTS: usize = 512;
+const MAX_CONCURRENT_UPSTREAM_CALLS: usize = 64;
+const MAX_BODY_BYTES: usize = 16 * 1024;
+const FACT_TIMEOUT_MS: u64 = 20_000;
+const ROUTE_TIMEOUT_MS: u64 = 30_000;
+const CIRCUIT_FAILURE_THRESHOLD: u32 = 5;
+const CIRCUIT_COOLDOWN_SECS: u64 = 30;
+
+#[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub application: ApplicationSettings,
+    #[serde(default)]
+    pub api: ApiSettings,
+    #[serde(default)]
+    pub retry: RetrySettings,
+    #[serde(default)]
+    pub cache: CacheSettings,
+    #[serde(default)]
+    pub readiness: ReadinessSettings,
+    #[serde(default)]
+    pub rate_limit: RateLimitSettings,
+    #[serde(default)]
+    pub auth: AuthSettings,
+    #[serde(default)]
+    pub compression: CompressionSettings,
+    #[serde(default)]
+    pub cors: CorsSettings,
+    #[serde(default)]
+    pub response_headers: ResponseHeaderSettings,
+    #[serde(default)]
+    pub translation: TranslationSettings,
+    #[serde(default)]
+    pub filter: FilterSettings,
+    #[serde(default)]
+    pub telemetry: TelemetrySettings,
+    #[serde(default)]
+    pub tls: TlsSettings,
+    #[serde(default)]
+    pub server: ServerSettings,
+    #[serde(default)]
+    pub batch: BatchSettings,
+    #[serde(default)]
+    pub limits: LimitSettings,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerSettings,
+    #[serde(default)]
+    pub errors: ErrorSettings,
+    #[serde(default)]
+    pub facts: FactSettings,
+    #[serde(default)]
+    pub logging: LoggingSettings,
+    #[serde(default)]
+    pub user_facts: UserFactSettings,
+    #[serde(default)]
+    pub webhook: WebhookSettings,
+    #[serde(default)]
+    pub warmer: WarmerSettings,
+    #[serde(default)]
+    pub feed: FeedSettings,
+    #[serde(default)]
+    pub idempotency: IdempotencySettings,
+    #[serde(default)]
+    pub history: HistorySettings,
+    #[serde(default)]
+    pub features: FeatureSettings,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
+    #[serde(
+        default = "default_shutdown_grace_secs",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub shutdown_grace_secs: u64,
+    /// The header carrying the request id, reused from the request when present and valid: up to
+    /// 128 ASCII letters, digits or `-_.:+/=`.
+    #[serde(default = "default_request_id_header")]
+    pub request_id_header: String,
+    /// Rejects requests other than the probes without a UUID in the request id header with a
+    /// 400, instead of generating an id for them.
+    #[serde(default)]
+    pub strict_request_id: bool,
+    /// Adds the request id to enveloped fact and error response bodies as `request_id`, on top of
+    /// the header.
+    #[serde(default)]
+    pub request_id_in_body: bool,
+    /// Sets `SO_REUSEADDR`, so the port can be bound again straight after a restart.
+    #[serde(default = "default_reuse_address")]
+    pub reuse_address: bool,
+    /// Sets `SO_REUSEPORT`, so several instances can share the port with the kernel balancing
+    /// connections between them. Only supported on Unix.
+    #[serde(default)]
+    pub reuse_port: bool,
+}
+
+fn default_shutdown_grace_secs() -> u64 {
+    SHUTDOWN_GRACE_SECS
+}
+
+fn default_request_id_header() -> String {
+    REQUEST_ID_HEADER.into()
+}
+
+fn default_reuse_address() -> bool {
+    true
+}
+
+/// The upstream animal fact API URLs and HTTP client settings.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct ApiSettings {
+    pub cat_url: String,
+    /// Tried in order when the cat API at `cat_url` fails.
+    pub cat_fallback_urls: Vec<String>,
+    pub dog_url: String,
+    /// Tried in order when the dog API at `dog_url` fails.
+    pub dog_fallback_urls: Vec<String>,
+    pub bird_url: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_ms: u64,
+    /// How long connecting to an upstream host may take, so a dead host fails fast within the
+    /// overall `timeout_ms`.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub connect_timeout_ms: u64,
+    /// How many idle connections to keep open to each upstream host.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub pool_max_idle_per_host: usize,
+    /// How long an idle upstream connection is kept open for.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub pool_idle_timeout_secs: u64,
+    /// How many upstream calls in a row to the same host may fail to connect before the client is
+    /// rebuilt, with a fresh connection pool and DNS lookups. 0 never rebuilds it.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub client_rebuild_threshold: u32,
+    /// The `User-Agent` upstream requests identify themselves with.
+    pub user_agent: String,
+    /// The proxy upstream requests go through, if any.
+    pub proxy: ProxySettings,
+    /// The query parameter asking an upstream for several facts at once, keyed by origin.
+    /// Upstreams not listed take `number`.
+    pub count_params: BTreeMap<String, String>,
+    /// Headers sent with every request to an upstream, keyed by origin, e.g. the API key of a
+    /// paid provider. Redirects to another origin aren't followed when any are configured.
+    pub headers: BTreeMap<String, BTreeMap<String, HeaderValueSource>>,
+    /// Animals supported on top of the compiled ones, keyed by name. The compiled animals and
+    /// their aliases take precedence over a configured animal of the same name.
+    pub animals: BTreeMap<String, AnimalApiSettings>,
+    /// The order an animal's upstream APIs are tried in.
+    pub provider_order: ProviderOrder,
+    /// The weight, from 0 to 1, each new latency sample gets in an upstream's moving average.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub latency_smoothing: f64,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        Self {
+            cat_url: CAT_API_URL.into(),
+            cat_fallback_urls: vec![CAT_FALLBACK_API_URL.into()],
+            dog_url: DOG_API_URL.into(),
+            dog_fallback_urls: vec![DOG_FALLBACK_API_URL.into()],
+            bird_url: BIRD_API_URL.into(),
+            timeout_ms: API_TIMEOUT_MS,
+            connect_timeout_ms: API_CONNECT_TIMEOUT_MS,
+            pool_max_idle_per_host: POOL_MAX_IDLE_PER_HOST,
+            pool_idle_timeout_secs: POOL_IDLE_TIMEOUT_SECS,
+            client_rebuild_threshold: CLIENT_REBUILD_THRESHOLD,
+            user_agent: USER_AGENT.into(),
+            proxy: ProxySettings::default(),
+            count_params: BTreeMap::from([(DOG_FALLBACK_API_ORIGIN.into(), "limit".into())]),
+            headers: BTreeMap::new(),
+            animals: BTreeMap::new(),
+            provider_order: ProviderOrder::default(),
+            latency_smoothing: LATENCY_SMOOTHING,
+        }
+    }
+}
+
+/// The order an animal's upstream APIs are tried in: as configured, or fastest first by their
+/// average latency, with those whose circuit is open last.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderOrder {
+    #[default]
+    Configured,
+    Latency,
+}
+
+/// The HTTP or HTTPS proxy upstream requests go through, when `url` is set, authenticating with
+/// `username` and `password` when a username is given. Hosts matching `no_proxy`, as in the
+/// `NO_PROXY` environment variable, e.g. `localhost` or `.internal`, are reached directly.
+#[derive(serde::Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ProxySettings {
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    pub no_proxy: Vec<String>,
+}
+
+/// Where the value of a header sent upstream comes from: given as is, or read from an environment
+/// variable or a file when it is a secret.
+#[derive(serde::Deserialize, Clone)]
+#[serde(untagged)]
+pub enum HeaderValueSource {
+    Value(String),
+    Env { env: String },
+    File { file: String },
+}
+
+/// The upstream API of an animal added through the config, and the JSON pointer, e.g. `/fact` or
+/// `/data/0/text`, locating the fact in its responses.
+#[derive(serde::Deserialize, Clone)]
+pub struct AnimalApiSettings {
+    pub url: String,
+    #[serde(default = "default_fact_pointer")]
+    pub fact_pointer: String,
+}
+
+fn default_fact_pointer() -> String {
+    FACT_POINTER.into()
+}
+
+/// The retry policy for upstream animal API calls.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct RetrySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_retries: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub base_delay_ms: u64,
+    /// The longest an upstream's `Retry-After` is honoured for on a 429.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_retry_after_ms: u64,
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self {
+            max_retries: MAX_RETRIES,
+            base_delay_ms: RETRY_BASE_DELAY_MS,
+            max_retry_after_ms: MAX_RETRY_AFTER_MS,
+        }
+    }
+}
+
+/// The fact cache settings. A capacity of 0 disables the cache.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct CacheSettings {
+    /// Where fetched facts and facts of the day are kept.
+    pub backend: CacheBackend,
+    pub redis: RedisSettings,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_secs: u64,
+    /// How long past `ttl_secs` a fact is still served while it is refreshed in the background.
+    /// Facts are refreshed before being served when 0.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub stale_ttl_secs: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub capacity: usize,
+    /// How long an unsupported animal is remembered for.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub rejected_ttl_secs: u64,
+    /// How many unsupported animals are remembered.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub rejected_capacity: usize,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            backend: CacheBackend::default(),
+            redis: RedisSettings::default(),
+            ttl_secs: CACHE_TTL_SECS,
+            stale_ttl_secs: 0,
+            capacity: CACHE_CAPACITY,
+            rejected_ttl_secs: REJECTED_TTL_SECS,
+            rejected_capacity: REJECTED_CAPACITY,
+        }
+    }
+}
+
+/// Where cached facts are kept. Redis is only available when built with the `redis` feature.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackend {
+    #[default]
+    Memory,
+    Redis,
+}
+
+/// The Redis connection used by the `redis` cache backend.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct RedisSettings {
+    pub url: String,
+    /// How long to wait for Redis to connect or answer before treating it as unavailable.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_ms: u64,
+    /// Prepended to every key, so several services can share one Redis.
+    pub key_prefix: String,
+}
+
+impl Default for RedisSettings {
+    fn default() -> Self {
+        Self {
+            url: REDIS_URL.into(),
+            timeout_ms: REDIS_TIMEOUT_MS,
+            key_prefix: REDIS_KEY_PREFIX.into(),
+        }
+    }
+}
+
+/// The readiness probe settings: which upstream animal APIs must be reachable, and how long to
+/// wait for each.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct ReadinessSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_ms: u64,
+    pub dependencies: Vec<String>,
+    /// How long a `/healthz` report is reused before the upstreams are probed again.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub health_cache_secs: u64,
+}
+
+impl Default for ReadinessSettings {
+    fn default() -> Self {
+        Self {
+            timeout_ms: READINESS_TIMEOUT_MS,
+            dependencies: vec!["cat".into(), "dog".into(), "bird".into()],
+            health_cache_secs: HEALTH_CACHE_SECS,
+        }
+    }
+}
+
+/// The `/fact` rate limit, shared by all clients, and the limit each client gets on top of it.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub per_second: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub burst: u32,
+    pub per_client: ClientRateLimitSettings,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            per_second: RATE_LIMIT_PER_SECOND,
+            burst: RATE_LIMIT_BURST,
+            per_client: ClientRateLimitSettings::default(),
+        }
+    }
+}
+
+/// The rate limit of each client, identified by the address in `key_header` appended by the
+/// outermost of `trusted_proxy_hops` proxies, or by its peer address when the header is missing.
+/// Up to `max_clients` clients are tracked at once.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct ClientRateLimitSettings {
+    pub enabled: bool,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub per_second: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub burst: u32,
+    pub key_header: String,
+    /// How many proxies in front of the service append to `key_header`. 0 ignores the header.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub trusted_proxy_hops: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_clients: usize,
+}
+
+impl Default for ClientRateLimitSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            per_second: CLIENT_RATE_LIMIT_PER_SECOND,
+            burst: CLIENT_RATE_LIMIT_BURST,
+            key_header: CLIENT_RATE_LIMIT_KEY_HEADER.into(),
+            trusted_proxy_hops: CLIENT_RATE_LIMIT_TRUSTED_PROXY_HOPS,
+            max_clients: CLIENT_RATE_LIMIT_MAX_CLIENTS,
+        }
+    }
+}
+
+/// The API keys accepted on protected routes, and on admin routes. Auth is disabled when
+/// `api_keys` is empty, but admin routes are disabled when `admin_api_keys` is.
+#[derive(serde::Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AuthSettings {
+    pub api_keys: Vec<String>,
+    pub admin_api_keys: Vec<String>,
+}
+
+/// The trace export settings. Traces are only exported when `otlp_endpoint` is set.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct TelemetrySettings {
+    pub otlp_endpoint: String,
+    /// The share, from 0 to 1, of traces exported.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub sample_ratio: f64,
+    /// Exports traces ending in an error whatever the ratio, at the cost of recording every span
+    /// until its trace ends. Turn off to tail-sample at the collector instead.
+    pub error_traces: bool,
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: String::new(),
+            sample_ratio: TRACE_SAMPLE_RATIO,
+            error_traces: true,
+        }
+    }
+}
+
+/// The PEM certificate chain and private key to serve HTTPS with. Plain HTTP is served when
+/// neither is set.
+#[derive(serde::Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct TlsSettings {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+/// How connections are served. HTTP/2 is accepted, negotiated over HTTPS or with prior knowledge
+/// over plain HTTP, unless `http2` is off. HTTP/1 connections are kept open between requests
+/// unless `keep_alive` is off, and HTTP/2 connections are pinged every `keep_alive_interval_secs`,
+/// when above 0, and closed when a ping goes unanswered for `keep_alive_timeout_secs`.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct ServerSettings {
+    pub http2: bool,
+    pub keep_alive: bool,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub keep_alive_interval_secs: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub keep_alive_timeout_secs: u64,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            http2: true,
+            keep_alive: true,
+            keep_alive_interval_secs: 0,
+            keep_alive_timeout_secs: KEEP_ALIVE_TIMEOUT_SECS,
+        }
+    }
+}
+
+/// The response compression settings. Responses smaller than `min_size_bytes` are sent as is.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct CompressionSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_size_bytes: u16,
+}
+
+impl Default for CompressionSettings {
+    fn default() -> Self {
+        Self {
+            min_size_bytes: COMPRESSION_MIN_SIZE_BYTES,
+        }
+    }
+}
+
+/// Whether request queries and response bodies are logged at debug level, how many characters
+/// of each body are kept, and the format of the access log, if any, along with the file it is
+/// appended to. The access log is written to stderr when no file is set.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct LoggingSettings {
+    pub capture_bodies: bool,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_body_len: usize,
+    pub access_log: AccessLogFormat,
+    pub access_log_path: String,
+}
+
+/// The format of the access log line written for each request.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    #[default]
+    Off,
+    /// The Apache combined log format, followed by the latency and request id.
+    Combined,
+    Json,
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        Self {
+            capture_bodies: false,
+            max_body_len: LOG_MAX_BODY_LEN,
+            access_log: AccessLogFormat::default(),
+            access_log_path: String::new(),
+        }
+    }
+}
+
+/// The CORS policy. Any origin is allowed when `allowed_origins` is empty.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct CorsSettings {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+}
+
+impl Default for CorsSettings {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![],
+            allowed_methods: vec!["GET".into()],
+            allowed_headers: vec![],
+        }
+    }
+}
+
+/// Static headers set on every response, replacing any the response already has, and whether the
+/// security headers are added to responses that don't set them.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct ResponseHeaderSettings {
+    pub security_headers: bool,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Default for ResponseHeaderSettings {
+    fn default() -> Self {
+        Self {
+            security_headers: true,
+            headers: BTreeMap::new(),
+        }
+    }
+}
+
+/// The LibreTranslate-compatible API used to translate facts.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct TranslationSettings {
+    pub url: String,
+}
+
+impl Default for TranslationSettings {
+    fn default() -> Self {
+        Self {
+            url: TRANSLATION_API_URL.into(),
+        }
+    }
+}
+
+/// How many facts to try when looking for one matching the request's filters, and the words a
+/// fact must never contain. No words are banned when `banned_words` is empty.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct FilterSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts: u32,
+    pub banned_words: Vec<String>,
+}
+
+impl Default for FilterSettings {
+    fn default() -> Self {
+        Self {
+            max_attempts: FILTER_MAX_ATTEMPTS,
+            banned_words: vec![],
+        }
+    }
+}
+
+/// How many facts of a batch request are fetched at once, and the most animals a batch request,
+/// or a comma-separated `/fact` request, may ask for.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct BatchSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub concurrency: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_batch_size: usize,
+}
+
+impl Default for BatchSettings {
+    fn default() -> Self {
+        Self {
+            concurrency: BATCH_CONCURRENCY,
+            max_batch_size: MAX_BATCH_SIZE,
+        }
+    }
+}
+
+/// Guardrails on the requests served at once, on request body sizes and on how long an API
+/// request may take. Requests beyond `max_concurrent_requests` are shed with a 503, and requests
+/// running past their timeout fail with a 504. A timeout of 0 disables it.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct LimitSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_concurrent_requests: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_body_bytes: usize,
+    /// The time allowed for a `/fact` request, including retries and translation.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub fact_timeout_ms: u64,
+    /// The time allowed for requests to the other API routes, except the fact stream.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub route_timeout_ms: u64,
+    /// The most calls to the upstream animal APIs in flight at once, across all requests.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_concurrent_upstream_calls: usize,
+}
+
+impl Default for LimitSettings {
+    fn default() -> Self {
+        Self {
+            max_concurrent_requests: MAX_CONCURRENT_REQUESTS,
+            max_body_bytes: MAX_BODY_BYTES,
+            fact_timeout_ms: FACT_TIMEOUT_MS,
+            route_timeout_ms: ROUTE_TIMEOUT_MS,
+            max_concurrent_upstream_calls: MAX_CONCURRENT_UPSTREAM_CALLS,
+        }
+    }
+}
+
+/// When to stop calling a failing upstream: after `failure_threshold` consecutive failures calls
+/// fail fast for `cooldown_secs`. A threshold of 0 disables the circuit breakers.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct CircuitBreakerSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub failure_threshold: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cooldown_secs: u64,
+}
+
+impl Default for CircuitBreakerSettings {
+    fn default() -> Self {
+        Self {
+            failure_threshold: CIRCUIT_FAILURE_THRESHOLD,
+            cooldown_secs: CIRCUIT_COOLDOWN_SECS,
+        }
+    }
+}
+
+/// The animal `/fact` serves when no `animal` param is given, which is required when
+/// `default_animal` is empty, whether facts are wrapped in an object with their animal unless
+/// a request's `envelope` param says otherwise (facts from users and fallbacks always are), and
+/// which fact is served from an upstream response with several unless a request's `selection`
+/// param says otherwise. The `animal` param is trimmed, has its whitespace collapsed and is
+/// lowercased unless `normalize_animal` is off. A random animal is chosen in proportion to its
+/// weight in `animal_weights`, 1 when unlisted; the app won't start with weights for unsupported
+/// animals, negative ones, or none positive. With `fallback` on, a single fact request that
+/// neither the cache nor any upstream can serve gets one of the bundled facts instead of an
+/// error.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct FactSettings {
+    pub default_animal: String,
+    pub envelope: bool,
+    pub selection: Selection,
+    pub normalize_animal: bool,
+    pub animal_weights: BTreeMap<String, f64>,
+    pub fallback: bool,
+}
+
+impl Default for FactSettings {
+    fn default() -> Self {
+        Self {
+            default_animal: String::new(),
+            envelope: true,
+            selection: Selection::default(),
+            normalize_animal: true,
+            animal_weights: BTreeMap::new(),
+            fallback: false,
+        }
+    }
+}
+
+/// Which fact is served from an upstream response with several.
+#[derive(
+    serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Selection {
+    #[default]
+    First,
+    Random,
+    Longest,
+}
+
+/// The facts contributed by users: how long each may be, how many are kept per animal, and the
+/// share of single fact requests served one when there is one. None are served when `share` is 0.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct UserFactSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_len: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub capacity: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub share: f64,
+}
+
+impl Default for UserFactSettings {
+    fn default() -> Self {
+        Self {
+            max_len: USER_FACT_MAX_LEN,
+            capacity: USER_FACT_CAPACITY,
+            share: USER_FACT_SHARE,
+        }
+    }
+}
+
+/// How long the response to a request with an `Idempotency-Key` is replayed for retries of it, and
+/// how many are kept. Keys are ignored when `capacity` is 0.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct IdempotencySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_secs: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub capacity: usize,
+}
+
+impl Default for IdempotencySettings {
+    fn default() -> Self {
+        Self {
+            ttl_secs: IDEMPOTENCY_TTL_SECS,
+            capacity: IDEMPOTENCY_CAPACITY,
+        }
+    }
+}
+
+/// How many of the facts most recently served are listed by `/fact/history`. None are kept when
+/// `capacity` is 0.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct HistorySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub capacity: usize,
+}
+
+impl Default for HistorySettings {
+    fn default() -> Self {
+        Self {
+            capacity: HISTORY_CAPACITY,
+        }
+    }
+}
+
+/// The optional endpoints, each served only when enabled: a disabled one answers 404 as if it
+/// didn't exist. `submissions` is `POST /fact`, where users contribute facts.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct FeatureSettings {
+    pub submissions: bool,
+    pub stream: bool,
+    pub feed: bool,
+    pub batch: bool,
+    pub history: bool,
+    pub graphql: bool,
+}
+
+impl FeatureSettings {
+    /// The names of the enabled features.
+    #[must_use]
+    pub fn enabled(&self) -> Vec<&'static str> {
+        [
+            ("submissions", self.submissions),
+            ("stream", self.stream),
+            ("feed", self.feed),
+            ("batch", self.batch),
+            ("history", self.history),
+            ("graphql", self.graphql),
+        ]
+        .into_iter()
+        .filter_map(|(name, enabled)| enabled.then_some(name))
+        .collect()
+    }
+}
+
+impl Default for FeatureSettings {
+    fn default() -> Self {
+        Self {
+            submissions: true,
+            stream: true,
+            feed: true,
+            batch: true,
+            history: true,
+            graphql: true,
+        }
+    }
+}
+
+/// The webhook the fact of the day for `animal` is posted to each day at `time`, as `HH:MM` UTC,
+/// retrying a failed post up to `max_retries` times. The webhook is disabled when `url` is empty.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct WebhookSettings {
+    pub url: String,
+    pub animal: String,
+    pub time: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_retries: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub retry_delay_ms: u64,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            animal: WEBHOOK_ANIMAL.into(),
+            time: WEBHOOK_TIME.into(),
+            max_retries: WEBHOOK_MAX_RETRIES,
+            retry_delay_ms: WEBHOOK_RETRY_DELAY_MS,
+        }
+    }
+}
+
+/// Whether the fact cache is filled for every animal on startup and again every `interval_secs`,
+/// so requests are served from the cache straight away.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct WarmerSettings {
+    pub enabled: bool,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub interval_secs: u64,
+}
+
+impl Default for WarmerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: WARMER_INTERVAL_SECS,
+        }
+    }
+}
+
+/// How many facts the RSS feed lists by default, from 1 to 10, and the URL clients reach the app
+/// at, which the feed links to itself with.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct FeedSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub items: u8,
+    pub base_url: String,
+}
+
+impl Default for FeedSettings {
+    fn default() -> Self {
+        Self {
+            items: FEED_ITEMS,
+            base_url: FEED_BASE_URL.into(),
+        }
+    }
+}
+
+/// How error responses are formatted.
+#[derive(serde::Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ErrorSettings {
+    pub format: ErrorFormat,
+    pub detail: ErrorDetail,
+}
+
+/// The error response body: `{"error": {"code", "message"}}`, or an RFC 7807 problem document,
+/// which clients can also ask for with `Accept: application/problem+json`.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorFormat {
+    #[default]
+    Simple,
+    Problem,
+}
+
+/// Whether error messages carrying upstream details are sent to clients as is, or replaced with a
+/// generic message. The full message is logged either way.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorDetail {
+    #[default]
+    Full,
+    Generic,
 }
 
 #[allow(clippy::module_name_repetitions)]
@@ -33,7 +980,16 @@ pub fn get_config() -> Result<Settings, ConfigError> {
         .add_source(
             config::Environment::with_prefix("APP")
                 .prefix_separator("_")
-                .separator("__"),
+                .separator("__")
+                .list_separator(",")
+                .with_list_parse_key("auth.api_keys")
+                .with_list_parse_key("auth.admin_api_keys")
+                .with_list_parse_key("cors.allowed_origins")
+                .with_list_parse_key("cors.allowed_methods")
+                .with_list_parse_key("cors.allowed_headers")
+                .with_list_parse_key("filter.banned_words")
+                .with_list_parse_key("api.cat_fallback_urls")
+                .with_list_parse_key("api.dog_fallback_urls"),
         )
         .build()?;
 
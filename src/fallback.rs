@@ -0,0 +1,48 @@
+/// Cat facts served when every upstream fails and nothing is cached.
+const CAT_FACTS: [&str; 3] = [
+    "Cats sleep for around 13 to 16 hours a day.",
+    "A group of cats is called a clowder.",
+    "Cats have five toes on their front paws but only four on their back paws.",
+];
+
+/// Dog facts served when every upstream fails and nothing is cached.
+const DOG_FACTS: [&str; 3] = [
+    "A dog's nose print is as unique as a human fingerprint.",
+    "Dogs sweat through the pads of their paws.",
+    "Puppies are born deaf and blind.",
+];
+
+/// Bird facts served when every upstream fails and nothing is cached.
+const BIRD_FACTS: [&str; 3] = [
+    "Birds are the only living animals with feathers.",
+    "Hummingbirds are the only birds that can fly backwards.",
+    "An ostrich's eye is bigger than its brain.",
+];
+
+/// Returns the bundled facts about the animal, which are only served as a last resort. Animals
+/// configured under `api.animals` have none.
+#[must_use]
+pub fn fallback_facts(animal: &str) -> &'static [&'static str] {
+    match animal {
+        "cat" => &CAT_FACTS,
+        "dog" => &DOG_FACTS,
+        "bird" => &BIRD_FACTS,
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use enum_iterator::all;
+
+    use super::fallback_facts;
+    use crate::handlers::Animal;
+
+    #[test]
+    fn test_fallback_facts() {
+        for animal in all::<Animal>() {
+            assert!(!fallback_facts(animal.as_str()).is_empty());
+        }
+        assert!(fallback_facts("fish").is_empty());
+    }
+}
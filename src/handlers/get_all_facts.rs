@@ -0,0 +1,75 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use futures::{stream, StreamExt};
+use serde_json::{json, Map, Value};
+
+use super::{batch_item, respond_error, Animal, ErrorKind, Format};
+use crate::state::AppState;
+
+/// Returns a fact about every supported animal, keyed by animal, with an error object in place of
+/// the fact for any animal whose fetch failed.
+#[utoipa::path(
+    get,
+    context_path = "/v1",
+    path = "/facts/all",
+    tag = "facts",
+    responses(
+        (status = 200, description = "A fact, or an error, for each supported animal", body = Object,
+            content_type = ["application/json", "text/plain"],
+            example = json!({ "cat": "Cats sleep for 16 hours a day.", "dog": { "error": { "code": "upstream_timeout", "message": "Request to animal API timed out" } } })),
+        (status = 406, description = "Neither JSON nor plain text is acceptable", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(name = "Fetching a fact about every animal", skip(state, headers))]
+pub async fn get_all_facts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    let Some(format) = Format::negotiate(&headers) else {
+        return respond_error(&ErrorKind::NotAcceptable, state.config.errors.detail)
+            .into_response();
+    };
+
+    let concurrency = state.config.batch.concurrency.max(1);
+    let items: Vec<Value> = stream::iter(Animal::supported(&state))
+        .map(|a| batch_item(&state, a.as_str().into()))
+        .buffered(concurrency)
+        .collect()
+        .await;
+    let facts: Map<String, Value> = items
+        .into_iter()
+        .map(|mut item| {
+            let animal = item["animal"].as_str().unwrap_or_default().to_string();
+            match item["fact"].take() {
+                // a failed item keeps only its error
+                Value::Null => (animal, json!({ "error": item["error"].take() })),
+                fact => (animal, fact),
+            }
+        })
+        .collect();
+
+    tracing::info!("Success response payload: {facts:?}");
+    format.render((StatusCode::OK, Json(Value::Object(facts))), all_facts_text)
+}
+
+/// The plain text body for an all facts response: one line per animal, with its fact or error
+/// message.
+fn all_facts_text(value: &Value) -> String {
+    value
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(animal, fact)| {
+            let text = fact
+                .as_str()
+                .or_else(|| fact["error"]["message"].as_str())
+                .unwrap_or_default();
+            format!("{animal}: {text}")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -1,28 +1,198 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
 
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
+    extract::{rejection::QueryRejection, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    response::IntoResponse,
     Json,
 };
 use enum_iterator::{all, Sequence};
-use rand::prelude::SliceRandom;
-use reqwest::Client;
-use serde::de;
+use futures::future::join_all;
+use rand::{
+    distributions::{Distribution, WeightedError, WeightedIndex},
+    prelude::{IteratorRandom, SliceRandom},
+    rngs::StdRng,
+    Rng, SeedableRng,
+};
+use reqwest::{Client, Url};
+use serde::de::{self, DeserializeOwned};
 use serde_json::{json, Value};
-use validator::{Validate, ValidationErrors};
+use tokio::sync::Semaphore;
+use tracing::Instrument;
+use utoipa::IntoParams;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use super::{batch_item, batch_text, Format, ANY_ANIMAL};
+use crate::cache::CachedFact;
+use crate::circuit_breaker::CircuitBreakers;
+use crate::config::{ApiSettings, ErrorDetail, ProviderOrder, RetrySettings, Selection};
+use crate::fallback::fallback_facts;
+use crate::latency::UpstreamLatencies;
+use crate::request_id::with_request_id;
+use crate::state::AppState;
+use crate::translation::{translate, SOURCE_LANG};
+use crate::upstream_client::UpstreamClient;
+use crate::upstream_headers::UpstreamHeaders;
+use crate::util::truncate;
 
-const CAT_API_URL: &str = "https://cat-fact.herokuapp.com/facts/random?animal_type=cat";
-const DOG_API_URL: &str = "http://dog-api.kinduff.com/api/facts";
+/// The most characters of an unexpected upstream response body that are logged.
+const MAX_LOGGED_BODY_LEN: usize = 256;
+
+/// The most languages a fact may be translated into at once with `langs`.
+const MAX_LANGS: usize = 5;
 
 /// Type alias for a JSON response.
 pub type Response = Json<Value>;
 
-/// The animal query parameter.
-#[derive(serde::Deserialize, serde::Serialize, Validate)]
+/// The fact query parameters.
+#[derive(serde::Deserialize, serde::Serialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[validate(schema(function = "validate_combination", skip_on_field_errors = false))]
 pub struct Param {
-    #[validate(required, length(max = 24))]
+    /// The animal to get a fact about, or `any` for a random one. Several animals may be given
+    /// separated by commas to get one fact about each; `count`, `lang`, `min_len`, `max_len` and
+    /// `contains` only apply to a single animal. May be left out when a default animal is
+    /// configured.
+    #[validate(
+        required(message = "is required"),
+        length(max = 24, message = "must be at most 24 characters")
+    )]
+    #[param(example = "dog")]
     animal: Option<String>,
+    /// The number of facts to return, from 1 to 10.
+    #[validate(range(min = 1, max = 10, message = "must be between 1 and 10"))]
+    #[param(minimum = 1, maximum = 10)]
+    count: Option<u8>,
+    /// A 2-letter language code to translate the fact into.
+    #[validate(custom(function = "validate_lang"))]
+    #[param(example = "es")]
+    lang: Option<String>,
+    /// Up to 5 comma-separated 2-letter language codes to also translate a single fact into, keyed
+    /// by language in `translations`. A language whose translation fails gets `null`. Not allowed
+    /// with `count` above 1, several animals or `all_sources`, and the fact keeps its envelope.
+    #[validate(custom(function = "validate_langs"))]
+    #[param(example = "es,fr,de")]
+    langs: Option<String>,
+    /// The maximum length of the fact, in characters.
+    #[validate(range(min = 1, message = "must be at least 1"))]
+    #[param(minimum = 1)]
+    max_len: Option<usize>,
+    /// The minimum length of the fact, in characters. Must not exceed `max_len`.
+    #[param(minimum = 0)]
+    min_len: Option<usize>,
+    /// A keyword the fact must contain, ignoring case.
+    #[validate(length(min = 1, max = 64, message = "must be between 1 and 64 characters"))]
+    #[param(example = "tail")]
+    contains: Option<String>,
+    /// Whether to include the upstream API the fact came from.
+    #[param(example = true)]
+    include_source: Option<bool>,
+    /// Whether to get a fact from every upstream API configured for the animal instead of one
+    /// fact, keyed by provider: `primary`, then `fallback_1` and so on. A failing API gets an
+    /// error in place of its fact.
+    #[param(example = true)]
+    all_sources: Option<bool>,
+    /// Seeds the random choices made for the request, so the same seed gives the same result.
+    #[param(example = 42)]
+    seed: Option<u64>,
+    /// Whether to wrap the fact in an object with its animal, or return the bare fact, or list of
+    /// facts. Defaults to the configured `facts.envelope`. Facts contributed by users and
+    /// fallback facts are always wrapped, so they stay marked as such.
+    #[param(example = false)]
+    envelope: Option<bool>,
+    /// Which fact to serve when the upstream returns several: `first`, `random` or `longest`.
+    /// Defaults to the configured `facts.selection`.
+    #[param(value_type = Option<String>, example = "longest")]
+    selection: Option<Selection>,
+}
+
+/// Checks that a language is a 2-letter ASCII code.
+fn validate_lang(lang: &str) -> Result<(), ValidationError> {
+    if lang.len() == 2 && lang.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("lang").with_message("must be a 2-letter language code".into()))
+    }
+}
+
+/// Checks that the languages are at most `MAX_LANGS` comma-separated 2-letter codes.
+fn validate_langs(langs: &str) -> Result<(), ValidationError> {
+    let langs = split_langs(langs);
+    if langs.is_empty() || langs.iter().any(|lang| validate_lang(lang).is_err()) {
+        return Err(ValidationError::new("langs")
+            .with_message("must be comma-separated 2-letter language codes".into()));
+    }
+    if langs.len() > MAX_LANGS {
+        return Err(ValidationError::new("langs")
+            .with_message(format!("must list at most {MAX_LANGS} languages").into()));
+    }
+    Ok(())
+}
+
+/// Splits comma-separated languages into distinct lowercase codes, in the order given.
+fn split_langs(langs: &str) -> Vec<String> {
+    let mut distinct: Vec<String> = vec![];
+    for lang in langs
+        .split(',')
+        .map(str::trim)
+        .filter(|lang| !lang.is_empty())
+    {
+        let lang = lang.to_lowercase();
+        if !distinct.contains(&lang) {
+            distinct.push(lang);
+        }
+    }
+    distinct
+}
+
+/// Checks that the params given make sense together.
+fn validate_combination(param: &Param) -> Result<(), ValidationError> {
+    validate_len_range(param)?;
+    validate_langs_use(param)
+}
+
+/// Checks that `min_len` doesn't exceed `max_len`.
+fn validate_len_range(param: &Param) -> Result<(), ValidationError> {
+    match (param.min_len, param.max_len) {
+        (Some(min_len), Some(max_len)) if min_len > max_len => {
+            Err(ValidationError::new("min_len")
+                .with_message("min_len must not exceed max_len".into()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks that `langs` is only asked for along with a single fact about a single animal, the
+/// only response it is applied to.
+fn validate_langs_use(param: &Param) -> Result<(), ValidationError> {
+    if param.langs.is_none() {
+        return Ok(());
+    }
+    let several_facts = param.count.is_some_and(|count| count > 1)
+        || param
+            .animal
+            .as_deref()
+            .is_some_and(|animal| animal.contains(','))
+        || param.all_sources == Some(true);
+    if several_facts {
+        return Err(ValidationError::new("langs").with_message(
+            "langs only applies to a single fact, not with count, several animals or all_sources"
+                .into(),
+        ));
+    }
+    Ok(())
+}
+
+impl Param {
+    /// The filter the facts returned for the request must match.
+    fn filter<'a>(&self, state: &'a AppState) -> FactFilter<'a> {
+        FactFilter::new(state, self.min_len, self.max_len)
+            .with_selection(self.selection)
+            .with_keyword(self.contains.as_deref())
+    }
 }
 
 impl Display for Param {
@@ -34,214 +204,2454 @@ impl Display for Param {
     }
 }
 
-/// Returns a 200 OK JSON response with an animal fact payload.
-fn respond_ok(fact: &str, animal: &str) -> (StatusCode, Response) {
-    let value = json!({ "fact": fact, "animal": animal });
+/// Returns a 200 OK JSON response with an animal fact payload, including the fact's language
+/// when a translation was requested and its source when given.
+pub(super) fn respond_ok(
+    fact: &str,
+    animal: &str,
+    lang: Option<&str>,
+    source: Option<&Source>,
+) -> (StatusCode, Response) {
+    let mut value = json!({ "fact": fact, "animal": animal });
+    if let Some(lang) = lang {
+        value["lang"] = json!(lang);
+    }
+    if let Some(source) = source {
+        value["source"] = json!(source);
+    }
+    tracing::info!("Success response payload: {value}");
+    (StatusCode::OK, Json(with_request_id(value)))
+}
+
+/// Returns a 200 OK JSON response with a multi-fact payload, including the facts' language when
+/// a translation was requested and their sources, in the same order, when requested.
+fn respond_ok_many(
+    facts: &[String],
+    animal: &str,
+    lang: Option<&str>,
+    sources: Option<&[Source]>,
+) -> (StatusCode, Response) {
+    let mut value = json!({ "facts": facts, "animal": animal });
+    if let Some(lang) = lang {
+        value["lang"] = json!(lang);
+    }
+    if let Some(sources) = sources {
+        value["sources"] = json!(sources);
+    }
     tracing::info!("Success response payload: {value}");
-    (StatusCode::OK, Json(value))
+    (StatusCode::OK, Json(with_request_id(value)))
+}
+
+/// Returns a JSON response with the error's HTTP status code, code and message at the given
+/// detail level, and for a validation error every failure of each param. The full message is
+/// logged either way.
+pub(super) fn respond_error(err: &ErrorKind, detail: ErrorDetail) -> (StatusCode, Response) {
+    let message = err.client_message(detail);
+    let mut value = json!({ "error": { "code": err.code(), "message": message } });
+    match err {
+        ErrorKind::Validation(errs) => value["error"]["errors"] = field_errors(errs),
+        ErrorKind::CircuitOpen(secs) => value["error"]["retry_after"] = json!(secs),
+        _ => {}
+    }
+    tracing::error!(error = %err, "Fail response payload: {value}");
+    (err.status_code(), Json(with_request_id(value)))
 }
 
-/// Returns a JSON response with an HTTP error code and an error message.
-fn respond_error(code: StatusCode, err: &ErrorKind) -> (StatusCode, Response) {
-    let value = json!({ "error": err.to_string() });
-    tracing::error!("Fail response payload: {value}");
-    (code, Json(value))
+/// Lists the messages of each param's validation failures, keyed by param. Failures of checks
+/// spanning several params are keyed by the param named in their code.
+fn field_errors(errs: &ValidationErrors) -> Value {
+    let mut fields = serde_json::Map::new();
+    for (field, errors) in errs.field_errors() {
+        for error in errors {
+            let field = if field == "__all__" {
+                error.code.to_string()
+            } else {
+                field.to_string()
+            };
+            let message = error
+                .message
+                .as_ref()
+                .map_or_else(|| error.code.to_string(), ToString::to_string);
+            let messages = fields.entry(field).or_insert_with(|| json!([]));
+            if let Some(messages) = messages.as_array_mut() {
+                messages.push(json!(message));
+            }
+        }
+    }
+    Value::Object(fields)
 }
 
+/// Returns a random fact about the requested animal.
+#[utoipa::path(
+    get,
+    context_path = "/v1",
+    path = "/fact",
+    tag = "facts",
+    params(Param),
+    responses(
+        (status = 200, description = "A fact, or several facts when `count` is above 1", body = FactResponse,
+            content_type = ["application/json", "text/plain"]),
+        (status = 400, description = "Invalid or unsupported animal", body = ErrorResponse),
+        (status = 404, description = "No fact matched the requested filters, or the upstream API found none", body = ErrorResponse),
+        (status = 406, description = "Neither JSON nor plain text is acceptable", body = ErrorResponse),
+        (status = 429, description = "The upstream animal API is rate limiting requests", body = ErrorResponse),
+        (status = 500, description = "The upstream animal API failed", body = ErrorResponse),
+        (status = 502, description = "The upstream animal API couldn't be connected to, or returned a server error or a response that isn't a fact", body = ErrorResponse),
+        (status = 503, description = "The upstream animal API is unavailable", body = ErrorResponse),
+        (status = 504, description = "The upstream animal API timed out", body = ErrorResponse)
+    )
+)]
 #[tracing::instrument(
     name = "Fetching an animal fact",
-    skip(client, param)
+    skip(state, headers, uri, param)
     fields(
-        param = % param.0
+        param = param.as_ref().map_or_else(|_| String::new(), |param| param.0.to_string())
     )
 )]
 pub async fn get_animal_fact(
-    State(client): State<Client>,
-    param: Query<Param>,
-) -> (StatusCode, Response) {
-    // validate param
-    if let Err(err) = param.0.validate() {
-        return respond_error(StatusCode::BAD_REQUEST, &ErrorKind::Validation(err));
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+    param: Result<Query<Param>, QueryRejection>,
+) -> axum::response::Response {
+    let detail = state.config.errors.detail;
+    let Some(format) = Format::negotiate(&headers) else {
+        return respond_error(&ErrorKind::NotAcceptable, detail).into_response();
+    };
+    let param = match valid_param(&state, &uri, param) {
+        Ok(param) => param,
+        Err(err) => return format.render(respond_error(&err, detail), fact_text),
     };
-    let animal = param.0.animal.unwrap(); // will always be Some(v) by this point
-    let mut animal = animal.as_str();
+    let filter = param.filter(&state);
+    let Param {
+        animal,
+        count,
+        lang,
+        langs,
+        include_source,
+        all_sources,
+        seed,
+        envelope,
+        ..
+    } = param;
+    let include_source = include_source.unwrap_or(false);
+    let envelope = envelope.unwrap_or(state.config.facts.envelope);
+    let animal = animal.unwrap(); // will always be Some(v) by this point
+    let mut rng = seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
 
-    // choose an animal randomly if the animal param is "any"
-    if animal.to_lowercase() == "any" {
-        let animals: Vec<&str> = all::<Animal>()
-            .collect::<Vec<_>>()
-            .iter()
-            .map(Animal::as_str)
-            .collect();
-        animal = animals.choose(&mut rand::thread_rng()).unwrap_or(&"dog");
+    // several comma-separated animals get one fact each
+    if animal.contains(',') {
+        return match split_animals(&animal, state.config.batch.max_batch_size) {
+            Ok(animals) => {
+                let res = fact_per_animal(&state, animals).await;
+                format.render(res, |value| batch_text(&value["results"]))
+            }
+            Err(err) => format.render(respond_error(&err, detail), fact_text),
+        };
     }
 
     // match on the animal and respond with the appropriate fact or an error
-    match animal.try_into() {
-        Ok(a) => match a {
-            Animal::Cat => match Cat::get_fact(&client, CAT_API_URL).await {
-                Ok(res) => respond_ok(&res.text, animal),
-                Err(err) => respond_error(StatusCode::INTERNAL_SERVER_ERROR, &err),
-            },
-            Animal::Dog => match Dog::get_fact(&client, DOG_API_URL).await {
-                Ok(res) => respond_ok(res.facts.first().unwrap_or(&"Not available".into()), animal),
-                Err(err) => respond_error(StatusCode::INTERNAL_SERVER_ERROR, &err),
-            },
+    let a = match resolve_requested_animal(&state, &animal, &mut rng) {
+        Ok(a) => a,
+        Err(err) => return format.render(respond_error(&err, detail), fact_text),
+    };
+    if all_sources == Some(true) {
+        let res = fact_per_source(&state, &a, &filter, &mut rng).await;
+        state.metrics.record_fact(a.as_str(), res.0.is_success());
+        return format.render(with_envelope(res, envelope), |value| {
+            sources_text(value.get("sources").unwrap_or(value))
+        });
+    }
+    // only a fact served from the cache for a named animal stays the same for a while
+    let mut max_age = None;
+    let res = match count.unwrap_or(1) {
+        1 => match filtered_fact(&state, &a, &filter, &mut rng).await {
+            Ok(Fact {
+                text,
+                source,
+                max_age: cached_for,
+            }) => {
+                if !animal.eq_ignore_ascii_case(ANY_ANIMAL) {
+                    max_age = cached_for;
+                }
+                let translations = translations(&state, &text, langs.as_deref()).await;
+                let (facts, lang) = translate_facts(&state, vec![text], lang.as_deref()).await;
+                record_history(&state, &headers, a.as_str(), &facts);
+                let source = (include_source || source.is_local()).then_some(&source);
+                let mut res = respond_ok(&facts[0], a.as_str(), lang, source);
+                if let Some(translations) = translations {
+                    res.1["translations"] = translations;
+                }
+                res
+            }
+            Err(err) => respond_error(&err, detail),
+        },
+        count => match filtered_facts(&state, &a, count, &filter, &mut rng).await {
+            Ok(facts) => {
+                let (texts, sources): (Vec<_>, Vec<_>) =
+                    facts.into_iter().map(|f| (f.text, f.source)).unzip();
+                let (texts, lang) = translate_facts(&state, texts, lang.as_deref()).await;
+                record_history(&state, &headers, a.as_str(), &texts);
+                // facts contributed by users and fallback facts are always marked
+                let local = sources.iter().any(Source::is_local);
+                let sources = (include_source || local).then_some(sources.as_slice());
+                respond_ok_many(&texts, a.as_str(), lang, sources)
+            }
+            Err(err) => respond_error(&err, detail),
         },
-        Err(err) => respond_error(StatusCode::BAD_REQUEST, &err),
+    };
+    state.metrics.record_fact(a.as_str(), res.0.is_success());
+    let ok = res.0.is_success();
+    let mut res = format.render(with_envelope(res, envelope), fact_text);
+    if ok {
+        res.headers_mut()
+            .extend(cache_headers(&state, max_age, true));
     }
+    res
 }
 
-/// The `Animal` enum.
-#[derive(Debug, PartialEq, Sequence)]
-pub enum Animal {
-    Cat,
-    Dog,
-    // add as many more animals as you want!
+/// Resolves the requested animal, rejecting one recently found unsupported straight away and
+/// remembering one found unsupported now.
+fn resolve_requested_animal(
+    state: &AppState,
+    animal: &str,
+    rng: &mut StdRng,
+) -> Result<Animal, ErrorKind> {
+    if state.rejected.contains(animal) {
+        state.metrics.record_rejected_from_cache();
+        return Err(ErrorKind::ConvertToAnimal(animal.to_lowercase()));
+    }
+    resolve_animal(state, animal, rng).inspect_err(|err| {
+        if let ErrorKind::ConvertToAnimal(_) = err {
+            state
+                .rejected
+                .insert(animal, state.metrics.record_unsupported_animal());
+        }
+    })
 }
 
-/// Implements type conversion from an `Animal` enum to a string literal.
-impl Animal {
-    #[must_use]
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            Animal::Cat => "cat",
-            Animal::Dog => "dog",
-        }
+/// Returns a random fact about the animal named in the path, as `/fact?animal=` does.
+#[utoipa::path(
+    get,
+    context_path = "/v1",
+    path = "/fact/{animal}",
+    tag = "facts",
+    params(
+        ("animal" = String, Path, description = "The animal to get a fact about, or `any` for a random one", example = "cat"),
+        Param
+    ),
+    responses(
+        (status = 200, description = "A fact, or several facts when `count` is above 1", body = FactResponse,
+            content_type = ["application/json", "text/plain"]),
+        (status = 400, description = "Invalid or unsupported animal", body = ErrorResponse),
+        (status = 404, description = "No fact matched the requested filters, or the upstream API found none", body = ErrorResponse),
+        (status = 406, description = "Neither JSON nor plain text is acceptable", body = ErrorResponse),
+        (status = 429, description = "The upstream animal API is rate limiting requests", body = ErrorResponse),
+        (status = 500, description = "The upstream animal API failed", body = ErrorResponse),
+        (status = 502, description = "The upstream animal API couldn't be connected to, or returned a server error or a response that isn't a fact", body = ErrorResponse),
+        (status = 503, description = "The upstream animal API is unavailable", body = ErrorResponse),
+        (status = 504, description = "The upstream animal API timed out", body = ErrorResponse)
+    )
+)]
+pub async fn get_animal_fact_by_path(
+    state: State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+    Path(animal): Path<String>,
+    param: Result<Query<Param>, QueryRejection>,
+) -> axum::response::Response {
+    let param = param.map(|Query(param)| {
+        Query(Param {
+            animal: Some(animal),
+            ..param
+        })
+    });
+    get_animal_fact(state, headers, uri, param).await
+}
+
+/// Answers a HEAD request for a fact about the animal named in the path, as `HEAD /fact` does.
+pub async fn head_animal_fact_by_path(
+    state: State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+    Path(animal): Path<String>,
+    param: Result<Query<Param>, QueryRejection>,
+) -> axum::response::Response {
+    let param = param.map(|Query(param)| {
+        Query(Param {
+            animal: Some(animal),
+            ..param
+        })
+    });
+    head_animal_fact(state, headers, uri, param).await
+}
+
+/// Answers a HEAD request for a fact with the status and headers a GET would get, without
+/// fetching a fact from upstream.
+#[tracing::instrument(name = "Checking an animal fact", skip(state, headers, uri, param))]
+pub async fn head_animal_fact(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+    param: Result<Query<Param>, QueryRejection>,
+) -> axum::response::Response {
+    let detail = state.config.errors.detail;
+    let Some(format) = Format::negotiate(&headers) else {
+        return respond_error(&ErrorKind::NotAcceptable, detail).into_response();
+    };
+    let param = match valid_param(&state, &uri, param) {
+        Ok(param) => param,
+        Err(err) => return format.render(respond_error(&err, detail), fact_text),
+    };
+    let animal = param.animal.unwrap_or_default(); // will always be Some(v) by this point
+    let res = if animal.contains(',') {
+        split_animals(&animal, state.config.batch.max_batch_size).map(|_| ())
+    } else {
+        resolve_animal(&state, &animal, &mut StdRng::from_entropy()).map(|_| ())
+    };
+    match res {
+        Ok(()) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, format.content_type())],
+        )
+            .into_response(),
+        Err(err) => format.render(respond_error(&err, detail), fact_text),
     }
 }
 
-/// Implements type conversion from a string literal to an `Animal` enum.
-impl TryFrom<&str> for Animal {
-    type Error = ErrorKind;
+/// Returns the request's params with the configured default animal and normalization applied,
+/// once they pass validation. A param that can't be parsed fails validation too.
+fn valid_param(
+    state: &AppState,
+    uri: &Uri,
+    param: Result<Query<Param>, QueryRejection>,
+) -> Result<Param, ErrorKind> {
+    let Query(mut param) = param.map_err(|_| ErrorKind::Validation(query_errors::<Param>(uri)))?;
+    apply_default_animal(state, &mut param);
+    normalize_animal_param(state, &mut param);
+    param.validate().map_err(ErrorKind::Validation)?;
+    Ok(param)
+}
 
-    fn try_from(animal_param: &str) -> Result<Self, Self::Error> {
-        match animal_param.to_lowercase().as_str() {
-            "cat" => Ok(Self::Cat),
-            "dog" => Ok(Self::Dog),
-            other => Err(ErrorKind::ConvertToAnimal(other.to_string())),
+/// Describes a query string that couldn't be parsed as a failure of each param given a value of
+/// the wrong type, found by parsing the params one at a time.
+fn query_errors<T: DeserializeOwned>(uri: &Uri) -> ValidationErrors {
+    let mut errs = ValidationErrors::new();
+    let query = Url::parse(&format!(
+        "http://localhost/?{}",
+        uri.query().unwrap_or_default()
+    ));
+    for (name, value) in query.iter().flat_map(Url::query_pairs) {
+        let mut single = Url::parse("http://localhost/").expect("A valid URL");
+        single.query_pairs_mut().append_pair(&name, &value);
+        let Ok(single) = single.as_str().parse::<Uri>() else {
+            continue;
+        };
+        if let Err(rejection) = Query::<T>::try_from_uri(&single) {
+            // keyed by the param named in the code, as for checks spanning several params
+            let mut error = ValidationError::new("invalid_value");
+            error.code = name.into_owned().into();
+            error.message = Some(rejection.body_text().into());
+            errs.add("__all__", error);
         }
     }
+    errs
 }
 
-/// Provides a `get_fact` function for an animal API return struct.
-trait GetFact {
-    async fn get_fact(client: &Client, url: &str) -> Result<Self, ErrorKind>
-    where
-        Self: for<'de> de::Deserialize<'de> + Sized,
-    {
-        let res = client
-            .get(url)
-            .send()
-            .await
-            .map_err(|err| ErrorKind::ApiRequest(err.to_string()))?;
-        // check status first
-        let status = res.status();
-        if !status.is_success() {
-            Err(ErrorKind::ApiResponse(status.as_u16()))?;
+/// Uses the configured default animal when the param is missing. An empty param is left for
+/// validation to reject.
+fn apply_default_animal(state: &AppState, param: &mut Param) {
+    let default_animal = &state.config.facts.default_animal;
+    if param.animal.is_none() && !default_animal.is_empty() {
+        param.animal = Some(default_animal.clone());
+    }
+}
+
+/// Normalizes the animal param, when enabled, so padded or differently cased names resolve.
+fn normalize_animal_param(state: &AppState, param: &mut Param) {
+    if state.config.facts.normalize_animal {
+        param.animal = param.animal.as_deref().map(normalize_animal);
+    }
+}
+
+/// Trims the name, collapses each run of whitespace inside it to a single space and lowercases
+/// it, following Unicode rather than only ASCII case rules.
+fn normalize_animal(animal: &str) -> String {
+    animal
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Splits a comma-separated animal param into distinct animals, in the order given, allowing at
+/// least one and at most `max` of them.
+fn split_animals(param: &str, max: usize) -> Result<Vec<String>, ErrorKind> {
+    let mut animals: Vec<String> = vec![];
+    for animal in param.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+        if !animals.iter().any(|a| a.eq_ignore_ascii_case(animal)) {
+            animals.push(animal.to_string());
         }
-        let text = res
-            .text()
-            .await
-            .map_err(|err| ErrorKind::ToText(err.to_string()))?;
-        serde_json::from_str(&text).map_err(|err| ErrorKind::Deserialization(err.to_string()))
     }
+    if animals.is_empty() {
+        return Err(ErrorKind::ConvertToAnimal(param.trim().to_string()));
+    }
+    if animals.len() > max {
+        return Err(ErrorKind::TooManyAnimals(max));
+    }
+    Ok(animals)
 }
 
-/// The cat API return type.
-#[derive(serde::Deserialize)]
-pub struct Cat {
-    text: String,
+/// Fetches a fact for each animal concurrently, describing any failure in the animal's result.
+async fn fact_per_animal(state: &AppState, animals: Vec<String>) -> (StatusCode, Response) {
+    let results = join_all(animals.into_iter().map(|animal| batch_item(state, animal))).await;
+    let value = json!({ "results": results });
+    tracing::info!("Success response payload: {value}");
+    (StatusCode::OK, Json(with_request_id(value)))
 }
 
-impl GetFact for Cat {}
+/// Fetches a fact matching the filter from each upstream API configured for the animal
+/// concurrently, keyed by provider, describing any failure in place of the API's fact.
+async fn fact_per_source(
+    state: &AppState,
+    animal: &Animal,
+    filter: &FactFilter<'_>,
+    rng: &mut StdRng,
+) -> (StatusCode, Response) {
+    let urls = animal.api_urls(&state.config.api);
+    let results = join_all(urls.iter().map(|url| {
+        let mut rng = StdRng::seed_from_u64(rng.gen());
+        async move { filtered_fact_from(state, animal, url, filter, &mut rng).await }
+    }))
+    .await;
+    let sources: serde_json::Map<String, Value> = results
+        .into_iter()
+        .enumerate()
+        .map(|(i, res)| {
+            let value = match res {
+                Ok(fact) => json!(fact.text),
+                Err(err) => {
+                    let message = err.client_message(state.config.errors.detail);
+                    json!({ "error": { "code": err.code(), "message": message } })
+                }
+            };
+            (provider_name(i), value)
+        })
+        .collect();
+    let value = json!({ "sources": sources, "animal": animal.as_str() });
+    tracing::info!("Success response payload: {value}");
+    (StatusCode::OK, Json(with_request_id(value)))
+}
 
-/// The dog API return type.
-#[derive(serde::Deserialize)]
-pub struct Dog {
-    facts: Vec<String>,
+/// Fetches a fact matching the filter from the upstream API at `url`, trying up to the
+/// configured number of attempts.
+async fn filtered_fact_from(
+    state: &AppState,
+    animal: &Animal,
+    url: &str,
+    filter: &FactFilter<'_>,
+    rng: &mut StdRng,
+) -> Result<Fact, ErrorKind> {
+    for _ in 0..state.config.filter.max_attempts.max(1) {
+        let fact = fetch_fact_from(state, animal, &[url], filter.selection, rng).await?;
+        if filter.matches(&fact.text) {
+            return Ok(fact);
+        }
+    }
+    Err(ErrorKind::NoMatchingFact(animal.as_str().into()))
 }
 
-impl GetFact for Dog {}
+/// Names the animal's upstream API at `index` in its configured order: `primary`, then
+/// `fallback_1` and so on.
+fn provider_name(index: usize) -> String {
+    match index {
+        0 => "primary".into(),
+        i => format!("fallback_{i}"),
+    }
+}
 
-/// The Handler error types.
-#[derive(Debug, thiserror::Error)]
-pub enum ErrorKind {
-    #[error("{0}")]
-    Validation(#[source] ValidationErrors),
+/// The plain text body for a response with a fact per source: one line per source, with its fact
+/// or error message.
+fn sources_text(value: &Value) -> String {
+    value
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(provider, fact)| {
+            let text = fact
+                .as_str()
+                .or_else(|| fact["error"]["message"].as_str())
+                .unwrap_or_default();
+            format!("{provider}: {text}")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
-    #[error("Error during Request to animal API: {0}")]
-    ApiRequest(String),
+/// Leaves a successful fact response as it is when `envelope` is set, and otherwise replaces it
+/// with its bare fact, or list of facts. A response with translations, or with a fact
+/// contributed by a user or a fallback fact, keeps its envelope either way, as the bare fact
+/// would lose them or its marker.
+pub(super) fn with_envelope(
+    (status, Json(value)): (StatusCode, Response),
+    envelope: bool,
+) -> (StatusCode, Response) {
+    if envelope
+        || !status.is_success()
+        || value.get("translations").is_some()
+        || has_local_source(&value)
+    {
+        return (status, Json(value));
+    }
+    let bare = value
+        .get("fact")
+        .or_else(|| value.get("facts"))
+        .or_else(|| value.get("sources"))
+        .cloned();
+    (status, Json(bare.unwrap_or(value)))
+}
 
-    #[error("Response from animal API returned error code: {0}")]
-    ApiResponse(u16),
+/// Checks whether any fact in the response is marked as contributed by a user or a fallback.
+fn has_local_source(value: &Value) -> bool {
+    let sources = value.get("sources").and_then(Value::as_array);
+    value
+        .get("source")
+        .into_iter()
+        .chain(sources.into_iter().flatten())
+        .any(|source| serde_json::from_value::<Source>(source.clone()).is_ok_and(|s| s.is_local()))
+}
 
-    #[error("Error fetching text: {0}")]
-    ToText(String),
+/// The plain text body for a fact response, with or without its envelope: the fact, or one fact
+/// per line.
+pub(super) fn fact_text(value: &Value) -> String {
+    match value
+        .get("fact")
+        .or_else(|| value.get("facts"))
+        .unwrap_or(value)
+    {
+        Value::String(fact) => fact.clone(),
+        Value::Array(facts) => facts
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
 
-    #[error("Error deserializing json string: {0}")]
-    Deserialization(String),
+/// Resolves an animal name or alias, choosing a random animal if it is `any`.
+pub(super) fn resolve_animal(
+    state: &AppState,
+    animal: &str,
+    rng: &mut impl Rng,
+) -> Result<Animal, ErrorKind> {
+    if animal.eq_ignore_ascii_case(ANY_ANIMAL) {
+        return Ok(random_animal(state, rng));
+    }
+    Animal::lookup(state, animal)
+}
 
-    #[error("'{0}' is not a supported animal.")]
-    ConvertToAnimal(String),
+/// Chooses one of the supported animals at random, in proportion to their configured weights.
+pub(super) fn random_animal(state: &AppState, rng: &mut impl Rng) -> Animal {
+    state
+        .animal_weights
+        .choose(&Animal::supported(state), rng)
+        .unwrap_or(Animal::Dog)
 }
 
-#[cfg(test)]
-mod tests {
-    use reqwest::Client;
-    use wiremock::matchers::{any, method, path, query_param};
-    use wiremock::{Mock, MockServer, ResponseTemplate};
+/// Why the configured animal weights are invalid.
+#[derive(Debug, thiserror::Error)]
+pub enum AnimalWeightsError {
+    #[error("'{0}' is not a supported animal")]
+    UnknownAnimal(String),
 
-    use super::GetFact;
-    use super::{Cat, Dog};
+    #[error(transparent)]
+    Invalid(#[from] WeightedError),
+}
 
-    #[tokio::test]
-    async fn test_cat_get_fact() {
-        let mock_server = MockServer::start().await;
+/// The weights random animals are chosen in proportion to, one for each supported animal in the
+/// order of [`Animal::supported`].
+pub struct AnimalWeights(WeightedIndex<f64>);
 
-        Mock::given(any())
-            .and(path("/facts/random"))
-            .and(query_param("animal_type", "cat"))
-            .and(method("GET"))
-            .respond_with(
-                ResponseTemplate::new(200).set_body_raw(r#"{"text": "fact"}"#, "application/json"),
-            )
-            .expect(1)
-            .mount(&mock_server)
-            .await;
+impl AnimalWeights {
+    /// Builds the weights of the animals from the configured ones, 1 for an animal without one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a weight is for an unsupported animal, or is negative or not a number,
+    /// or if no animal has a positive weight.
+    pub fn new(
+        animals: &[Animal],
+        weights: &BTreeMap<String, f64>,
+    ) -> Result<Self, AnimalWeightsError> {
+        if let Some(unknown) = weights.keys().find(|name| {
+            !animals
+                .iter()
+                .any(|animal| animal.as_str() == name.as_str())
+        }) {
+            return Err(AnimalWeightsError::UnknownAnimal(unknown.clone()));
+        }
+        let weights = animals
+            .iter()
+            .map(|animal| weights.get(animal.as_str()).copied().unwrap_or(1.0));
+        Ok(Self(WeightedIndex::new(weights)?))
+    }
 
-        let res = Cat::get_fact(
-            &Client::new(),
-            &format!("{}/{}", mock_server.uri(), "facts/random?animal_type=cat"),
+    /// Chooses one of the animals the weights were built for, in proportion to its weight.
+    fn choose(&self, animals: &[Animal], rng: &mut impl Rng) -> Option<Animal> {
+        animals.get(self.0.sample(rng)).cloned()
+    }
+}
+
+/// Translates the facts into `lang` if requested, returning them with the language they are in.
+///
+/// If any translation fails the original English facts are returned instead.
+async fn translate_facts<'a>(
+    state: &AppState,
+    facts: Vec<String>,
+    lang: Option<&'a str>,
+) -> (Vec<String>, Option<&'a str>) {
+    let Some(lang) = lang else {
+        return (facts, None);
+    };
+    if lang.eq_ignore_ascii_case(SOURCE_LANG) {
+        return (facts, Some(SOURCE_LANG));
+    }
+    let client = state.client.current();
+    let translations = join_all(
+        facts
+            .iter()
+            .map(|fact| translate(&client, &state.config.translation.url, fact, lang)),
+    )
+    .await;
+    match translations.into_iter().collect::<Result<Vec<_>, _>>() {
+        Ok(translated) => (translated, Some(lang)),
+        Err(err) => {
+            tracing::warn!("Translation to '{lang}' failed, returning the original: {err}");
+            (facts, Some(SOURCE_LANG))
+        }
+    }
+}
+
+/// Translates the fact into each of the comma-separated languages concurrently, keyed by
+/// language, with `null` for a language whose translation failed.
+async fn translations(state: &AppState, fact: &str, langs: Option<&str>) -> Option<Value> {
+    let langs = split_langs(langs?);
+    let translations = join_all(langs.iter().map(|lang| async move {
+        if lang == SOURCE_LANG {
+            return (lang.clone(), Some(fact.to_string()));
+        }
+        match translate(
+            &state.client.current(),
+            &state.config.translation.url,
+            fact,
+            lang,
         )
         .await
-        .expect("Failed to get cat fact.");
+        {
+            Ok(translated) => (lang.clone(), Some(translated)),
+            Err(err) => {
+                tracing::warn!("Translation to '{lang}' failed, returning null for it: {err}");
+                (lang.clone(), None)
+            }
+        }
+    }))
+    .await;
+    let translations: serde_json::Map<_, _> = translations
+        .into_iter()
+        .map(|(lang, translated)| (lang, json!(translated)))
+        .collect();
+    Some(translations.into())
+}
 
-        assert!(!res.text.is_empty());
+/// The constraints on which facts may be returned: the request's length bounds and keyword and
+/// the configured banned words, along with which fact to take from an upstream response with
+/// several.
+pub(super) struct FactFilter<'a> {
+    min_len: Option<usize>,
+    max_len: Option<usize>,
+    /// The keyword facts must contain, lowercased.
+    keyword: Option<String>,
+    banned_words: &'a [String],
+    selection: Selection,
+}
+
+impl<'a> FactFilter<'a> {
+    pub(super) fn new(state: &'a AppState, min_len: Option<usize>, max_len: Option<usize>) -> Self {
+        Self {
+            min_len,
+            max_len,
+            keyword: None,
+            banned_words: &state.config.filter.banned_words,
+            selection: state.config.facts.selection,
+        }
     }
 
-    #[tokio::test]
-    async fn test_dog_get_fact() {
-        let mock_server = MockServer::start().await;
+    /// Overrides the configured selection when the request gives one.
+    fn with_selection(mut self, selection: Option<Selection>) -> Self {
+        if let Some(selection) = selection {
+            self.selection = selection;
+        }
+        self
+    }
 
-        Mock::given(any())
-            .and(path("/api/facts"))
-            .and(method("GET"))
-            .respond_with(
-                ResponseTemplate::new(200)
-                    .set_body_raw(r#"{"facts": ["fact"]}"#, "application/json"),
-            )
-            .expect(1)
-            .mount(&mock_server)
-            .await;
+    /// Only lets through facts containing the keyword, ignoring case, when the request gives one.
+    fn with_keyword(mut self, keyword: Option<&str>) -> Self {
+        self.keyword = keyword.map(str::to_lowercase);
+        self
+    }
 
-        let res = Dog::get_fact(
-            &Client::new(),
-            &format!("{}/{}", mock_server.uri(), "api/facts"),
-        )
-        .await
-        .expect("Failed to get dog fact.");
+    fn is_empty(&self) -> bool {
+        self.min_len.is_none()
+            && self.max_len.is_none()
+            && self.keyword.is_none()
+            && self.banned_words.is_empty()
+    }
 
-        assert!(!res.facts.first().expect("").is_empty());
+    pub(super) fn matches(&self, fact: &str) -> bool {
+        let len = fact.chars().count();
+        self.min_len.is_none_or(|min_len| len >= min_len)
+            && self.max_len.is_none_or(|max_len| len <= max_len)
+            && self
+                .keyword
+                .as_ref()
+                .is_none_or(|keyword| fact.to_lowercase().contains(keyword))
+            && !self.is_banned(fact)
+    }
+
+    /// Checks whether any word of the fact is banned, ignoring case.
+    fn is_banned(&self, fact: &str) -> bool {
+        !self.banned_words.is_empty()
+            && fact.split(|c: char| !c.is_alphanumeric()).any(|word| {
+                self.banned_words
+                    .iter()
+                    .any(|b| b.eq_ignore_ascii_case(word))
+            })
+    }
+}
+
+/// Returns a fact matching the filter, trying fresh facts up to the configured number of
+/// attempts, or a bundled fallback fact when enabled and neither the cache nor any upstream has
+/// one.
+pub(super) async fn filtered_fact(
+    state: &AppState,
+    animal: &Animal,
+    filter: &FactFilter<'_>,
+    rng: &mut StdRng,
+) -> Result<Fact, ErrorKind> {
+    if let Some(fact) = user_fact(state, animal, filter, rng).await {
+        return Ok(fact);
+    }
+    let fact = match cached_fact(state, animal, filter.selection, rng).await {
+        Ok(fact) => fact,
+        Err(err) => return fallback_fact(state, animal, filter, rng).ok_or(err),
+    };
+    if filter.matches(&fact.text) {
+        return Ok(fact);
+    }
+    for _ in 1..state.config.filter.max_attempts {
+        let fact = fetch_fact(state, animal, filter.selection, rng).await?;
+        if filter.selection == state.config.facts.selection {
+            cache_fact(state, animal, &fact).await;
+        }
+        if filter.matches(&fact.text) {
+            return Ok(fact);
+        }
+    }
+    Err(ErrorKind::NoMatchingFact(animal.as_str().into()))
+}
+
+/// Picks a fact contributed by a user matching the filter for a configured share of requests.
+async fn user_fact(
+    state: &AppState,
+    animal: &Animal,
+    filter: &FactFilter<'_>,
+    rng: &mut StdRng,
+) -> Option<Fact> {
+    let share = state.config.user_facts.share.clamp(0.0, 1.0);
+    if !rng.gen_bool(share) {
+        return None;
+    }
+    let fact = state
+        .user_facts
+        .random(animal.as_str(), &|fact| filter.matches(fact), rng)
+        .await?;
+    tracing::info!("Serving {} fact contributed by a user", animal.as_str());
+    Some(Fact::from_user(fact))
+}
+
+/// Picks a bundled fallback fact matching the filter, when they're enabled.
+fn fallback_fact(
+    state: &AppState,
+    animal: &Animal,
+    filter: &FactFilter<'_>,
+    rng: &mut StdRng,
+) -> Option<Fact> {
+    if !state.config.facts.fallback {
+        return None;
+    }
+    let fact = fallback_facts(animal.as_str())
+        .iter()
+        .filter(|fact| filter.matches(fact))
+        .choose(rng)?;
+    tracing::warn!("No {} fact available, serving a fallback", animal.as_str());
+    state.metrics.record_fallback_fact(animal.as_str());
+    Some(Fact::fallback(fact))
+}
+
+/// Fetches a fact straight from upstream, bypassing the cache, retrying until one matches the
+/// filter.
+pub(super) async fn fresh_fact(
+    state: &AppState,
+    animal: &Animal,
+    filter: &FactFilter<'_>,
+    rng: &mut StdRng,
+) -> Result<Fact, ErrorKind> {
+    for _ in 0..state.config.filter.max_attempts.max(1) {
+        let fact = fetch_fact(state, animal, filter.selection, rng).await?;
+        if filter.matches(&fact.text) {
+            return Ok(fact);
+        }
+    }
+    Err(ErrorKind::NoMatchingFact(animal.as_str().into()))
+}
+
+/// Fetches up to `count` facts, keeping those matching the filter.
+pub(super) async fn filtered_facts(
+    state: &AppState,
+    animal: &Animal,
+    count: u8,
+    filter: &FactFilter<'_>,
+    rng: &mut StdRng,
+) -> Result<Vec<Fact>, ErrorKind> {
+    let mut facts = fetch_facts(state, animal, count, filter.selection, rng).await?;
+    if filter.is_empty() {
+        return Ok(facts);
+    }
+    facts.retain(|fact| filter.matches(&fact.text));
+    if facts.is_empty() {
+        Err(ErrorKind::NoMatchingFact(animal.as_str().into()))
+    } else {
+        Ok(facts)
+    }
+}
+
+/// Returns a fact for the animal from the cache, fetching and caching one on a miss.
+///
+/// The fetch is coalesced with any other in flight for the same animal and selection. The cache
+/// only holds facts taken with the configured selection, so a request for another one bypasses
+/// it.
+pub(super) async fn cached_fact(
+    state: &AppState,
+    animal: &Animal,
+    selection: Selection,
+    rng: &mut StdRng,
+) -> Result<Fact, ErrorKind> {
+    if selection != state.config.facts.selection {
+        return fetch_fact(state, animal, selection, rng).await;
+    }
+    if let Some(CachedFact {
+        mut fact,
+        stale,
+        expires_in,
+    }) = state.cache.get_fact(animal.as_str(), rng).await
+    {
+        tracing::info!("Serving {} fact from cache", animal.as_str());
+        fact.max_age = Some(expires_in);
+        if stale {
+            state.metrics.record_cache_lookup("stale");
+            refresh_in_background(state, animal, StdRng::seed_from_u64(rng.gen()));
+        } else {
+            state.metrics.record_cache_lookup("hit");
+        }
+        return Ok(fact);
+    }
+    state.metrics.record_cache_lookup("miss");
+    fetch_into_cache(state, animal, StdRng::seed_from_u64(rng.gen())).await
+}
+
+/// Fetches a fact for the animal with the configured selection and caches it.
+///
+/// Concurrent fetches for the same animal, whether for cache misses or refreshes of stale facts,
+/// share one upstream call, which caches its fact once.
+async fn fetch_into_cache(
+    state: &AppState,
+    animal: &Animal,
+    mut rng: StdRng,
+) -> Result<Fact, ErrorKind> {
+    let selection = state.config.facts.selection;
+    let key = (animal.as_str(), selection);
+    let (state, animal) = (state.clone(), animal.clone());
+    let in_flight = state.in_flight.clone();
+    in_flight
+        .run(key, move || async move {
+            let fact = fetch_fact(&state, &animal, selection, &mut rng).await?;
+            cache_fact(&state, &animal, &fact).await;
+            Ok(fact)
+        })
+        .await
+}
+
+/// Fetches a fresh fact for the animal into the cache, replacing a stale one, without holding up
+/// the request that found it stale. Requests finding the fact stale while it is refreshed share
+/// the refresh.
+fn refresh_in_background(state: &AppState, animal: &Animal, rng: StdRng) {
+    tracing::info!(
+        "Refreshing stale {} fact in the background",
+        animal.as_str()
+    );
+    let state = state.clone();
+    let animal = animal.clone();
+    tokio::spawn(
+        async move {
+            if let Err(err) = fetch_into_cache(&state, &animal, rng).await {
+                tracing::warn!("Failed to refresh stale {} fact: {err}", animal.as_str());
+            }
+        }
+        .in_current_span(),
+    );
+}
+
+/// Fetches up to `count` facts for the animal, caching each of them when taken with the
+/// configured selection.
+///
+/// The dog API can return several facts in one response, asked for with the count param of its
+/// origin, and they are taken in the order of the selection; the other APIs are called
+/// concurrently once per fact.
+async fn fetch_facts(
+    state: &AppState,
+    animal: &Animal,
+    count: u8,
+    selection: Selection,
+    rng: &mut StdRng,
+) -> Result<Vec<Fact>, ErrorKind> {
+    let AppState {
+        client,
+        upstream_headers,
+        upstream_permits,
+        config,
+        breakers,
+        latencies,
+        ..
+    } = state;
+    let (api, retry) = (&config.api, &config.retry);
+    let facts = match animal {
+        Animal::Dog => {
+            let urls = animal
+                .api_urls(api)
+                .into_iter()
+                .map(|url| {
+                    let mut url =
+                        Url::parse(url).map_err(|err| ErrorKind::ApiRequest(err.to_string()))?;
+                    let param = api
+                        .count_params
+                        .get(&url.origin().ascii_serialization())
+                        .map_or("number", String::as_str);
+                    url.query_pairs_mut().append_pair(param, &count.to_string());
+                    Ok(url.to_string())
+                })
+                .collect::<Result<Vec<_>, ErrorKind>>()?;
+            let urls: Vec<&str> = urls.iter().map(String::as_str).collect();
+            let urls = provider_order(state, &urls);
+            let _in_flight = state.metrics.start_upstream_request();
+            let (mut dog, url) = Dog::get_fact_from_any(
+                client,
+                upstream_headers,
+                upstream_permits,
+                &urls,
+                retry,
+                breakers,
+                latencies,
+            )
+            .await?;
+            order_facts(&mut dog.facts, selection, rng);
+            dog.facts
+                .into_iter()
+                .take(count.into())
+                .map(|text| Fact::new(text, url, None))
+                .collect()
+        }
+        _ => join_all((0..count).map(|_| {
+            let mut rng = StdRng::seed_from_u64(rng.gen());
+            async move { fetch_fact(state, animal, selection, &mut rng).await }
+        }))
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?,
+    };
+    if selection == config.facts.selection {
+        for fact in &facts {
+            cache_fact(state, animal, fact).await;
+        }
+    }
+    Ok(facts)
+}
+
+/// Caches a fact for the animal unless it has a banned word, so it is never served from the
+/// cache to a request that doesn't filter it out. Returns whether it was cached.
+pub async fn cache_fact(state: &AppState, animal: &Animal, fact: &Fact) -> bool {
+    if !FactFilter::new(state, None, None).matches(&fact.text) {
+        tracing::info!("Not caching a {} fact with a banned word", animal.as_str());
+        return false;
+    }
+    state.cache.insert_fact(animal.as_str(), fact.clone()).await;
+    true
+}
+
+/// Fetches a fact for the animal from its upstream API, taking the selected one when the response
+/// has several.
+pub async fn fetch_fact(
+    state: &AppState,
+    animal: &Animal,
+    selection: Selection,
+    rng: &mut StdRng,
+) -> Result<Fact, ErrorKind> {
+    let urls = provider_order(state, &animal.api_urls(&state.config.api));
+    fetch_fact_from(state, animal, &urls, selection, rng).await
+}
+
+/// Orders an animal's upstream API URLs by the configured provider order: as listed, or fastest
+/// first.
+fn provider_order<'a>(state: &AppState, urls: &[&'a str]) -> Vec<&'a str> {
+    match state.config.api.provider_order {
+        ProviderOrder::Configured => urls.to_vec(),
+        ProviderOrder::Latency => state.latencies.order(urls, &state.breakers),
+    }
+}
+
+/// Fetches a fact for the animal from the first of the upstream APIs at `urls` to succeed.
+async fn fetch_fact_from(
+    state: &AppState,
+    animal: &Animal,
+    urls: &[&str],
+    selection: Selection,
+    rng: &mut StdRng,
+) -> Result<Fact, ErrorKind> {
+    let AppState {
+        client,
+        upstream_headers,
+        upstream_permits,
+        config,
+        breakers,
+        latencies,
+        ..
+    } = state;
+    let retry = &config.retry;
+    let _in_flight = state.metrics.start_upstream_request();
+    match animal {
+        Animal::Cat => Cat::get_fact_from_any(
+            client,
+            upstream_headers,
+            upstream_permits,
+            urls,
+            retry,
+            breakers,
+            latencies,
+        )
+        .await
+        .map(|(res, url)| Fact::new(res.text, url, res.id)),
+        Animal::Dog => Dog::get_fact_from_any(
+            client,
+            upstream_headers,
+            upstream_permits,
+            urls,
+            retry,
+            breakers,
+            latencies,
+        )
+        .await
+        .map(|(mut res, url)| {
+            order_facts(&mut res.facts, selection, rng);
+            let text = res
+                .facts
+                .into_iter()
+                .next()
+                .unwrap_or("Not available".into());
+            Fact::new(text, url, None)
+        }),
+        Animal::Bird => Bird::get_fact_from_any(
+            client,
+            upstream_headers,
+            upstream_permits,
+            urls,
+            retry,
+            breakers,
+            latencies,
+        )
+        .await
+        .map(|(res, url)| Fact::new(res.fact, url, None)),
+        Animal::Configured(ConfiguredAnimal(name)) => {
+            let (res, url) = Value::get_fact_from_any(
+                client,
+                upstream_headers,
+                upstream_permits,
+                urls,
+                retry,
+                breakers,
+                latencies,
+            )
+            .await?;
+            let pointer = config
+                .api
+                .animals
+                .get(*name)
+                .map_or("", |api| api.fact_pointer.as_str());
+            if let Some(Value::String(text)) = res.pointer(pointer) {
+                Ok(Fact::new(text.clone(), url, None))
+            } else {
+                tracing::error!(
+                    "The {name} API returned no fact at '{pointer}': {}",
+                    truncate(&res.to_string(), MAX_LOGGED_BODY_LEN)
+                );
+                Err(ErrorKind::UpstreamContract(format!(
+                    "no fact at '{pointer}'"
+                )))
+            }
+        }
+    }
+}
+
+/// A fact and the upstream it came from.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Fact {
+    pub text: String,
+    pub source: Source,
+    /// How much longer the fact is cached for, when it was served from the cache.
+    #[serde(skip)]
+    pub max_age: Option<Duration>,
+}
+
+impl Fact {
+    fn new(text: String, url: &str, id: Option<String>) -> Self {
+        Self {
+            text,
+            source: Source::Upstream {
+                url: url.to_string(),
+                id,
+            },
+            max_age: None,
+        }
+    }
+
+    fn from_user(text: String) -> Self {
+        Self {
+            text,
+            source: Source::User(UserSource::User),
+            max_age: None,
+        }
+    }
+
+    fn fallback(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            source: Source::Fallback(FallbackSource::Fallback),
+            max_age: None,
+        }
+    }
+}
+
+/// Records the facts served about the animal in the history, along with the request's id.
+pub(super) fn record_history(
+    state: &AppState,
+    headers: &HeaderMap,
+    animal: &str,
+    facts: &[String],
+) {
+    let request_id = headers
+        .get(state.config.application.request_id_header.as_str())
+        .and_then(|id| id.to_str().ok());
+    for fact in facts {
+        state.history.record(animal, fact, request_id);
+    }
+}
+
+/// The caching headers of a fact response: cacheable for `max_age` when the response will be the
+/// same until then, otherwise not to be stored.
+///
+/// A response `negotiated` by its `Accept` header varies by it. When API keys are configured,
+/// only private caches may store the response, and it varies by the key too.
+pub(super) fn cache_headers(
+    state: &AppState,
+    max_age: Option<Duration>,
+    negotiated: bool,
+) -> HeaderMap {
+    let keyed = !state.api_keys.is_empty();
+    let mut headers = HeaderMap::new();
+    let cache_control = match max_age {
+        Some(max_age) => {
+            let scope = if keyed { "private" } else { "public" };
+            HeaderValue::from_str(&format!("{scope}, max-age={}", max_age.as_secs()))
+                .expect("Cache-Control is a valid header value")
+        }
+        None => HeaderValue::from_static("no-store"),
+    };
+    headers.insert(header::CACHE_CONTROL, cache_control);
+    let vary = match (negotiated, keyed) {
+        (true, true) => Some("accept, x-api-key"),
+        (true, false) => Some("accept"),
+        (false, true) => Some("x-api-key"),
+        (false, false) => None,
+    };
+    if let Some(vary) = vary {
+        headers.insert(header::VARY, HeaderValue::from_static(vary));
+    }
+    headers
+}
+
+/// Where a fact came from: the upstream API that served it, with the fact's id there when it has
+/// one, a user, serialized as `"user"`, or the bundled fallback facts, serialized as `"fallback"`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum Source {
+    Upstream {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    User(UserSource),
+    Fallback(FallbackSource),
+}
+
+impl Source {
+    /// Whether the fact was contributed by a user or is a fallback rather than from upstream,
+    /// which is always shown.
+    #[must_use]
+    pub fn is_local(&self) -> bool {
+        self.local_marker().is_some()
+    }
+
+    /// The marker of a fact contributed by a user or a fallback, as serialized, or `None` for a
+    /// fact from upstream.
+    #[must_use]
+    pub fn local_marker(&self) -> Option<&'static str> {
+        match self {
+            Self::User(_) => Some("user"),
+            Self::Fallback(_) => Some("fallback"),
+            Self::Upstream { .. } => None,
+        }
+    }
+}
+
+/// The marker of a fact contributed by a user.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserSource {
+    User,
+}
+
+/// The marker of a bundled fallback fact.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FallbackSource {
+    Fallback,
+}
+
+/// The `Animal` enum.
+#[derive(Clone, Debug, PartialEq, Sequence)]
+pub enum Animal {
+    Cat,
+    Dog,
+    Bird,
+    // add as many more animals as you want, or configure them under `api.animals`!
+    Configured(ConfiguredAnimal),
+}
+
+/// An animal added through `api.animals` in the config rather than compiled in.
+///
+/// Configured animals are only known once the config is loaded, so there are none to enumerate
+/// and `all::<Animal>()` only yields the compiled animals; use `Animal::supported` for them all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConfiguredAnimal(&'static str);
+
+impl ConfiguredAnimal {
+    /// Makes a configured animal of the name, which is leaked so it lives as long as the compiled
+    /// animals' names. Only call this once per configured animal, when building the state.
+    #[must_use]
+    pub fn new(name: &str) -> Self {
+        Self(Box::leak(name.to_lowercase().into_boxed_str()))
+    }
+}
+
+impl Sequence for ConfiguredAnimal {
+    const CARDINALITY: usize = 0;
+
+    fn next(&self) -> Option<Self> {
+        None
+    }
+
+    fn previous(&self) -> Option<Self> {
+        None
+    }
+
+    fn first() -> Option<Self> {
+        None
+    }
+
+    fn last() -> Option<Self> {
+        None
+    }
+}
+
+/// Implements type conversion from an `Animal` enum to a string literal.
+impl Animal {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Animal::Cat => "cat",
+            Animal::Dog => "dog",
+            Animal::Bird => "bird",
+            Animal::Configured(ConfiguredAnimal(name)) => name,
+        }
+    }
+
+    /// Resolves a name or alias to a compiled animal, or failing that to a configured one.
+    pub fn lookup(state: &AppState, name: &str) -> Result<Self, ErrorKind> {
+        Self::try_from(name).or_else(|err| {
+            state
+                .configured_animals
+                .iter()
+                .find(|animal| animal.0.eq_ignore_ascii_case(name))
+                .map(|animal| Animal::Configured(*animal))
+                .ok_or(err)
+        })
+    }
+
+    /// The compiled animals followed by the configured ones.
+    #[must_use]
+    pub fn supported(state: &AppState) -> Vec<Self> {
+        Self::supported_with(&state.configured_animals)
+    }
+
+    /// The compiled animals followed by the `configured` ones.
+    #[must_use]
+    pub fn supported_with(configured: &[ConfiguredAnimal]) -> Vec<Self> {
+        all::<Animal>()
+            .chain(configured.iter().copied().map(Animal::Configured))
+            .collect()
+    }
+
+    /// Returns the configured upstream API URLs for the animal, in the order they should be tried.
+    #[must_use]
+    pub fn api_urls<'a>(&self, api: &'a ApiSettings) -> Vec<&'a str> {
+        let fallback_urls = match self {
+            Animal::Cat => api.cat_fallback_urls.as_slice(),
+            Animal::Dog => api.dog_fallback_urls.as_slice(),
+            Animal::Bird | Animal::Configured(_) => &[],
+        };
+        let mut urls = vec![self.api_url(api)];
+        urls.extend(fallback_urls.iter().map(String::as_str));
+        urls
+    }
+
+    /// Returns the primary configured upstream API URL for the animal.
+    #[must_use]
+    pub fn api_url<'a>(&self, api: &'a ApiSettings) -> &'a str {
+        match self {
+            Animal::Cat => &api.cat_url,
+            Animal::Dog => &api.dog_url,
+            Animal::Bird => &api.bird_url,
+            Animal::Configured(ConfiguredAnimal(name)) => api
+                .animals
+                .get(*name)
+                .map_or("", |animal| animal.url.as_str()),
+        }
+    }
+}
+
+/// Implements type conversion from a string literal to an `Animal` enum.
+impl TryFrom<&str> for Animal {
+    type Error = ErrorKind;
+
+    fn try_from(animal_param: &str) -> Result<Self, Self::Error> {
+        match animal_param.to_lowercase().as_str() {
+            "cat" | "kitty" | "kitten" | "feline" => Ok(Self::Cat),
+            "dog" | "puppy" | "doggy" | "canine" => Ok(Self::Dog),
+            "bird" | "birdie" | "avian" => Ok(Self::Bird),
+            other => Err(ErrorKind::ConvertToAnimal(other.to_string())),
+        }
+    }
+}
+
+/// Sends one GET request to an upstream API with the headers configured for its host, in a span of
+/// its own recording the response status and how long the upstream took.
+#[tracing::instrument(
+    name = "Upstream HTTP request",
+    skip(client, headers),
+    fields(status = tracing::field::Empty, elapsed_ms = tracing::field::Empty)
+)]
+async fn send_request(
+    client: &Client,
+    headers: &UpstreamHeaders,
+    url: &str,
+) -> reqwest::Result<reqwest::Response> {
+    let started = Instant::now();
+    let mut req = client.get(url);
+    if let Some(headers) = headers.for_url(url) {
+        req = req.headers(headers.clone());
+    }
+    let res = req.send().await;
+    let span = tracing::Span::current();
+    span.record(
+        "elapsed_ms",
+        u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+    );
+    if let Ok(res) = &res {
+        span.record("status", res.status().as_u16());
+    }
+    res
+}
+
+/// Provides a `get_fact` function for an animal API return struct.
+trait GetFact {
+    /// Fetches a fact, retrying connection errors, 5xx and 429 responses with exponential backoff,
+    /// or after the `Retry-After` of a 429 when given.
+    ///
+    /// Each attempt holds one of the shared upstream `permits` until its response is read, so
+    /// calls wait for a permit once the upstream concurrency limit is reached.
+    #[tracing::instrument(
+        name = "Calling animal API",
+        skip(client, headers, permits, retry),
+        fields(attempts = tracing::field::Empty)
+    )]
+    async fn get_fact(
+        client: &Client,
+        headers: &UpstreamHeaders,
+        permits: &Semaphore,
+        url: &str,
+        retry: &RetrySettings,
+    ) -> Result<Self, ErrorKind>
+    where
+        Self: for<'de> de::Deserialize<'de> + Sized,
+    {
+        let mut attempt = 0;
+        let (res, _permit) = loop {
+            attempt += 1;
+            let can_retry = attempt <= retry.max_retries;
+            let permit = permits.acquire().await.expect("Upstream semaphore closed");
+            let delay = match send_request(client, headers, url).await {
+                Ok(res) if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS && can_retry => {
+                    let delay = retry_after(res.headers(), retry)
+                        .unwrap_or_else(|| backoff_delay(retry, attempt));
+                    tracing::warn!("Animal API rate limited us, retrying in {delay:?}");
+                    delay
+                }
+                Ok(res) if res.status().is_server_error() && can_retry => {
+                    tracing::warn!("Animal API returned {}, retrying", res.status());
+                    backoff_delay(retry, attempt)
+                }
+                Err(err) if err.is_connect() && can_retry => {
+                    tracing::warn!("Connection to animal API failed, retrying: {err}");
+                    backoff_delay(retry, attempt)
+                }
+                res => break (res, permit),
+            };
+            drop(permit);
+            tokio::time::sleep(delay).await;
+        };
+        tracing::Span::current().record("attempts", attempt);
+
+        let res = res.map_err(|err| ErrorKind::from_reqwest(&err, ErrorKind::ApiRequest))?;
+        // check status first
+        let status = res.status();
+        if !status.is_success() {
+            Err(ErrorKind::ApiResponse(status.as_u16()))?;
+        }
+        let text = res
+            .text()
+            .await
+            .map_err(|err| ErrorKind::from_reqwest(&err, ErrorKind::ToText))?;
+        serde_json::from_str(&text).map_err(|err| {
+            tracing::error!(
+                "Animal API returned a body that isn't a fact: {}",
+                truncate(&text, MAX_LOGGED_BODY_LEN)
+            );
+            ErrorKind::UpstreamContract(err.to_string())
+        })
+    }
+
+    /// Fetches a fact unless the upstream's circuit is open, recording the outcome with its
+    /// circuit breaker and the client and, on success, how long it took.
+    async fn get_fact_guarded(
+        client: &UpstreamClient,
+        headers: &UpstreamHeaders,
+        permits: &Semaphore,
+        url: &str,
+        retry: &RetrySettings,
+        breakers: &CircuitBreakers,
+        latencies: &UpstreamLatencies,
+    ) -> Result<Self, ErrorKind>
+    where
+        Self: for<'de> de::Deserialize<'de> + Sized,
+    {
+        let breaker = breakers.get(url);
+        let _permit = match breaker.try_acquire() {
+            Ok(permit) => permit,
+            Err(wait) => return Err(ErrorKind::CircuitOpen(wait.as_secs().max(1))),
+        };
+        let started = Instant::now();
+        let res = Self::get_fact(&client.current(), headers, permits, url, retry).await;
+        client.record(url, &res);
+        match &res {
+            Err(err) if err.is_upstream_failure() => {
+                breaker.record_failure();
+                latencies.record_failure(url);
+            }
+            Err(_) => breaker.record_success(),
+            Ok(_) => {
+                breaker.record_success();
+                latencies.record(url, started.elapsed());
+            }
+        }
+        res
+    }
+
+    /// Fetches a fact from each URL in order until one succeeds, returning it with the URL that
+    /// served it, or the last error if they all fail.
+    async fn get_fact_from_any<'a>(
+        client: &UpstreamClient,
+        headers: &UpstreamHeaders,
+        permits: &Semaphore,
+        urls: &[&'a str],
+        retry: &RetrySettings,
+        breakers: &CircuitBreakers,
+        latencies: &UpstreamLatencies,
+    ) -> Result<(Self, &'a str), ErrorKind>
+    where
+        Self: for<'de> de::Deserialize<'de> + Sized,
+    {
+        let mut last_err = ErrorKind::ApiRequest("No animal API URLs configured".into());
+        for url in urls {
+            let res =
+                Self::get_fact_guarded(client, headers, permits, url, retry, breakers, latencies)
+                    .await;
+            match res {
+                Ok(res) => {
+                    tracing::info!("Fact served by animal API: {url}");
+                    return Ok((res, url));
+                }
+                Err(err) => {
+                    tracing::warn!("Animal API {url} failed: {err}");
+                    last_err = err;
+                }
+            }
+        }
+        Err(last_err)
+    }
+}
+
+/// Returns the delay before the next attempt: the base delay doubled for each previous attempt,
+/// plus a random jitter of up to one base delay.
+fn backoff_delay(retry: &RetrySettings, attempt: u32) -> Duration {
+    let base = retry.base_delay_ms;
+    let jitter = rand::thread_rng().gen_range(0..=base);
+    Duration::from_millis(base.saturating_mul(1 << (attempt - 1).min(16)) + jitter)
+}
+
+/// Reads how long a 429 response asks us to wait, given in seconds or as an HTTP date, capped at
+/// `max_retry_after_ms`.
+fn retry_after(headers: &reqwest::header::HeaderMap, retry: &RetrySettings) -> Option<Duration> {
+    let value = headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    let delay = if let Ok(secs) = value.parse() {
+        Duration::from_secs(secs)
+    } else {
+        let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+        (at.to_utc() - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default()
+    };
+    Some(delay.min(Duration::from_millis(retry.max_retry_after_ms)))
+}
+
+/// Orders the facts of one upstream response so that the selected fact comes first.
+fn order_facts(facts: &mut [String], selection: Selection, rng: &mut StdRng) {
+    match selection {
+        Selection::First => {}
+        Selection::Random => facts.shuffle(rng),
+        Selection::Longest => facts.sort_by_key(|fact| Reverse(fact.chars().count())),
+    }
+}
+
+/// The cat API return type.
+#[derive(serde::Deserialize)]
+#[serde(from = "CatResponse")]
+pub struct Cat {
+    text: String,
+    id: Option<String>,
+}
+
+/// The response shapes accepted from cat APIs.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum CatResponse {
+    /// `{ "_id": "...", "text": "..." }`, as returned by cat-fact.herokuapp.com.
+    Text {
+        text: String,
+        #[serde(rename = "_id", default)]
+        id: Option<String>,
+    },
+    /// `{ "fact": "...", "length": 42 }`, as returned by catfact.ninja.
+    Fact { fact: String },
+}
+
+impl From<CatResponse> for Cat {
+    fn from(res: CatResponse) -> Self {
+        match res {
+            CatResponse::Text { text, id } => Self { text, id },
+            CatResponse::Fact { fact } => Self {
+                text: fact,
+                id: None,
+            },
+        }
+    }
+}
+
+impl GetFact for Cat {}
+
+/// The dog API return type.
+#[derive(serde::Deserialize)]
+#[serde(from = "DogResponse")]
+pub struct Dog {
+    facts: Vec<String>,
+}
+
+/// The response shapes accepted from dog APIs.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum DogResponse {
+    /// `{ "facts": ["..."] }`, as returned by dog-api.kinduff.com.
+    Facts { facts: Vec<String> },
+    /// `{ "data": [{ "attributes": { "body": "..." } }] }`, as returned by dogapi.dog.
+    Data { data: Vec<DogData> },
+}
+
+#[derive(serde::Deserialize)]
+struct DogData {
+    attributes: DogAttributes,
+}
+
+#[derive(serde::Deserialize)]
+struct DogAttributes {
+    body: String,
+}
+
+impl From<DogResponse> for Dog {
+    fn from(res: DogResponse) -> Self {
+        let facts = match res {
+            DogResponse::Facts { facts } => facts,
+            DogResponse::Data { data } => data.into_iter().map(|d| d.attributes.body).collect(),
+        };
+        Self { facts }
+    }
+}
+
+impl GetFact for Dog {}
+
+/// The bird API return type.
+#[derive(serde::Deserialize)]
+pub struct Bird {
+    fact: String,
+}
+
+impl GetFact for Bird {}
+
+/// Configured animals' APIs return any JSON, their fact being found with a JSON pointer.
+impl GetFact for Value {}
+
+/// The Handler error types.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum ErrorKind {
+    #[error("{0}")]
+    Validation(#[source] ValidationErrors),
+
+    #[error("Error during Request to animal API: {0}")]
+    ApiRequest(String),
+
+    #[error("Response from animal API returned error code: {0}")]
+    ApiResponse(u16),
+
+    #[error("Error fetching text: {0}")]
+    ToText(String),
+
+    #[error("The animal API returned an unexpected response: {0}")]
+    UpstreamContract(String),
+
+    #[error("'{0}' is not a supported animal.")]
+    ConvertToAnimal(String),
+
+    #[error("Request to animal API timed out")]
+    Timeout,
+
+    #[error("Failed to connect to animal API: {0}")]
+    Connect(String),
+
+    #[error("No {0} fact matching the request was found.")]
+    NoMatchingFact(String),
+
+    #[error("Invalid request body: {0}")]
+    InvalidBody(String),
+
+    #[error("Only application/json and text/plain responses are available.")]
+    NotAcceptable,
+
+    #[error("The animal API is temporarily unavailable, retry in {0}s.")]
+    CircuitOpen(u64),
+
+    #[error("At most {0} animals may be requested at once.")]
+    TooManyAnimals(usize),
+
+    #[error("'{0}' is not a log level; use trace, debug, info, warn, error or off.")]
+    InvalidLogLevel(String),
+
+    #[error("The log level can't be changed while the service is running.")]
+    LogLevelUnavailable,
+
+    #[error("A request with this idempotency key is still in progress, retry later.")]
+    IdempotencyKeyInUse,
+
+    #[error("The idempotency key was already used for a different request.")]
+    IdempotencyKeyReused,
+}
+
+impl ErrorKind {
+    /// Maps a reqwest error to `Connect` if the upstream couldn't be connected to, including in
+    /// time, to `Timeout` if it timed out afterwards, otherwise to the given variant.
+    fn from_reqwest(err: &reqwest::Error, other: fn(String) -> Self) -> Self {
+        if err.is_connect() {
+            Self::Connect(err.to_string())
+        } else if err.is_timeout() {
+            Self::Timeout
+        } else {
+            other(err.to_string())
+        }
+    }
+
+    /// Whether the error means the upstream is failing, as opposed to the request or the
+    /// upstream's response being unusable.
+    fn is_upstream_failure(&self) -> bool {
+        match self {
+            Self::ApiRequest(_) | Self::ToText(_) | Self::Timeout | Self::Connect(_) => true,
+            Self::ApiResponse(code) => *code == 429 || *code >= 500,
+            _ => false,
+        }
+    }
+
+    /// The message sent to clients at the given detail level: the full message, or a generic
+    /// one for errors whose messages can leak upstream details.
+    #[must_use]
+    pub fn client_message(&self, detail: ErrorDetail) -> String {
+        match (detail, self.generic_message()) {
+            (ErrorDetail::Generic, Some(generic)) => generic.to_string(),
+            _ => self.to_string(),
+        }
+    }
+
+    /// The message sent in place of this error's own when that can leak upstream details.
+    fn generic_message(&self) -> Option<&'static str> {
+        match self {
+            Self::ApiRequest(_) => Some("The animal API could not be reached."),
+            Self::Connect(_) => Some("The animal API could not be connected to."),
+            Self::ApiResponse(_) => Some("The animal API returned an error."),
+            Self::ToText(_) => Some("The animal API response could not be read."),
+            Self::UpstreamContract(_) => Some("The animal API returned an unexpected response."),
+            _ => None,
+        }
+    }
+
+    /// A stable, machine-readable code identifying the error.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Validation(_) => "validation_failed",
+            Self::ApiRequest(_) => "upstream_unavailable",
+            Self::ApiResponse(_) => "upstream_error",
+            Self::ToText(_) => "upstream_read_failed",
+            Self::UpstreamContract(_) => "upstream_invalid_response",
+            Self::ConvertToAnimal(_) => "unsupported_animal",
+            Self::Timeout => "upstream_timeout",
+            Self::Connect(_) => "upstream_connect_failed",
+            Self::NoMatchingFact(_) => "no_matching_fact",
+            Self::InvalidBody(_) => "invalid_body",
+            Self::NotAcceptable => "not_acceptable",
+            Self::CircuitOpen(_) => "circuit_open",
+            Self::TooManyAnimals(_) => "too_many_animals",
+            Self::InvalidLogLevel(_) => "invalid_log_level",
+            Self::LogLevelUnavailable => "log_level_unavailable",
+            Self::IdempotencyKeyInUse => "idempotency_key_in_use",
+            Self::IdempotencyKeyReused => "idempotency_key_reused",
+        }
+    }
+
+    /// The HTTP status code returned to the client for this error.
+    ///
+    /// Upstream 404, 429 and 503 responses are passed on as is, other upstream server errors,
+    /// successful responses that aren't a fact and failures to connect become a 502 and any other
+    /// upstream status a 500.
+    #[must_use]
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ApiResponse(code @ (404 | 429 | 503)) => {
+                StatusCode::from_u16(*code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            Self::ApiResponse(500..=599) | Self::UpstreamContract(_) | Self::Connect(_) => {
+                StatusCode::BAD_GATEWAY
+            }
+            Self::Validation(_)
+            | Self::ConvertToAnimal(_)
+            | Self::InvalidBody(_)
+            | Self::TooManyAnimals(_)
+            | Self::InvalidLogLevel(_) => StatusCode::BAD_REQUEST,
+            Self::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            Self::NoMatchingFact(_) => StatusCode::NOT_FOUND,
+            Self::NotAcceptable => StatusCode::NOT_ACCEPTABLE,
+            Self::IdempotencyKeyInUse => StatusCode::CONFLICT,
+            Self::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::CircuitOpen(_) | Self::LogLevelUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Self::ApiRequest(_) | Self::ApiResponse(_) | Self::ToText(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashSet};
+    use std::time::{Duration, Instant};
+
+    use axum::http::StatusCode;
+    use enum_iterator::all;
+    use rand::{rngs::StdRng, SeedableRng};
+    use reqwest::header::{self, HeaderMap, HeaderValue};
+    use reqwest::Client;
+    use serde_json::Value;
+    use tokio::sync::Semaphore;
+    use tracing_subscriber::fmt::format::FmtSpan;
+    use validator::ValidationErrors;
+    use wiremock::matchers::{any, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::GetFact;
+    use super::{
+        normalize_animal, order_facts, retry_after, split_langs, validate_langs, Animal,
+        AnimalWeights, Bird, Cat, Dog, ErrorKind,
+    };
+    use crate::circuit_breaker::CircuitBreakers;
+    use crate::config::{ApiSettings, ErrorDetail, RetrySettings, Selection};
+    use crate::latency::UpstreamLatencies;
+    use crate::upstream_client::UpstreamClient;
+    use crate::upstream_headers::UpstreamHeaders;
+    use crate::util::testing::LogBuffer;
+
+    /// An upstream client built from the default API settings.
+    fn upstream_client() -> UpstreamClient {
+        UpstreamClient::new(&ApiSettings::default()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cat_get_fact() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .and(path("/facts/random"))
+            .and(query_param("animal_type", "cat"))
+            .and(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw(r#"{"text": "fact"}"#, "application/json"),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let res = Cat::get_fact(
+            &Client::new(),
+            &UpstreamHeaders::default(),
+            &Semaphore::new(1),
+            &format!("{}/{}", mock_server.uri(), "facts/random?animal_type=cat"),
+            &RetrySettings::default(),
+        )
+        .await
+        .expect("Failed to get cat fact.");
+
+        assert!(!res.text.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dog_get_fact() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .and(path("/api/facts"))
+            .and(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(r#"{"facts": ["fact"]}"#, "application/json"),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let res = Dog::get_fact(
+            &Client::new(),
+            &UpstreamHeaders::default(),
+            &Semaphore::new(1),
+            &format!("{}/{}", mock_server.uri(), "api/facts"),
+            &RetrySettings::default(),
+        )
+        .await
+        .expect("Failed to get dog fact.");
+
+        assert!(!res.facts.first().expect("").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_bird_get_fact() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .and(path("/animal/bird"))
+            .and(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw(r#"{"fact": "fact"}"#, "application/json"),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let res = Bird::get_fact(
+            &Client::new(),
+            &UpstreamHeaders::default(),
+            &Semaphore::new(1),
+            &format!("{}/{}", mock_server.uri(), "animal/bird"),
+            &RetrySettings::default(),
+        )
+        .await
+        .expect("Failed to get bird fact.");
+
+        assert!(!res.fact.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_fact_traces_upstream_call_in_child_span() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .and(path("/animal/bird"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw(r#"{"fact": "fact"}"#, "application/json"),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let logs = LogBuffer::default();
+        let sub = tracing_subscriber::fmt()
+            .json()
+            .with_span_events(FmtSpan::CLOSE)
+            .with_span_list(true)
+            .with_writer(logs.clone())
+            .finish();
+        let guard = tracing::subscriber::set_default(sub);
+        Bird::get_fact(
+            &Client::new(),
+            &UpstreamHeaders::default(),
+            &Semaphore::new(1),
+            &format!("{}/{}", mock_server.uri(), "animal/bird"),
+            &RetrySettings::default(),
+        )
+        .await
+        .expect("Failed to get bird fact.");
+        drop(guard);
+
+        let output = logs.contents();
+        let closed = output
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).expect("Log line is not JSON"))
+            .find(|line| line["span"]["name"] == "Upstream HTTP request")
+            .expect("No upstream request span was closed");
+        assert_eq!(200, closed["span"]["status"]);
+        assert!(closed["span"]["elapsed_ms"].is_u64());
+        assert_eq!("Calling animal API", closed["spans"][0]["name"]);
+    }
+
+    #[tokio::test]
+    async fn test_get_fact_retries_server_errors() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .and(path("/api/facts"))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(any())
+            .and(path("/api/facts"))
+            .and(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(r#"{"facts": ["fact"]}"#, "application/json"),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let res = Dog::get_fact(
+            &Client::new(),
+            &UpstreamHeaders::default(),
+            &Semaphore::new(1),
+            &format!("{}/{}", mock_server.uri(), "api/facts"),
+            &RetrySettings {
+                max_retries: 2,
+                base_delay_ms: 1,
+                ..RetrySettings::default()
+            },
+        )
+        .await
+        .expect("Failed to get dog fact.");
+
+        assert_eq!("fact", res.facts.first().expect(""));
+    }
+
+    #[tokio::test]
+    async fn test_get_fact_waits_for_upstream_permit() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .and(path("/api/facts"))
+            .and(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(r#"{"facts": ["fact"]}"#, "application/json")
+                    .set_delay(Duration::from_millis(200)),
+            )
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let client = Client::new();
+        let headers = UpstreamHeaders::default();
+        let permits = Semaphore::new(1);
+        let url = format!("{}/{}", mock_server.uri(), "api/facts");
+        let retry = RetrySettings::default();
+        let started = Instant::now();
+        let results = futures::future::join_all(
+            (0..3).map(|_| Dog::get_fact(&client, &headers, &permits, &url, &retry)),
+        )
+        .await;
+
+        assert!(results.iter().all(Result::is_ok));
+        // with a single permit the three calls run one after another
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(600), "{elapsed:?}");
+        assert_eq!(1, permits.available_permits());
+    }
+
+    #[tokio::test]
+    async fn test_get_fact_honours_retry_after_on_429() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .and(path("/api/facts"))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(any())
+            .and(path("/api/facts"))
+            .and(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(r#"{"facts": ["fact"]}"#, "application/json"),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let started = Instant::now();
+        let res = Dog::get_fact(
+            &Client::new(),
+            &UpstreamHeaders::default(),
+            &Semaphore::new(1),
+            &format!("{}/{}", mock_server.uri(), "api/facts"),
+            &RetrySettings {
+                max_retries: 1,
+                base_delay_ms: 1,
+                ..RetrySettings::default()
+            },
+        )
+        .await
+        .expect("Failed to get dog fact.");
+
+        assert_eq!("fact", res.facts.first().expect(""));
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(950), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(3), "{elapsed:?}");
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let retry = RetrySettings {
+            max_retry_after_ms: 5_000,
+            ..RetrySettings::default()
+        };
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+            headers
+        };
+
+        assert_eq!(None, retry_after(&HeaderMap::new(), &retry));
+        assert_eq!(
+            Some(Duration::from_secs(2)),
+            retry_after(&headers("2"), &retry)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(5)),
+            retry_after(&headers("60"), &retry)
+        );
+        let past = "Sun, 06 Nov 1994 08:49:37 GMT";
+        assert_eq!(Some(Duration::ZERO), retry_after(&headers(past), &retry));
+        let soon = (chrono::Utc::now() + chrono::Duration::seconds(3)).to_rfc2822();
+        let delay = retry_after(&headers(&soon), &retry).unwrap();
+        assert!(delay > Duration::from_secs(1) && delay <= Duration::from_secs(3));
+        assert_eq!(None, retry_after(&headers("soon"), &retry));
+    }
+
+    #[tokio::test]
+    async fn test_dog_get_fact_falls_back_to_next_url() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .and(path("/api/facts"))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(any())
+            .and(path("/api/v2/facts"))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"data": [{"attributes": {"body": "fallback fact"}}]}"#,
+                "application/json",
+            ))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let primary = format!("{}/{}", mock_server.uri(), "api/facts");
+        let fallback = format!("{}/{}", mock_server.uri(), "api/v2/facts");
+        let res = Dog::get_fact_from_any(
+            &upstream_client(),
+            &UpstreamHeaders::default(),
+            &Semaphore::new(1),
+            &[&primary, &fallback],
+            &RetrySettings {
+                max_retries: 0,
+                base_delay_ms: 1,
+                ..RetrySettings::default()
+            },
+            &CircuitBreakers::new(0, Duration::ZERO),
+            &UpstreamLatencies::new(0.3, Duration::from_secs(10)),
+        )
+        .await
+        .expect("Failed to get dog fact.");
+
+        assert_eq!("fallback fact", res.0.facts.first().expect(""));
+        assert_eq!(fallback, res.1);
+    }
+
+    #[tokio::test]
+    async fn test_cat_get_fact_falls_back_to_next_url() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .and(path("/facts/random"))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(any())
+            .and(path("/fact"))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"fact": "fallback cat fact", "length": 17}"#,
+                "application/json",
+            ))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let primary = format!("{}/{}", mock_server.uri(), "facts/random");
+        let fallback = format!("{}/{}", mock_server.uri(), "fact");
+        let res = Cat::get_fact_from_any(
+            &upstream_client(),
+            &UpstreamHeaders::default(),
+            &Semaphore::new(1),
+            &[&primary, &fallback],
+            &RetrySettings {
+                max_retries: 0,
+                base_delay_ms: 1,
+                ..RetrySettings::default()
+            },
+            &CircuitBreakers::new(0, Duration::ZERO),
+            &UpstreamLatencies::new(0.3, Duration::from_secs(10)),
+        )
+        .await
+        .expect("Failed to get cat fact.");
+
+        assert_eq!("fallback cat fact", res.0.text);
+        assert_eq!(fallback, res.1);
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_fails_fast_without_calling_upstream() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .and(path("/api/facts"))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(any())
+            .and(path("/api/facts"))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/{}", mock_server.uri(), "api/facts");
+        let retry = RetrySettings {
+            max_retries: 0,
+            base_delay_ms: 1,
+            ..RetrySettings::default()
+        };
+        let breakers = CircuitBreakers::new(2, Duration::from_secs(30));
+        let latencies = UpstreamLatencies::new(0.3, Duration::from_secs(10));
+        for _ in 0..2 {
+            let err = Dog::get_fact_from_any(
+                &upstream_client(),
+                &UpstreamHeaders::default(),
+                &Semaphore::new(1),
+                &[&url],
+                &retry,
+                &breakers,
+                &latencies,
+            )
+            .await
+            .err()
+            .expect("Expected the upstream to fail.");
+            assert_eq!("upstream_error", err.code());
+        }
+
+        let err = Dog::get_fact_from_any(
+            &upstream_client(),
+            &UpstreamHeaders::default(),
+            &Semaphore::new(1),
+            &[&url],
+            &retry,
+            &breakers,
+            &latencies,
+        )
+        .await
+        .err()
+        .expect("Expected the circuit to be open.");
+        assert_eq!("circuit_open", err.code());
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, err.status_code());
+    }
+
+    #[tokio::test]
+    async fn test_client_rebuilt_after_repeated_connect_failures() {
+        // nothing listens on the port once the listener is dropped, so connecting is refused
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api/facts", listener.local_addr().unwrap());
+        drop(listener);
+
+        let client = UpstreamClient::new(&ApiSettings {
+            client_rebuild_threshold: 2,
+            ..ApiSettings::default()
+        })
+        .unwrap();
+        let retry = RetrySettings {
+            max_retries: 0,
+            base_delay_ms: 1,
+            ..RetrySettings::default()
+        };
+        let breakers = CircuitBreakers::new(0, Duration::ZERO);
+        let latencies = UpstreamLatencies::new(0.3, Duration::from_secs(10));
+        for rebuilds in [0, 1] {
+            let err = Dog::get_fact_from_any(
+                &client,
+                &UpstreamHeaders::default(),
+                &Semaphore::new(1),
+                &[&url],
+                &retry,
+                &breakers,
+                &latencies,
+            )
+            .await
+            .err()
+            .expect("Expected connecting to fail.");
+            assert_eq!("upstream_connect_failed", err.code());
+            assert_eq!(rebuilds, client.rebuilds());
+        }
+    }
+
+    #[test]
+    fn test_upstream_statuses_map_to_client_statuses() {
+        for (upstream, expected) in [
+            (404, StatusCode::NOT_FOUND),
+            (429, StatusCode::TOO_MANY_REQUESTS),
+            (503, StatusCode::SERVICE_UNAVAILABLE),
+            (500, StatusCode::BAD_GATEWAY),
+            (400, StatusCode::INTERNAL_SERVER_ERROR),
+        ] {
+            assert_eq!(expected, ErrorKind::ApiResponse(upstream).status_code());
+        }
+    }
+
+    #[test]
+    fn test_client_message() {
+        let err = ErrorKind::ApiResponse(500);
+        assert_eq!(
+            "Response from animal API returned error code: 500",
+            err.client_message(ErrorDetail::Full)
+        );
+        assert_eq!(
+            "The animal API returned an error.",
+            err.client_message(ErrorDetail::Generic)
+        );
+
+        let err = ErrorKind::ConvertToAnimal("dragon".into());
+        assert_eq!(
+            "'dragon' is not a supported animal.",
+            err.client_message(ErrorDetail::Generic)
+        );
+    }
+
+    #[test]
+    fn test_error_codes_are_distinct() {
+        let errors = [
+            ErrorKind::Validation(ValidationErrors::new()),
+            ErrorKind::ApiRequest(String::new()),
+            ErrorKind::ApiResponse(500),
+            ErrorKind::ToText(String::new()),
+            ErrorKind::UpstreamContract(String::new()),
+            ErrorKind::ConvertToAnimal(String::new()),
+            ErrorKind::Timeout,
+            ErrorKind::Connect(String::new()),
+            ErrorKind::NoMatchingFact(String::new()),
+            ErrorKind::InvalidBody(String::new()),
+            ErrorKind::NotAcceptable,
+            ErrorKind::CircuitOpen(1),
+            ErrorKind::TooManyAnimals(1),
+        ];
+
+        let codes: HashSet<&str> = errors.iter().map(ErrorKind::code).collect();
+        assert_eq!(errors.len(), codes.len());
+    }
+
+    #[test]
+    fn test_order_facts() {
+        let facts = ["short", "the longest fact", "mid fact"].map(String::from);
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut ordered = facts.clone();
+        order_facts(&mut ordered, Selection::First, &mut rng);
+        assert_eq!(facts, ordered);
+
+        order_facts(&mut ordered, Selection::Longest, &mut rng);
+        assert_eq!(["the longest fact", "mid fact", "short"], ordered);
+
+        order_facts(&mut ordered, Selection::Random, &mut rng);
+        let mut sorted = ordered.to_vec();
+        sorted.sort();
+        assert_eq!(["mid fact", "short", "the longest fact"], sorted.as_slice());
+    }
+
+    #[test]
+    fn test_animal_aliases() {
+        for (alias, animal) in [
+            ("kitty", Animal::Cat),
+            ("Kitten", Animal::Cat),
+            ("FELINE", Animal::Cat),
+            ("puppy", Animal::Dog),
+            ("Doggy", Animal::Dog),
+            ("canine", Animal::Dog),
+            ("birdie", Animal::Bird),
+            ("Avian", Animal::Bird),
+        ] {
+            assert_eq!(animal, Animal::try_from(alias).expect(alias));
+        }
+    }
+
+    #[test]
+    fn test_animal_weights_favour_heavier_animals() {
+        let animals: Vec<Animal> = all().collect();
+        let weights = BTreeMap::from([("cat".to_string(), 1000.0), ("bird".to_string(), 0.0)]);
+        let mut rng = StdRng::seed_from_u64(7);
+
+        let picks: Vec<Animal> = (0..1000)
+            .map(|_| {
+                AnimalWeights::new(&animals, &weights)
+                    .unwrap()
+                    .choose(&animals, &mut rng)
+                    .unwrap()
+            })
+            .collect();
+        let cats = picks.iter().filter(|a| **a == Animal::Cat).count();
+        assert!(cats > 980, "{cats}");
+        assert!(!picks.contains(&Animal::Bird));
+
+        for (name, weight) in [("cat", -1.0), ("cat", f64::NAN), ("unicorn", 1.0)] {
+            let weights = BTreeMap::from([(name.to_string(), weight)]);
+            assert!(AnimalWeights::new(&animals, &weights).is_err(), "{name}");
+        }
+        let weights = animals
+            .iter()
+            .map(|animal| (animal.as_str().to_string(), 0.0))
+            .collect();
+        assert!(AnimalWeights::new(&animals, &weights).is_err());
+    }
+
+    #[test]
+    fn test_validate_langs() {
+        assert!(validate_langs("es, FR,de,es").is_ok());
+        assert_eq!(vec!["es", "fr", "de"], split_langs("es, FR,de,es"));
+        assert!(validate_langs("es,spanish").is_err());
+        assert!(validate_langs(",").is_err());
+        assert!(validate_langs("es,fr,de,it,pt,nl").is_err());
+    }
+
+    #[test]
+    fn test_normalize_animal() {
+        assert_eq!("cat", normalize_animal("  cat\t"));
+        assert_eq!("kitten", normalize_animal("KiTTeN"));
+        assert_eq!("cat, dog", normalize_animal(" Cat,   DOG "));
+        assert_eq!("écureuil", normalize_animal("ÉCUREUIL"));
+    }
+
+    #[test]
+    fn test_unknown_animal() {
+        assert!(matches!(
+            Animal::try_from("dragon"),
+            Err(ErrorKind::ConvertToAnimal(other)) if other == "dragon"
+        ));
     }
 }
@@ -0,0 +1,28 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde_json::json;
+
+use super::{Animal, Response};
+use crate::state::AppState;
+
+/// The pseudo-animal that picks a random supported animal on each request.
+pub const ANY_ANIMAL: &str = "any";
+
+/// Returns a 200 OK JSON response listing the supported animals.
+#[tracing::instrument(name = "Listing supported animals", skip(state))]
+pub async fn get_animals(State(state): State<AppState>) -> (StatusCode, Response) {
+    let value = json!({
+        "animals": animal_names(&state),
+        "note": format!("'{ANY_ANIMAL}' picks a random supported animal on each request."),
+    });
+    (StatusCode::OK, Json(value))
+}
+
+/// The supported animals, followed by `any`.
+pub(super) fn animal_names(state: &AppState) -> Vec<&'static str> {
+    let mut animals: Vec<&str> = Animal::supported(state)
+        .iter()
+        .map(Animal::as_str)
+        .collect();
+    animals.push(ANY_ANIMAL);
+    animals
+}
@@ -0,0 +1,36 @@
+use axum::{response::Html, Json};
+use utoipa::OpenApi;
+
+use crate::openapi::ApiDoc;
+
+/// The path the `OpenAPI` document is served from.
+pub const OPENAPI_PATH: &str = "/api-docs/openapi.json";
+
+/// Returns the `OpenAPI` document describing the API.
+pub async fn get_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Returns a Swagger UI page rendering the `OpenAPI` document.
+pub async fn get_swagger_ui() -> Html<String> {
+    Html(format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8" />
+  <title>Animal Facts API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.onload = () => {{
+      window.ui = SwaggerUIBundle({{ url: "{OPENAPI_PATH}", dom_id: "#swagger-ui" }});
+    }};
+  </script>
+</body>
+</html>
+"##
+    ))
+}
@@ -0,0 +1,292 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::Duration;
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Utc};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use utoipa::IntoParams;
+use validator::{Validate, ValidationError};
+
+use super::{
+    cache_headers, fact_text, fresh_fact, respond_error, respond_ok, with_envelope, Animal,
+    ErrorKind, FactFilter, Format, Response, ANY_ANIMAL,
+};
+use crate::state::AppState;
+
+/// The format of the `date` query parameter.
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The format of the `Last-Modified` header.
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// The fact of the day query parameters.
+#[derive(serde::Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DailyParam {
+    /// The animal to get the fact of the day about, or `any` for the animal of the day.
+    #[validate(required, length(max = 24))]
+    #[param(required = true, example = "cat")]
+    animal: Option<String>,
+    /// The UTC day to get the fact for, as `YYYY-MM-DD`. Defaults to today.
+    #[validate(custom(function = "validate_date"))]
+    #[param(example = "2024-01-01")]
+    date: Option<String>,
+    /// Whether to wrap the fact in an object with its animal, or return the bare fact. Defaults
+    /// to the configured `facts.envelope`.
+    #[param(example = false)]
+    envelope: Option<bool>,
+}
+
+/// Checks that a date is formatted as `YYYY-MM-DD`.
+fn validate_date(date: &str) -> Result<(), ValidationError> {
+    NaiveDate::parse_from_str(date, DATE_FORMAT)
+        .map(|_| ())
+        .map_err(|_| ValidationError::new("date").with_message("must be a YYYY-MM-DD date".into()))
+}
+
+/// Returns the fact of the day, which is the same for every request on a given UTC day.
+#[utoipa::path(
+    get,
+    context_path = "/v1",
+    path = "/fact/daily",
+    tag = "facts",
+    params(DailyParam),
+    responses(
+        (status = 200, description = "The fact of the day", body = FactResponse),
+        (status = 304, description = "The fact matches the `If-None-Match` ETag, or hasn't changed since `If-Modified-Since`"),
+        (status = 400, description = "Invalid or unsupported animal, or invalid date", body = ErrorResponse),
+        (status = 500, description = "The upstream animal API failed", body = ErrorResponse),
+        (status = 504, description = "The upstream animal API timed out", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(name = "Fetching the fact of the day", skip(state, param))]
+pub async fn get_daily_fact(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    param: Query<DailyParam>,
+) -> axum::response::Response {
+    if let Err(err) = param.0.validate() {
+        return respond_error(&ErrorKind::Validation(err), state.config.errors.detail)
+            .into_response();
+    }
+    let Query(DailyParam {
+        animal,
+        date,
+        envelope,
+    }) = param;
+    let animal = animal.unwrap(); // will always be Some(v) by this point
+    let date = date
+        .and_then(|date| NaiveDate::parse_from_str(&date, DATE_FORMAT).ok())
+        .unwrap_or_else(|| Utc::now().date_naive());
+
+    let a = match daily_animal(&state, &animal, date) {
+        Ok(a) => a,
+        Err(err) => return respond_error(&err, state.config.errors.detail).into_response(),
+    };
+
+    let res = match daily_fact(&state, &a, date).await {
+        Ok(fact) => respond_ok(&fact, a.as_str(), None, None),
+        Err(err) => respond_error(&err, state.config.errors.detail),
+    };
+    let res = with_envelope(res, envelope.unwrap_or(state.config.facts.envelope));
+    state.metrics.record_fact(a.as_str(), res.0.is_success());
+    if !res.0.is_success() {
+        return Format::Json.render(res, fact_text);
+    }
+
+    let now = Utc::now();
+    let etag = etag(&res.1);
+    let last_modified = last_modified(date, now);
+    let mut headers_out = cache_headers(&state, max_age(date, now), false);
+    headers_out.insert(header::ETAG, etag.clone());
+    headers_out.insert(header::LAST_MODIFIED, http_date(last_modified));
+    // If-Modified-Since is only a fallback for clients that don't send an ETag back
+    let not_modified = if headers.contains_key(header::IF_NONE_MATCH) {
+        if_none_match(&headers, &etag)
+    } else {
+        if_modified_since(&headers, last_modified).is_some_and(|modified| !modified)
+    };
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, headers_out).into_response();
+    }
+    (res.0, headers_out, res.1).into_response()
+}
+
+/// Returns when the fact of the day last changed: the start of its UTC day, or `now` for a day
+/// yet to come.
+fn last_modified(date: NaiveDate, now: DateTime<Utc>) -> DateTime<Utc> {
+    date.and_time(NaiveTime::MIN).and_utc().min(now)
+}
+
+/// Formats a time as an HTTP date, e.g. `Mon, 01 Jan 2024 00:00:00 GMT`.
+fn http_date(time: DateTime<Utc>) -> HeaderValue {
+    HeaderValue::from_str(&time.format(HTTP_DATE_FORMAT).to_string())
+        .expect("HTTP date is a valid header value")
+}
+
+/// Checks whether the fact changed since the request's `If-Modified-Since` date, or returns
+/// `None` when there is no valid date to compare with.
+fn if_modified_since(headers: &HeaderMap, last_modified: DateTime<Utc>) -> Option<bool> {
+    let since = headers.get(header::IF_MODIFIED_SINCE)?.to_str().ok()?;
+    let since = DateTime::parse_from_rfc2822(since).ok()?;
+    Some(last_modified.timestamp() > since.timestamp())
+}
+
+/// Returns how long the fact of `date` can be cached at `now`: until the end of its day, but no
+/// later than the end of today, after which it may be evicted. `None` for dates other than today
+/// and the days either side, whose facts are evicted as soon as another day's is stored.
+fn max_age(date: NaiveDate, now: DateTime<Utc>) -> Option<Duration> {
+    let today = now.date_naive();
+    if (date - today).num_days().abs() > 1 {
+        return None;
+    }
+    Some(until_end_of_day(date, now).min(until_end_of_day(today, now)))
+}
+
+/// Returns how long is left of the UTC day `date` at `now`, for which its fact stays the same.
+fn until_end_of_day(date: NaiveDate, now: DateTime<Utc>) -> Duration {
+    let end = date.and_time(NaiveTime::MIN).and_utc() + chrono::Duration::days(1);
+    (end - now).to_std().unwrap_or_default()
+}
+
+/// Computes a weak `ETag` from a hash of the response body.
+fn etag(body: &Response) -> HeaderValue {
+    let mut hasher = DefaultHasher::new();
+    body.0.to_string().hash(&mut hasher);
+    HeaderValue::from_str(&format!("W/\"{:016x}\"", hasher.finish()))
+        .expect("ETag is a valid header value")
+}
+
+/// Checks whether the request's `If-None-Match` header matches the `ETag`, using weak comparison.
+fn if_none_match(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let strip_weak = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let Ok(etag) = etag.to_str() else {
+        return false;
+    };
+    let etag = strip_weak(etag);
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || strip_weak(tag) == etag)
+}
+
+/// Resolves an animal name or alias, or `any` to the animal of the day.
+pub fn daily_animal(state: &AppState, animal: &str, date: NaiveDate) -> Result<Animal, ErrorKind> {
+    if animal.eq_ignore_ascii_case(ANY_ANIMAL) {
+        Ok(animal_of_the_day(&Animal::supported(state), date))
+    } else {
+        Animal::lookup(state, animal)
+    }
+}
+
+/// Picks one of the animals for `any`, seeded by the date so it is stable for the day.
+fn animal_of_the_day(animals: &[Animal], date: NaiveDate) -> Animal {
+    let mut rng = StdRng::seed_from_u64(u64::from(date.num_days_from_ce().unsigned_abs()));
+    animals.choose(&mut rng).cloned().unwrap_or(Animal::Dog)
+}
+
+/// Returns the stored fact of the day for the animal, fetching and storing one that passes the
+/// configured filters if there is none.
+pub async fn daily_fact(
+    state: &AppState,
+    animal: &Animal,
+    date: NaiveDate,
+) -> Result<String, ErrorKind> {
+    if let Some(fact) = state.cache.get_daily(date, animal.as_str()).await {
+        return Ok(fact);
+    }
+    let filter = FactFilter::new(state, None, None);
+    let fact = fresh_fact(state, animal, &filter, &mut StdRng::from_entropy()).await?;
+    Ok(state
+        .cache
+        .insert_daily(date, animal.as_str(), fact.text)
+        .await)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use enum_iterator::all;
+
+    use axum::http::{header, HeaderMap, HeaderValue};
+
+    use super::{
+        animal_of_the_day, http_date, if_modified_since, if_none_match, last_modified, max_age,
+        until_end_of_day,
+    };
+    use crate::handlers::Animal;
+
+    #[test]
+    fn test_animal_of_the_day_is_stable() {
+        let animals: Vec<Animal> = all().collect();
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        assert_eq!(
+            animal_of_the_day(&animals, date),
+            animal_of_the_day(&animals, date)
+        );
+    }
+
+    #[test]
+    fn test_until_end_of_day() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 23, 0, 0).unwrap();
+        assert_eq!(Duration::from_hours(1), until_end_of_day(date, now));
+        let now = Utc.with_ymd_and_hms(2024, 1, 2, 1, 0, 0).unwrap();
+        assert_eq!(Duration::ZERO, until_end_of_day(date, now));
+    }
+
+    #[test]
+    fn test_max_age_is_capped_at_the_end_of_today() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 23, 0, 0).unwrap();
+
+        let today = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        assert_eq!(Some(Duration::from_hours(1)), max_age(today, now));
+        let tomorrow = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        assert_eq!(Some(Duration::from_hours(1)), max_age(tomorrow, now));
+        let yesterday = NaiveDate::from_ymd_opt(2023, 12, 31).unwrap();
+        assert_eq!(Some(Duration::ZERO), max_age(yesterday, now));
+        let next_year = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        assert_eq!(None, max_age(next_year, now));
+    }
+
+    #[test]
+    fn test_if_modified_since() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let modified = last_modified(date, now);
+        assert_eq!("Mon, 01 Jan 2024 00:00:00 GMT", http_date(modified));
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(None, if_modified_since(&headers, modified));
+        headers.insert(header::IF_MODIFIED_SINCE, http_date(modified));
+        assert_eq!(Some(false), if_modified_since(&headers, modified));
+        let yesterday = Utc.with_ymd_and_hms(2023, 12, 31, 0, 0, 0).unwrap();
+        headers.insert(header::IF_MODIFIED_SINCE, http_date(yesterday));
+        assert_eq!(Some(true), if_modified_since(&headers, modified));
+    }
+
+    #[test]
+    fn test_if_none_match_uses_weak_comparison() {
+        let etag = HeaderValue::from_static("W/\"abc\"");
+        let mut headers = HeaderMap::new();
+        assert!(!if_none_match(&headers, &etag));
+
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_static("\"xyz\", \"abc\""),
+        );
+        assert!(if_none_match(&headers, &etag));
+
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("W/\"xyz\""));
+        assert!(!if_none_match(&headers, &etag));
+    }
+}
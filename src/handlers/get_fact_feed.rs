@@ -0,0 +1,200 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use rand::{rngs::StdRng, SeedableRng};
+use sha2::{Digest, Sha256};
+use utoipa::IntoParams;
+use validator::Validate;
+
+use super::{
+    fact_text, filtered_facts, resolve_animal, respond_error, ErrorKind, Fact, FactFilter, Format,
+    Source,
+};
+use crate::history::FeedDates;
+use crate::state::AppState;
+
+/// The media type of an RSS document.
+const RSS_XML: &str = "application/rss+xml; charset=utf-8";
+
+/// The fact feed query parameters.
+#[derive(serde::Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FeedParam {
+    /// The animal to list facts about, or `any` for a random one.
+    #[validate(required, length(max = 24))]
+    #[param(required = true, example = "cat")]
+    animal: Option<String>,
+    /// How many facts to list, from 1 to 10. Defaults to the configured number.
+    #[validate(range(min = 1, max = 10))]
+    #[param(example = 5)]
+    items: Option<u8>,
+}
+
+/// Returns an RSS 2.0 feed of the latest facts about the animal, as many as configured.
+#[utoipa::path(
+    get,
+    context_path = "/v1",
+    path = "/fact/feed",
+    tag = "facts",
+    params(FeedParam),
+    responses(
+        (status = 200, description = "An RSS 2.0 feed with a fact per item",
+            content_type = "application/rss+xml"),
+        (status = 400, description = "Invalid or unsupported animal, or number of items", body = ErrorResponse),
+        (status = 502, description = "The upstream animal API couldn't be connected to, or returned a server error or a response that isn't a fact", body = ErrorResponse),
+        (status = 504, description = "The upstream animal API timed out", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(name = "Fetching an animal fact feed", skip(state, param))]
+pub async fn get_fact_feed(
+    State(state): State<AppState>,
+    param: Query<FeedParam>,
+) -> axum::response::Response {
+    if let Err(err) = param.0.validate() {
+        return respond_error(&ErrorKind::Validation(err), state.config.errors.detail)
+            .into_response();
+    }
+    let animal = param.0.animal.unwrap(); // will always be Some(v) by this point
+    let mut rng = StdRng::from_entropy();
+    let a = match resolve_animal(&state, &animal, &mut rng) {
+        Ok(a) => a,
+        Err(err) => return respond_error(&err, state.config.errors.detail).into_response(),
+    };
+    let count = param
+        .0
+        .items
+        .unwrap_or(state.config.feed.items)
+        .clamp(1, 10);
+    let filter = FactFilter::new(&state, None, None);
+    let facts = match filtered_facts(&state, &a, count, &filter, &mut rng).await {
+        Ok(facts) => facts,
+        Err(err) => {
+            return Format::Json.render(respond_error(&err, state.config.errors.detail), fact_text)
+        }
+    };
+    state.metrics.record_fact(a.as_str(), true);
+
+    let link = format!(
+        "{}/v1/fact/feed?animal={}",
+        state.config.feed.base_url.trim_end_matches('/'),
+        a.as_str()
+    );
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, RSS_XML)],
+        rss(a.as_str(), &link, &facts, &state.feed_dates, Utc::now()),
+    )
+        .into_response()
+}
+
+/// Renders the facts as an RSS 2.0 document, an item per fact, built at `now`. Facts aren't
+/// timestamped upstream, so each is dated when it was first listed.
+fn rss(animal: &str, link: &str, facts: &[Fact], dates: &FeedDates, now: DateTime<Utc>) -> String {
+    let link = escape(link);
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\"><channel>\
+         <title>{animal} facts</title><link>{link}</link>\
+         <description>The latest {animal} facts</description><lastBuildDate>{}</lastBuildDate>",
+        now.to_rfc2822()
+    );
+    for fact in facts {
+        let guid = escape(&guid(animal, fact));
+        let _ = write!(
+            xml,
+            "<item><title>{animal} fact</title><description>{}</description>\
+             <guid isPermaLink=\"false\">{guid}</guid><pubDate>{}</pubDate></item>",
+            escape(&fact.text),
+            dates.first_listed(&guid, now).to_rfc2822(),
+        );
+    }
+    xml.push_str("</channel></rss>");
+    xml
+}
+
+/// The guid of the fact's item: its upstream id when it has one, or else a hash of its text, so
+/// the same fact keeps its guid across builds and restarts.
+fn guid(animal: &str, fact: &Fact) -> String {
+    if let Source::Upstream { id: Some(id), .. } = &fact.source {
+        return format!("{animal}-{id}");
+    }
+    let digest = Sha256::digest(fact.text.as_bytes());
+    digest[..16]
+        .iter()
+        .fold(format!("{animal}-"), |mut guid, byte| {
+            let _ = write!(guid, "{byte:02x}");
+            guid
+        })
+}
+
+/// Escapes the characters with a special meaning in XML text and attributes.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::{escape, guid, rss};
+    use crate::handlers::{Fact, Source};
+    use crate::history::FeedDates;
+
+    fn fact(text: &str, id: Option<&str>) -> Fact {
+        Fact {
+            text: text.into(),
+            source: Source::Upstream {
+                url: "https://cat-fact.herokuapp.com/facts/random".into(),
+                id: id.map(Into::into),
+            },
+            max_age: None,
+        }
+    }
+
+    #[test]
+    fn test_guid_is_the_upstream_id_or_a_stable_hash() {
+        assert_eq!(
+            "cat-591f98",
+            guid("cat", &fact("Cats purr.", Some("591f98")))
+        );
+        assert_eq!(
+            "cat-127d764476c7d1f465033c7925cf234a",
+            guid("cat", &fact("Cats purr.", None))
+        );
+    }
+
+    #[test]
+    fn test_items_keep_the_date_they_were_first_listed() {
+        let dates = FeedDates::new(10);
+        let first = Utc::now();
+        let later = first + Duration::hours(1);
+
+        rss("cat", "", &[fact("Cats purr.", None)], &dates, first);
+        let xml = rss("cat", "", &[fact("Cats purr.", None)], &dates, later);
+        assert!(xml.contains(&format!("<lastBuildDate>{}<", later.to_rfc2822())));
+        assert!(xml.contains(&format!("<pubDate>{}<", first.to_rfc2822())));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            "Cats &amp; dogs &lt;3 &quot;treats&quot; &apos;n&apos; naps &gt; walks",
+            escape(r#"Cats & dogs <3 "treats" 'n' naps > walks"#)
+        );
+    }
+}
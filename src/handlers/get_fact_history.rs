@@ -0,0 +1,32 @@
+use axum::{extract::State, Json};
+use serde_json::json;
+
+use super::Response;
+use crate::state::AppState;
+
+/// Returns the facts most recently served by `/fact` and `/fact/random`, newest first, with
+/// their animal, when they were served and the id of the request they were served to.
+#[utoipa::path(
+    get,
+    context_path = "/v1",
+    path = "/fact/history",
+    tag = "facts",
+    responses((status = 200, description = "The facts most recently served, newest first"))
+)]
+#[tracing::instrument(name = "Listing recently served facts", skip(state))]
+pub async fn get_fact_history(State(state): State<AppState>) -> Response {
+    let facts: Vec<_> = state
+        .history
+        .recent()
+        .into_iter()
+        .map(|served| {
+            json!({
+                "animal": served.animal,
+                "fact": served.fact,
+                "served_at": served.served_at.to_rfc3339(),
+                "request_id": served.request_id,
+            })
+        })
+        .collect();
+    Json(json!({ "facts": facts }))
+}
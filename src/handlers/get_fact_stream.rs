@@ -0,0 +1,184 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::{
+    extract::{Query, State},
+    response::{
+        sse::{Event, KeepAlive},
+        IntoResponse, Sse,
+    },
+};
+use futures::{stream, Stream};
+use rand::{rngs::StdRng, SeedableRng};
+use serde_json::json;
+use tokio::time::{interval, Interval, MissedTickBehavior};
+use utoipa::IntoParams;
+use validator::{Validate, ValidationError};
+
+use super::{fresh_fact, resolve_animal, respond_error, ErrorKind, FactFilter};
+use crate::state::AppState;
+
+/// The interval between streamed facts when none is requested.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The shortest interval a client may request, to spare the upstream APIs.
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The longest interval a client may request.
+const MAX_INTERVAL: Duration = Duration::from_mins(5);
+
+/// The fact stream query parameters.
+#[derive(serde::Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StreamParam {
+    /// The animal to stream facts about, or `any` for a random animal each time.
+    #[validate(required, length(max = 24))]
+    #[param(required = true, example = "cat")]
+    animal: Option<String>,
+    /// The time between facts, in seconds (`5s`) or milliseconds (`1500ms`), from 1s to 300s.
+    /// Defaults to 5s.
+    #[validate(custom(function = "validate_interval"))]
+    #[param(example = "5s")]
+    interval: Option<String>,
+}
+
+/// Parses an interval given in seconds (`5s`) or milliseconds (`1500ms`).
+fn parse_interval(interval: &str) -> Option<Duration> {
+    if let Some(millis) = interval.strip_suffix("ms") {
+        millis.parse().ok().map(Duration::from_millis)
+    } else {
+        let secs = interval.strip_suffix('s')?;
+        secs.parse().ok().map(Duration::from_secs)
+    }
+}
+
+/// Checks that an interval is well formed and within bounds.
+fn validate_interval(interval: &str) -> Result<(), ValidationError> {
+    match parse_interval(interval) {
+        Some(interval) if (MIN_INTERVAL..=MAX_INTERVAL).contains(&interval) => Ok(()),
+        _ => Err(ValidationError::new("interval")
+            .with_message("must be between 1s and 300s, e.g. 5s or 1500ms".into())),
+    }
+}
+
+/// Streams a fact as a server-sent event every interval until the client disconnects.
+///
+/// Each fact is sent as a `fact` event, and a failure to get one as an `error` event, after which
+/// the stream carries on.
+#[utoipa::path(
+    get,
+    context_path = "/v1",
+    path = "/fact/stream",
+    tag = "facts",
+    params(StreamParam),
+    responses(
+        (status = 200, description = "A stream of `fact` and `error` events",
+            content_type = "text/event-stream"),
+        (status = 400, description = "Invalid or unsupported animal, or invalid interval", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(name = "Streaming animal facts", skip(state, param))]
+pub async fn get_fact_stream(
+    State(state): State<AppState>,
+    param: Query<StreamParam>,
+) -> axum::response::Response {
+    if let Err(err) = param.0.validate() {
+        return respond_error(&ErrorKind::Validation(err), state.config.errors.detail)
+            .into_response();
+    }
+    let Query(StreamParam { animal, interval }) = param;
+    let animal = animal.unwrap(); // will always be Some(v) by this point
+    if let Err(err) = resolve_animal(&state, &animal, &mut StdRng::from_entropy()) {
+        return respond_error(&err, state.config.errors.detail).into_response();
+    }
+    let period = interval
+        .as_deref()
+        .and_then(parse_interval)
+        .unwrap_or(DEFAULT_INTERVAL);
+
+    Sse::new(fact_events(state, animal, period))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// The state of one client's stream, logging when the client goes away.
+struct Ticker {
+    state: AppState,
+    animal: String,
+    interval: Interval,
+}
+
+impl Drop for Ticker {
+    fn drop(&mut self) {
+        tracing::info!("Fact stream for {} closed", self.animal);
+    }
+}
+
+/// Produces a fact event every `period`, starting straight away.
+///
+/// Nothing is fetched ahead of the client: once it disconnects the stream, and any fetch in
+/// flight, is dropped.
+fn fact_events(
+    state: AppState,
+    animal: String,
+    period: Duration,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    let mut interval = interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let ticker = Ticker {
+        state,
+        animal,
+        interval,
+    };
+    stream::unfold(ticker, |mut ticker| async move {
+        ticker.interval.tick().await;
+        let event = fact_event(&ticker.state, &ticker.animal).await;
+        Some((Ok(event), ticker))
+    })
+}
+
+/// Fetches a fact for the animal, describing it, or the failure to get one, as an event.
+async fn fact_event(state: &AppState, animal: &str) -> Event {
+    let mut rng = StdRng::from_entropy();
+    let res = match resolve_animal(state, animal, &mut rng) {
+        Ok(a) => {
+            let res = fresh_fact(state, &a, &FactFilter::new(state, None, None), &mut rng).await;
+            state.metrics.record_fact(a.as_str(), res.is_ok());
+            res.map(|fact| json!({ "fact": fact.text, "animal": a.as_str() }))
+        }
+        Err(err) => Err(err),
+    };
+    let (name, data) = match res {
+        Ok(data) => ("fact", data),
+        Err(err) => {
+            tracing::warn!("Failed to get a {animal} fact for the stream: {err}");
+            let message = err.client_message(state.config.errors.detail);
+            let error = json!({ "error": { "code": err.code(), "message": message } });
+            ("error", error)
+        }
+    };
+    Event::default().event(name).data(data.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{parse_interval, validate_interval};
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(Some(Duration::from_secs(5)), parse_interval("5s"));
+        assert_eq!(Some(Duration::from_millis(1500)), parse_interval("1500ms"));
+        assert_eq!(None, parse_interval("5"));
+        assert_eq!(None, parse_interval("fives"));
+    }
+
+    #[test]
+    fn test_interval_bounds() {
+        assert!(validate_interval("1s").is_ok());
+        assert!(validate_interval("300s").is_ok());
+        assert!(validate_interval("999ms").is_err());
+        assert!(validate_interval("301s").is_err());
+    }
+}
@@ -0,0 +1,12 @@
+use axum::{extract::State, http::header, response::IntoResponse};
+use prometheus::TEXT_FORMAT;
+
+use crate::state::AppState;
+
+/// Returns the application metrics in the Prometheus text format.
+pub async fn get_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, TEXT_FORMAT)],
+        state.metrics.render(),
+    )
+}
@@ -0,0 +1,71 @@
+use std::slice;
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::IntoResponse,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use utoipa::IntoParams;
+
+use super::{
+    cache_headers, fact_text, filtered_fact, random_animal, record_history, respond_error,
+    respond_ok, with_envelope, ErrorKind, FactFilter, Format,
+};
+use crate::state::AppState;
+
+/// The random fact query parameters.
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RandomParam {
+    /// Whether to wrap the fact in an object with its animal, or return the bare fact. Defaults
+    /// to the configured `facts.envelope`.
+    #[param(example = false)]
+    envelope: Option<bool>,
+}
+
+/// Returns a fact about a randomly chosen animal.
+#[utoipa::path(
+    get,
+    context_path = "/v1",
+    path = "/fact/random",
+    tag = "facts",
+    params(RandomParam),
+    responses(
+        (status = 200, description = "A fact about a random animal", body = FactResponse,
+            content_type = ["application/json", "text/plain"]),
+        (status = 406, description = "Neither JSON nor plain text is acceptable", body = ErrorResponse),
+        (status = 500, description = "The upstream animal API failed", body = ErrorResponse),
+        (status = 504, description = "The upstream animal API timed out", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(name = "Fetching a random animal fact", skip(state, headers, param))]
+pub async fn get_random_fact(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(param): Query<RandomParam>,
+) -> axum::response::Response {
+    let Some(format) = Format::negotiate(&headers) else {
+        return respond_error(&ErrorKind::NotAcceptable, state.config.errors.detail)
+            .into_response();
+    };
+    let mut rng = StdRng::from_entropy();
+    let a = random_animal(&state, &mut rng);
+    let filter = FactFilter::new(&state, None, None);
+    let res = match filtered_fact(&state, &a, &filter, &mut rng).await {
+        Ok(fact) => {
+            record_history(&state, &headers, a.as_str(), slice::from_ref(&fact.text));
+            let source = fact.source.is_local().then_some(&fact.source);
+            respond_ok(&fact.text, a.as_str(), None, source)
+        }
+        Err(err) => respond_error(&err, state.config.errors.detail),
+    };
+    state.metrics.record_fact(a.as_str(), res.0.is_success());
+    let ok = res.0.is_success();
+    let envelope = param.envelope.unwrap_or(state.config.facts.envelope);
+    let mut res = format.render(with_envelope(res, envelope), fact_text);
+    if ok {
+        res.headers_mut().extend(cache_headers(&state, None, true));
+    }
+    res
+}
@@ -0,0 +1,48 @@
+use axum::{extract::State, Json};
+use serde_json::json;
+
+use super::Response;
+use crate::config::CacheBackend;
+use crate::state::AppState;
+
+/// Returns counters since startup and current gauges for the cache, upstream requests and
+/// circuit breakers, along with each upstream's average latency and how many times the upstream
+/// client was rebuilt.
+#[utoipa::path(
+    get,
+    path = "/stats",
+    responses((status = 200, description = "Cache, upstream and circuit breaker statistics"))
+)]
+pub async fn get_stats(State(state): State<AppState>) -> Response {
+    let snapshot = state.metrics.snapshot();
+    let config = &state.config;
+    let backend = match config.cache.backend {
+        CacheBackend::Memory => "memory",
+        CacheBackend::Redis => "redis",
+    };
+    Json(json!({
+        "uptime_secs": snapshot.uptime_secs,
+        "facts": {
+            "served": snapshot.facts_served,
+            "failed": snapshot.facts_failed,
+            "fallback": snapshot.facts_fallback,
+        },
+        "cache": {
+            "backend": backend,
+            "size": state.cache.size().await,
+            "capacity_per_animal": config.cache.capacity,
+            "hits": snapshot.cache_hits,
+            "stale_hits": snapshot.cache_stale_hits,
+            "misses": snapshot.cache_misses,
+        },
+        "upstream": {
+            "requests": snapshot.upstream_requests,
+            "in_flight": snapshot.upstream_in_flight,
+            "client_rebuilds": state.client.rebuilds(),
+            "pool_max_idle_per_host": config.api.pool_max_idle_per_host,
+            "pool_idle_timeout_secs": config.api.pool_idle_timeout_secs,
+            "latency_ms": state.latencies.averages(),
+        },
+        "circuit_breakers": state.breakers.states(),
+    }))
+}
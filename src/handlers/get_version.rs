@@ -0,0 +1,25 @@
+use axum::Json;
+use chrono::DateTime;
+use serde_json::json;
+
+use super::Response;
+
+/// Returns the version of the running build, its git commit and when it was built.
+#[utoipa::path(
+    get,
+    path = "/version",
+    responses((status = 200, description = "The build's version, git commit and build time"))
+)]
+pub async fn get_version() -> Response {
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "commit": option_env!("GIT_COMMIT").unwrap_or("unknown"),
+        "built_at": built_at(),
+    }))
+}
+
+/// The build time as an RFC 3339 timestamp, if the build script recorded one.
+fn built_at() -> Option<String> {
+    let secs = option_env!("BUILD_TIMESTAMP")?.parse().ok()?;
+    DateTime::from_timestamp(secs, 0).map(|time| time.to_rfc3339())
+}
@@ -0,0 +1,128 @@
+use async_graphql::{
+    http::GraphiQLSource, Context, EmptyMutation, EmptySubscription, ErrorExtensions, Object,
+    Schema, SimpleObject,
+};
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    response::{Html, IntoResponse},
+    Json,
+};
+use once_cell::sync::Lazy;
+use rand::{rngs::StdRng, SeedableRng};
+
+use super::{animal_names, filtered_fact, resolve_animal, respond_error, ErrorKind, FactFilter};
+use crate::config::ErrorDetail;
+use crate::state::AppState;
+
+/// The path the GraphQL API and its `GraphiQL` playground are served on.
+pub const GRAPHQL_PATH: &str = "/graphql";
+
+/// How deeply a query may nest fields. `GraphiQL`'s introspection query needs 14 levels.
+const MAX_QUERY_DEPTH: usize = 16;
+
+/// The total complexity a query may add up to, each field counting for 1 and each fact, which
+/// calls an upstream, for `FACT_COMPLEXITY`. Enough for `GraphiQL`'s introspection query or a
+/// handful of facts.
+const MAX_QUERY_COMPLEXITY: usize = 256;
+
+/// The complexity of a fact, on top of its fields.
+const FACT_COMPLEXITY: usize = 50;
+
+type FactSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// The schema is the same for every request; the app state is passed in with each one.
+#[allow(clippy::non_std_lazy_statics)]
+static SCHEMA: Lazy<FactSchema> = Lazy::new(|| {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .limit_complexity(MAX_QUERY_COMPLEXITY)
+        .finish()
+});
+
+/// A fact about an animal.
+#[derive(SimpleObject)]
+#[graphql(name = "Fact")]
+struct FactObject {
+    fact: String,
+    animal: String,
+    /// Where the fact came from when not from an upstream API: `user` or `fallback`.
+    source: Option<String>,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// A random fact about the animal, or about a random animal if it is `any`.
+    #[graphql(complexity = "FACT_COMPLEXITY + child_complexity")]
+    async fn fact(&self, ctx: &Context<'_>, animal: String) -> async_graphql::Result<FactObject> {
+        let state = ctx.data::<AppState>()?;
+        let mut rng = StdRng::from_entropy();
+        let detail = state.config.errors.detail;
+        let a =
+            resolve_animal(state, &animal, &mut rng).map_err(|err| graphql_error(&err, detail))?;
+        let filter = FactFilter::new(state, None, None);
+        let res = filtered_fact(state, &a, &filter, &mut rng).await;
+        state.metrics.record_fact(a.as_str(), res.is_ok());
+        let fact = res.map_err(|err| graphql_error(&err, detail))?;
+        Ok(FactObject {
+            source: fact.source.local_marker().map(String::from),
+            fact: fact.text,
+            animal: a.as_str().into(),
+        })
+    }
+
+    /// The supported animals, and `any` for a random one.
+    async fn animals(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<&'static str>> {
+        Ok(animal_names(ctx.data::<AppState>()?))
+    }
+}
+
+/// Describes an error as a GraphQL error carrying the same code and message as the REST error
+/// body at the given detail level.
+fn graphql_error(err: &ErrorKind, detail: ErrorDetail) -> async_graphql::Error {
+    tracing::error!("GraphQL error: {err}");
+    async_graphql::Error::new(err.client_message(detail))
+        .extend_with(|_, e| e.set("code", err.code()))
+}
+
+/// Runs a GraphQL query against the fact schema.
+#[tracing::instrument(name = "Running a GraphQL query", skip(state, body))]
+pub async fn post_graphql(
+    State(state): State<AppState>,
+    body: Result<Json<async_graphql::Request>, JsonRejection>,
+) -> axum::response::Response {
+    let Json(req) = match body {
+        Ok(body) => body,
+        Err(rejection) => {
+            return respond_error(
+                &ErrorKind::InvalidBody(rejection.body_text()),
+                state.config.errors.detail,
+            )
+            .into_response()
+        }
+    };
+    Json(SCHEMA.execute(req.data(state)).await).into_response()
+}
+
+/// Serves the `GraphiQL` playground for exploring the GraphQL API.
+pub async fn get_graphiql() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint(GRAPHQL_PATH).finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SCHEMA;
+
+    #[tokio::test]
+    async fn test_schema_rejects_too_complex_queries() {
+        let facts = (0..6)
+            .map(|i| format!("f{i}: fact(animal: \"cat\") {{ fact }}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let res = SCHEMA.execute(format!("{{ {facts} }}")).await;
+
+        assert_eq!("Query is too complex.", res.errors[0].message);
+    }
+}
@@ -1,5 +1,10 @@
 use hyper::StatusCode;
 
+#[utoipa::path(
+    get,
+    path = "/health-check",
+    responses((status = 200, description = "The service is alive"))
+)]
 #[allow(clippy::async_yields_async)]
 #[tracing::instrument(name = "Performing health check")]
 pub async fn health_check() -> StatusCode {
@@ -0,0 +1,83 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::{extract::State, http::StatusCode, Json};
+use futures::future::join_all;
+use serde_json::{json, Map, Value};
+
+use super::{probe, Animal, Response};
+use crate::state::AppState;
+
+/// The last health report, reused until it is older than the configured TTL so frequent polls
+/// don't probe the upstreams each time.
+#[derive(Default)]
+pub struct HealthReports(Mutex<Option<(Instant, StatusCode, Value)>>);
+
+impl HealthReports {
+    fn get(&self, ttl: Duration) -> Option<(StatusCode, Value)> {
+        let report = self.0.lock().unwrap();
+        report
+            .as_ref()
+            .filter(|(at, _, _)| at.elapsed() < ttl)
+            .map(|(_, status, value)| (*status, value.clone()))
+    }
+
+    fn insert(&self, status: StatusCode, value: Value) {
+        *self.0.lock().unwrap() = Some((Instant::now(), status, value));
+    }
+}
+
+/// Checks each component the service depends on and returns 200 when all are healthy, otherwise
+/// 503 with the overall status `degraded`.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses(
+        (status = 200, description = "Every component is healthy"),
+        (status = 503, description = "A component is unhealthy")
+    )
+)]
+#[tracing::instrument(name = "Performing component health check", skip(state))]
+pub async fn healthz(State(state): State<AppState>) -> (StatusCode, Response) {
+    let ttl = Duration::from_secs(state.config.readiness.health_cache_secs);
+    if let Some((status, value)) = state.health_reports.get(ttl) {
+        return (status, Json(value));
+    }
+
+    let timeout = Duration::from_millis(state.config.readiness.timeout_ms);
+    let probes = state.config.readiness.dependencies.iter().map(|name| {
+        let state = &state;
+        async move {
+            let up = match Animal::lookup(state, name) {
+                Ok(animal) => probe(state, animal.api_url(&state.config.api), timeout).await,
+                Err(_) => false,
+            };
+            (format!("{name}_api"), json!(if up { "up" } else { "down" }))
+        }
+    });
+    let mut components: Map<String, Value> = join_all(probes).await.into_iter().collect();
+    // the fact store behaves as if empty when it can't be reached, so requests still succeed,
+    // but every one of them goes upstream
+    let cache = if state.cache.healthy().await {
+        "ok"
+    } else {
+        "down"
+    };
+    components.insert("cache".into(), json!(cache));
+
+    let healthy = components
+        .values()
+        .all(|status| status == "up" || status == "ok");
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        tracing::warn!("Health check degraded: {components:?}");
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let value = json!({
+        "status": if healthy { "ok" } else { "degraded" },
+        "components": components,
+    });
+    state.health_reports.insert(status, value.clone());
+    (status, Json(value))
+}
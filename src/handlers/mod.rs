@@ -1,5 +1,43 @@
+pub use get_all_facts::*;
 pub use get_animal_fact::*;
+pub use get_animals::*;
+pub use get_api_docs::*;
+pub use get_daily_fact::*;
+pub use get_fact_feed::*;
+pub use get_fact_history::*;
+pub use get_fact_stream::*;
+pub use get_metrics::*;
+pub use get_random_fact::*;
+pub use get_stats::*;
+pub use get_version::*;
+pub use graphql::*;
 pub use health_check::*;
+pub use healthz::*;
+pub use negotiate::*;
+pub use post_cache_flush::*;
+pub use post_fact_batch::*;
+pub use post_user_fact::*;
+pub use put_log_level::*;
+pub use readiness_check::*;
 
+mod get_all_facts;
 mod get_animal_fact;
+mod get_animals;
+mod get_api_docs;
+mod get_daily_fact;
+mod get_fact_feed;
+mod get_fact_history;
+mod get_fact_stream;
+mod get_metrics;
+mod get_random_fact;
+mod get_stats;
+mod get_version;
+mod graphql;
 pub mod health_check;
+mod healthz;
+mod negotiate;
+mod post_cache_flush;
+mod post_fact_batch;
+mod post_user_fact;
+mod put_log_level;
+mod readiness_check;
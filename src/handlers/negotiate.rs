@@ -0,0 +1,168 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde_json::Value;
+
+use super::Response;
+
+/// The representations a response can be rendered in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Text,
+}
+
+impl Format {
+    /// Picks the supported format the `Accept` header prefers, by the quality of the most
+    /// specific range matching each, and then by which is listed first. Defaults to JSON when
+    /// there is no header, and returns `None` if no supported format is acceptable, such as when
+    /// they all have a quality of 0.
+    #[must_use]
+    pub fn negotiate(headers: &HeaderMap) -> Option<Self> {
+        let ranges: Vec<(String, f32)> = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(media_range)
+            .collect();
+        if ranges.is_empty() {
+            return Some(Self::Json);
+        }
+        [Self::Json, Self::Text]
+            .into_iter()
+            .filter_map(|format| {
+                let (q, position) = format.quality(&ranges)?;
+                (q > 0.0).then_some((format, q, position))
+            })
+            .min_by(|(_, q_a, position_a), (_, q_b, position_b)| {
+                q_b.total_cmp(q_a).then(position_a.cmp(position_b))
+            })
+            .map(|(format, ..)| format)
+    }
+
+    /// The quality the format is accepted with, that of the most specific media range matching
+    /// it, along with where that range is listed.
+    fn quality(self, ranges: &[(String, f32)]) -> Option<(f32, usize)> {
+        let matching: [&[&str]; 3] = match self {
+            Self::Json => [
+                &["application/json", "application/problem+json"],
+                &["application/*"],
+                &["*/*"],
+            ],
+            Self::Text => [&["text/plain"], &["text/*"], &["*/*"]],
+        };
+        matching.iter().find_map(|media_types| {
+            ranges
+                .iter()
+                .position(|(range, _)| media_types.contains(&range.as_str()))
+                .map(|position| (ranges[position].1, position))
+        })
+    }
+
+    /// The `Content-Type` of a successful response in this format.
+    #[must_use]
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Text => "text/plain; charset=utf-8",
+        }
+    }
+
+    /// Renders a JSON response in this format. Successful plain text bodies are built with
+    /// `to_text`, while errors are rendered as their message, with a `Retry-After` when they say
+    /// when to retry.
+    pub fn render(
+        self,
+        (status, Json(value)): (StatusCode, Response),
+        to_text: impl FnOnce(&Value) -> String,
+    ) -> axum::response::Response {
+        let retry_after = value["error"]["retry_after"].as_u64();
+        let mut response = match self {
+            Self::Json => (status, Json(value)).into_response(),
+            Self::Text => {
+                let text = match value["error"]["message"].as_str() {
+                    Some(message) => message.to_string(),
+                    None => to_text(&value),
+                };
+                (status, text).into_response()
+            }
+        };
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+/// Parses a media range of an `Accept` header into its lowercased media type and its quality,
+/// 1 unless given. Returns `None` for an empty range or one with an invalid quality.
+fn media_range(range: &str) -> Option<(String, f32)> {
+    let mut parts = range.split(';');
+    let media_type = parts.next()?.trim().to_ascii_lowercase();
+    if media_type.is_empty() {
+        return None;
+    }
+    let q = match parts
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+    {
+        Some((_, q)) => q
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|q| (0.0..=1.0).contains(q))?,
+        None => 1.0,
+    };
+    Some((media_type, q))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, HeaderMap, HeaderValue};
+
+    use super::Format;
+
+    fn accept(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Some(Format::Json), Format::negotiate(&HeaderMap::new()));
+        assert_eq!(Some(Format::Json), Format::negotiate(&accept("*/*")));
+        assert_eq!(Some(Format::Text), Format::negotiate(&accept("text/plain")));
+        assert_eq!(
+            Some(Format::Text),
+            Format::negotiate(&accept("text/html, text/plain;q=0.9, */*;q=0.8"))
+        );
+        assert_eq!(None, Format::negotiate(&accept("image/png")));
+    }
+
+    #[test]
+    fn test_negotiate_honours_quality() {
+        assert_eq!(None, Format::negotiate(&accept("text/plain;q=0")));
+        assert_eq!(
+            Some(Format::Json),
+            Format::negotiate(&accept("text/plain;q=0, */*"))
+        );
+        assert_eq!(
+            Some(Format::Text),
+            Format::negotiate(&accept("application/json;q=0.5, text/plain"))
+        );
+        assert_eq!(
+            Some(Format::Text),
+            Format::negotiate(&accept("*/*;q=0.1, text/*;q=0.8"))
+        );
+        assert_eq!(
+            Some(Format::Text),
+            Format::negotiate(&accept("application/json;q=0, */*"))
+        );
+    }
+}
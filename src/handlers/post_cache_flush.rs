@@ -0,0 +1,40 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+
+use super::{respond_error, Animal};
+use crate::state::AppState;
+
+/// The cache flush query parameters.
+#[derive(serde::Deserialize)]
+pub struct FlushParam {
+    /// The animal whose facts to evict; every animal's are evicted when left out.
+    animal: Option<String>,
+}
+
+/// Evicts the cached facts and facts of the day, for one animal or all of them, returning the
+/// number of entries evicted.
+#[tracing::instrument(name = "Flushing the fact cache", skip(state, param))]
+pub async fn post_cache_flush(
+    State(state): State<AppState>,
+    Query(param): Query<FlushParam>,
+) -> axum::response::Response {
+    let animal = match param
+        .animal
+        .as_deref()
+        .map(|name| Animal::lookup(&state, name))
+    {
+        Some(Ok(animal)) => Some(animal),
+        Some(Err(err)) => return respond_error(&err, state.config.errors.detail).into_response(),
+        None => None,
+    };
+    let name = animal.as_ref().map(Animal::as_str);
+    let evicted = state.cache.flush(name).await;
+    let value = json!({ "animal": name, "evicted": evicted });
+    tracing::warn!("Fact cache flushed: {value}");
+    (StatusCode::OK, Json(value)).into_response()
+}
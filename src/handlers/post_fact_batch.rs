@@ -0,0 +1,173 @@
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use futures::{stream, StreamExt};
+use rand::{rngs::StdRng, SeedableRng};
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use super::{filtered_fact, resolve_animal, respond_error, ErrorKind, FactFilter, Format};
+use crate::state::AppState;
+
+/// The longest animal name accepted in a batch.
+const MAX_ANIMAL_LEN: usize = 24;
+
+/// The batch fact request body.
+#[derive(serde::Deserialize, Validate, ToSchema)]
+pub struct BatchRequest {
+    /// The animals to get facts about, each of which may be `any`.
+    #[validate(
+        length(min = 1, message = "must list at least one animal"),
+        custom(function = "validate_animal_names")
+    )]
+    #[schema(example = json!(["cat", "dog", "cat"]), min_items = 1)]
+    animals: Vec<String>,
+}
+
+/// Validates that each animal name is neither blank nor too long.
+fn validate_animal_names(animals: &[String]) -> Result<(), ValidationError> {
+    for (i, animal) in animals.iter().enumerate() {
+        if animal.trim().is_empty() {
+            return Err(invalid(format!("item {i} must not be empty")));
+        }
+        if animal.chars().count() > MAX_ANIMAL_LEN {
+            return Err(invalid(format!(
+                "item {i} must be at most {MAX_ANIMAL_LEN} characters"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Reads the batch request from a JSON body, reporting a missing `animals` or one that isn't an
+/// array of strings as a validation failure of the field, as serde would only give a generic one.
+fn batch_request(body: Value) -> Result<BatchRequest, ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    match body.get("animals") {
+        None | Some(Value::Null) => errors.add("animals", invalid("is required")),
+        Some(Value::Array(animals)) => {
+            for (i, animal) in animals.iter().enumerate() {
+                if !animal.is_string() {
+                    let message = format!("item {i} must be a string");
+                    errors.add("animals", invalid(message));
+                }
+            }
+        }
+        Some(_) => errors.add("animals", invalid("must be an array of animal names")),
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let request: BatchRequest = serde_json::from_value(body).map_err(|err| {
+        let mut errors = ValidationErrors::new();
+        errors.add("animals", invalid(err.to_string()));
+        errors
+    })?;
+    request.validate()?;
+    Ok(request)
+}
+
+/// A validation failure of `animals` with the message.
+fn invalid(message: impl Into<String>) -> ValidationError {
+    ValidationError::new("animals").with_message(message.into().into())
+}
+
+/// Returns a fact or an error for each requested animal, in the order requested.
+#[utoipa::path(
+    post,
+    context_path = "/v1",
+    path = "/fact/batch",
+    tag = "facts",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "A fact or an error for each requested animal", body = [BatchItem],
+            content_type = ["application/json", "text/plain"]),
+        (status = 400, description = "The request body is invalid, with each invalid field's failures, or asks for too many animals", body = ErrorResponse),
+        (status = 406, description = "Neither JSON nor plain text is acceptable", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(name = "Fetching a batch of animal facts", skip(state, body))]
+pub async fn post_fact_batch(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Result<Json<Value>, JsonRejection>,
+) -> axum::response::Response {
+    let Some(format) = Format::negotiate(&headers) else {
+        return respond_error(&ErrorKind::NotAcceptable, state.config.errors.detail)
+            .into_response();
+    };
+    let BatchRequest { animals } = match body.map(|Json(body)| batch_request(body)) {
+        Ok(Ok(request)) => request,
+        Ok(Err(errors)) => {
+            let err = ErrorKind::Validation(errors);
+            return format.render(respond_error(&err, state.config.errors.detail), batch_text);
+        }
+        Err(rejection) => {
+            let err = ErrorKind::InvalidBody(rejection.body_text());
+            return format.render(respond_error(&err, state.config.errors.detail), batch_text);
+        }
+    };
+    let max_batch_size = state.config.batch.max_batch_size;
+    if animals.len() > max_batch_size {
+        let err = ErrorKind::TooManyAnimals(max_batch_size);
+        return format.render(respond_error(&err, state.config.errors.detail), batch_text);
+    }
+
+    let concurrency = state.config.batch.concurrency.max(1);
+    let results: Vec<Value> = stream::iter(animals)
+        .map(|animal| batch_item(&state, animal))
+        .buffered(concurrency)
+        .collect()
+        .await;
+
+    tracing::info!("Success response payload: {results:?}");
+    format.render((StatusCode::OK, Json(Value::Array(results))), batch_text)
+}
+
+/// The plain text body for a batch response: one line per item, with its fact or error message.
+pub(super) fn batch_text(value: &Value) -> String {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|item| {
+            let text = item["fact"]
+                .as_str()
+                .or_else(|| item["error"]["message"].as_str())
+                .unwrap_or_default();
+            format!("{}: {text}", item["animal"].as_str().unwrap_or_default())
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Fetches a fact for one animal of a batch, describing any failure in the item itself.
+pub(super) async fn batch_item(state: &AppState, animal: String) -> Value {
+    let mut rng = StdRng::from_entropy();
+    let res = match resolve_animal(state, &animal, &mut rng) {
+        Ok(a) => {
+            let filter = FactFilter::new(state, None, None);
+            let res = filtered_fact(state, &a, &filter, &mut rng).await;
+            state.metrics.record_fact(a.as_str(), res.is_ok());
+            res.map(|fact| (fact, a.as_str()))
+        }
+        Err(err) => Err(err),
+    };
+    match res {
+        Ok((fact, animal)) if fact.source.is_local() => {
+            json!({ "fact": fact.text, "animal": animal, "source": fact.source })
+        }
+        Ok((fact, animal)) => json!({ "fact": fact.text, "animal": animal }),
+        Err(err) => json!({
+            "animal": animal,
+            "error": {
+                "code": err.code(),
+                "message": err.client_message(state.config.errors.detail),
+            },
+        }),
+    }
+}
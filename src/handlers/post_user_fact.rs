@@ -0,0 +1,126 @@
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+use utoipa::ToSchema;
+use validator::{ValidationError, ValidationErrors};
+
+use super::{respond_error, Animal, ErrorKind};
+use crate::auth::API_KEY_HEADER;
+use crate::idempotency::{Claim, IdempotencyKeys, IDEMPOTENT_REPLAYED};
+use crate::state::AppState;
+
+/// A fact contributed by a user.
+#[derive(serde::Deserialize, ToSchema)]
+pub struct UserFactRequest {
+    /// The animal the fact is about.
+    #[schema(example = "cat")]
+    animal: String,
+    /// The fact, up to the configured maximum length.
+    #[schema(example = "A group of cats is called a clowder.")]
+    fact: String,
+}
+
+/// The endpoint idempotency keys of user fact submissions are scoped to.
+const ENDPOINT: &str = "POST /fact";
+
+/// Stores a fact contributed by a user, to be served alongside upstream facts.
+///
+/// A submission retried with the same `Idempotency-Key` header by the same API key gets the first
+/// submission's response back, with an `Idempotent-Replayed` header, instead of storing the fact
+/// again. Reusing a key for a different submission is refused.
+#[utoipa::path(
+    post,
+    context_path = "/v1",
+    path = "/fact",
+    tag = "facts",
+    request_body = UserFactRequest,
+    responses(
+        (status = 201, description = "The fact was stored, or was already stored with the same idempotency key", body = FactResponse),
+        (status = 400, description = "The animal is unsupported, or the fact is empty or too long", body = ErrorResponse),
+        (status = 401, description = "No API key was given", body = ErrorResponse),
+        (status = 403, description = "The API key is invalid, or contributions are disabled", body = ErrorResponse),
+        (status = 409, description = "A submission with the same idempotency key is still being stored", body = ErrorResponse),
+        (status = 422, description = "The idempotency key was already used for a different submission", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(name = "Storing a user fact", skip(state, headers, body))]
+pub async fn post_user_fact(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Result<Json<UserFactRequest>, JsonRejection>,
+) -> axum::response::Response {
+    let Json(UserFactRequest { animal, fact }) = match body {
+        Ok(body) => body,
+        Err(rejection) => {
+            return respond_error(
+                &ErrorKind::InvalidBody(rejection.body_text()),
+                state.config.errors.detail,
+            )
+            .into_response()
+        }
+    };
+    let mut pending = None;
+    if let Some(key) = IdempotencyKeys::key(&headers) {
+        // the route requires an API key, which identifies the client
+        let client = headers
+            .get(API_KEY_HEADER)
+            .and_then(|key| key.to_str().ok())
+            .unwrap_or_default();
+        let fingerprint = format!("{animal}\n{fact}");
+        match state
+            .idempotency_keys
+            .claim(ENDPOINT, client, key, fingerprint)
+        {
+            Claim::New(claim) => pending = Some(claim),
+            Claim::Replay(status, value) => {
+                tracing::info!("Replaying the response stored for idempotency key '{key}'");
+                let replayed = [(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"))];
+                return (status, replayed, Json(value)).into_response();
+            }
+            Claim::InProgress => {
+                return respond_error(&ErrorKind::IdempotencyKeyInUse, state.config.errors.detail)
+                    .into_response()
+            }
+            Claim::Reused => {
+                return respond_error(&ErrorKind::IdempotencyKeyReused, state.config.errors.detail)
+                    .into_response()
+            }
+        }
+    }
+    let fact = fact.trim().to_string();
+    if let Err(err) = validate_fact(&fact, state.config.user_facts.max_len) {
+        return respond_error(&ErrorKind::Validation(err), state.config.errors.detail)
+            .into_response();
+    }
+    let animal = match Animal::lookup(&state, &animal) {
+        Ok(animal) => animal,
+        Err(err) => return respond_error(&err, state.config.errors.detail).into_response(),
+    };
+
+    state.user_facts.add(animal.as_str(), fact.clone()).await;
+    let value = json!({ "fact": fact, "animal": animal.as_str(), "source": "user" });
+    tracing::info!("Stored user fact: {value}");
+    if let Some(pending) = pending {
+        pending.complete(StatusCode::CREATED, value.clone());
+    }
+    (StatusCode::CREATED, Json(value)).into_response()
+}
+
+/// Checks that the fact isn't empty and is at most `max_len` characters long.
+fn validate_fact(fact: &str, max_len: usize) -> Result<(), ValidationErrors> {
+    let len = fact.chars().count();
+    if len > 0 && len <= max_len {
+        return Ok(());
+    }
+    let mut errs = ValidationErrors::new();
+    errs.add(
+        "fact",
+        ValidationError::new("length")
+            .with_message(format!("must be between 1 and {max_len} characters").into()),
+    );
+    Err(errs)
+}
@@ -0,0 +1,61 @@
+use std::str::FromStr;
+
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+use tracing::level_filters::LevelFilter;
+
+use super::{respond_error, ErrorKind};
+use crate::state::AppState;
+
+/// The log level change request body.
+#[derive(serde::Deserialize)]
+pub struct LogLevelRequest {
+    /// The lowest level to log: `trace`, `debug`, `info`, `warn`, `error` or `off`.
+    level: String,
+}
+
+/// Changes the level the running service logs at, returning the previous and new levels.
+#[tracing::instrument(name = "Changing the log level", skip(state, body))]
+pub async fn put_log_level(
+    State(state): State<AppState>,
+    body: Result<Json<LogLevelRequest>, JsonRejection>,
+) -> axum::response::Response {
+    let Json(LogLevelRequest { level }) = match body {
+        Ok(body) => body,
+        Err(rejection) => {
+            return respond_error(
+                &ErrorKind::InvalidBody(rejection.body_text()),
+                state.config.errors.detail,
+            )
+            .into_response()
+        }
+    };
+    let Ok(filter) = LevelFilter::from_str(&level) else {
+        return respond_error(
+            &ErrorKind::InvalidLogLevel(level),
+            state.config.errors.detail,
+        )
+        .into_response();
+    };
+    let Some(handle) = &state.log_level else {
+        return respond_error(&ErrorKind::LogLevelUnavailable, state.config.errors.detail)
+            .into_response();
+    };
+    match handle.set(filter) {
+        Ok(previous) => {
+            let value = json!({ "previous": previous, "level": filter.to_string() });
+            tracing::warn!("Log level changed: {value}");
+            (StatusCode::OK, Json(value)).into_response()
+        }
+        Err(err) => {
+            tracing::error!("Failed to change the log level: {err}");
+            respond_error(&ErrorKind::LogLevelUnavailable, state.config.errors.detail)
+                .into_response()
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode, Json};
+use futures::future::join_all;
+use serde_json::{json, Map, Value};
+
+use super::{Animal, Response};
+use crate::state::AppState;
+
+/// Probes each configured upstream and returns 200 if all are reachable, otherwise 503.
+#[tracing::instrument(name = "Performing readiness check", skip(state))]
+pub async fn readiness_check(State(state): State<AppState>) -> (StatusCode, Response) {
+    let timeout = Duration::from_millis(state.config.readiness.timeout_ms);
+    let probes = state.config.readiness.dependencies.iter().map(|name| {
+        let state = &state;
+        async move {
+            let up = match Animal::lookup(state, name) {
+                Ok(animal) => probe(state, animal.api_url(&state.config.api), timeout).await,
+                Err(err) => {
+                    tracing::error!("Unknown readiness dependency: {err}");
+                    false
+                }
+            };
+            (name.clone(), up)
+        }
+    });
+    let results = join_all(probes).await;
+
+    let ready = results.iter().all(|(_, up)| *up);
+    let dependencies: Map<String, Value> = results
+        .into_iter()
+        .map(|(name, up)| (name, json!(if up { "up" } else { "down" })))
+        .collect();
+    let value = json!({
+        "status": if ready { "ready" } else { "unavailable" },
+        "dependencies": dependencies,
+    });
+
+    if ready {
+        tracing::info!("Readiness check passed: {value}");
+        (StatusCode::OK, Json(value))
+    } else {
+        tracing::error!("Readiness check failed: {value}");
+        (StatusCode::SERVICE_UNAVAILABLE, Json(value))
+    }
+}
+
+/// Returns whether the upstream responds without a server error within the timeout, sending the
+/// headers configured for its host.
+pub(super) async fn probe(state: &AppState, url: &str, timeout: Duration) -> bool {
+    let mut req = state.client.current().get(url).timeout(timeout);
+    if let Some(headers) = state.upstream_headers.for_url(url) {
+        req = req.headers(headers.clone());
+    }
+    match req.send().await {
+        Ok(res) => !res.status().is_server_error(),
+        Err(err) => {
+            tracing::warn!("Readiness probe to {url} failed: {err}");
+            false
+        }
+    }
+}
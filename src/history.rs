@@ -0,0 +1,139 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+
+/// A fact served to a client, and the request it was served to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServedFact {
+    pub animal: String,
+    pub fact: String,
+    pub served_at: DateTime<Utc>,
+    pub request_id: Option<String>,
+}
+
+/// The facts most recently served, the oldest evicted once at capacity.
+///
+/// It is kept in memory on a best-effort basis, so each instance has its own and it is lost on
+/// restart.
+pub struct FactHistory {
+    capacity: usize,
+    facts: Mutex<VecDeque<ServedFact>>,
+}
+
+impl FactHistory {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            facts: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Records a fact served about the animal to the request with the id.
+    pub fn record(&self, animal: &str, fact: &str, request_id: Option<&str>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut facts = self.facts.lock().unwrap();
+        if facts.len() >= self.capacity {
+            facts.pop_back();
+        }
+        facts.push_front(ServedFact {
+            animal: animal.to_string(),
+            fact: fact.to_string(),
+            served_at: Utc::now(),
+            request_id: request_id.map(str::to_string),
+        });
+    }
+
+    /// Returns the facts served, newest first.
+    #[must_use]
+    pub fn recent(&self) -> Vec<ServedFact> {
+        self.facts.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// When each feed item was first listed, so it keeps its date whenever the feed is built again.
+///
+/// Like [`FactHistory`], it is kept in memory, and the earliest listed items are forgotten once
+/// at capacity.
+pub struct FeedDates {
+    capacity: usize,
+    listings: Mutex<Listings>,
+}
+
+/// The dates items were first listed on, along with the order they were listed in.
+#[derive(Default)]
+struct Listings {
+    dates: HashMap<String, DateTime<Utc>>,
+    order: VecDeque<String>,
+}
+
+impl FeedDates {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            listings: Mutex::default(),
+        }
+    }
+
+    /// Returns when the item with the guid was first listed, recording `now` if it wasn't yet.
+    pub fn first_listed(&self, guid: &str, now: DateTime<Utc>) -> DateTime<Utc> {
+        let mut listings = self.listings.lock().unwrap();
+        if let Some(date) = listings.dates.get(guid) {
+            return *date;
+        }
+        if listings.dates.len() >= self.capacity {
+            if let Some(oldest) = listings.order.pop_front() {
+                listings.dates.remove(&oldest);
+            }
+        }
+        listings.dates.insert(guid.to_string(), now);
+        listings.order.push_back(guid.to_string());
+        now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::{FactHistory, FeedDates};
+
+    #[test]
+    fn test_fact_history() {
+        let history = FactHistory::new(2);
+        history.record("cat", "first", Some("a"));
+        history.record("dog", "second", None);
+        history.record("bird", "third", Some("c"));
+
+        let facts: Vec<_> = history
+            .recent()
+            .into_iter()
+            .map(|served| (served.fact, served.request_id))
+            .collect();
+        assert_eq!(
+            vec![("third".into(), Some("c".into())), ("second".into(), None)],
+            facts
+        );
+
+        let disabled = FactHistory::new(0);
+        disabled.record("cat", "fact", None);
+        assert!(disabled.recent().is_empty());
+    }
+
+    #[test]
+    fn test_feed_dates_keep_the_first_listing() {
+        let dates = FeedDates::new(2);
+        let first = Utc::now();
+        let later = first + Duration::hours(1);
+
+        assert_eq!(first, dates.first_listed("a", first));
+        assert_eq!(first, dates.first_listed("a", later));
+        assert_eq!(later, dates.first_listed("b", later));
+        assert_eq!(later, dates.first_listed("c", later));
+        assert_eq!(later, dates.first_listed("a", later));
+    }
+}
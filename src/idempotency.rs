@@ -0,0 +1,281 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::http::{HeaderMap, HeaderName, StatusCode};
+use serde_json::Value;
+
+/// The header a client sets to make retrying a request safe.
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// The header set on a response replayed for a repeated idempotency key.
+pub const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// The longest idempotency key accepted; longer keys are ignored.
+const MAX_KEY_LEN: usize = 255;
+
+/// What an idempotency key is scoped to: the endpoint, the client and the key itself.
+type Scope = (&'static str, String, String);
+
+/// The results of requests made with an idempotency key, so a retried request gets the same
+/// response instead of being carried out again.
+///
+/// Keys are scoped per endpoint and client, and remember the request they were first used with,
+/// so reusing one for a different request is refused. Entries expire after `ttl`, and the oldest
+/// one is evicted when at capacity.
+pub struct IdempotencyKeys {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+/// The stored entries, along with the order they were stored in. As every entry lives for the
+/// same `ttl`, that is also the order they expire in.
+#[derive(Default)]
+struct Entries {
+    stored: HashMap<Scope, Entry>,
+    order: VecDeque<(Instant, Scope)>,
+}
+
+/// A request made with an idempotency key, and its response once it has one.
+struct Entry {
+    stored_at: Instant,
+    fingerprint: String,
+    response: Option<(StatusCode, Value)>,
+}
+
+/// The outcome of claiming an idempotency key for a request.
+pub enum Claim<'a> {
+    /// The key is new; the request should be carried out and its response stored.
+    New(Pending<'a>),
+    /// The same request was already made with the key; its response should be replayed.
+    Replay(StatusCode, Value),
+    /// The same request is still being carried out with the key.
+    InProgress,
+    /// The key was already used for a different request.
+    Reused,
+}
+
+/// A request claiming an idempotency key. The claim is released, so the key can be retried, if
+/// it is dropped without a response being stored.
+pub struct Pending<'a> {
+    keys: &'a IdempotencyKeys,
+    claimed: Option<(Instant, Scope)>,
+}
+
+impl IdempotencyKeys {
+    #[must_use]
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// The idempotency key of the request, if it has a usable one.
+    #[must_use]
+    pub fn key(headers: &HeaderMap) -> Option<&str> {
+        headers
+            .get(IDEMPOTENCY_KEY)
+            .and_then(|key| key.to_str().ok())
+            .map(str::trim)
+            .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LEN)
+    }
+
+    /// Claims the key for the client's request to the endpoint, identified by its `fingerprint`,
+    /// unless it was already used.
+    pub fn claim(
+        &self,
+        endpoint: &'static str,
+        client: &str,
+        key: &str,
+        fingerprint: String,
+    ) -> Claim<'_> {
+        if self.capacity == 0 {
+            return Claim::New(Pending {
+                keys: self,
+                claimed: None,
+            });
+        }
+        let mut entries = self.entries.lock().expect("Idempotency keys lock poisoned");
+        let scope = (endpoint, client.to_string(), key.to_string());
+        let stored = entries.stored.get(&scope);
+        if let Some(stored) = stored.filter(|stored| stored.stored_at.elapsed() < self.ttl) {
+            return match &stored.response {
+                _ if stored.fingerprint != fingerprint => Claim::Reused,
+                Some((status, body)) => Claim::Replay(*status, body.clone()),
+                None => Claim::InProgress,
+            };
+        }
+        entries.evict(self.ttl, self.capacity);
+        let now = Instant::now();
+        entries.stored.insert(
+            scope.clone(),
+            Entry {
+                stored_at: now,
+                fingerprint,
+                response: None,
+            },
+        );
+        entries.order.push_back((now, scope.clone()));
+        Claim::New(Pending {
+            keys: self,
+            claimed: Some((now, scope)),
+        })
+    }
+}
+
+impl Entries {
+    /// Evicts expired entries, then the oldest ones until there is room for another.
+    fn evict(&mut self, ttl: Duration, capacity: usize) {
+        while let Some((stored_at, _)) = self.order.front() {
+            if stored_at.elapsed() < ttl && self.stored.len() < capacity {
+                break;
+            }
+            let Some((stored_at, scope)) = self.order.pop_front() else {
+                break;
+            };
+            self.remove(stored_at, &scope);
+        }
+    }
+
+    /// Removes the entry for the scope if it is the one stored at `stored_at`, and not one
+    /// stored for the same scope since.
+    fn remove(&mut self, stored_at: Instant, scope: &Scope) {
+        if self
+            .stored
+            .get(scope)
+            .is_some_and(|stored| stored.stored_at == stored_at)
+        {
+            self.stored.remove(scope);
+        }
+    }
+}
+
+impl Pending<'_> {
+    /// Stores the response to replay for the key.
+    pub fn complete(mut self, status: StatusCode, body: Value) {
+        let Some((stored_at, scope)) = self.claimed.take() else {
+            return;
+        };
+        let mut entries = self
+            .keys
+            .entries
+            .lock()
+            .expect("Idempotency keys lock poisoned");
+        if let Some(stored) = entries
+            .stored
+            .get_mut(&scope)
+            .filter(|stored| stored.stored_at == stored_at)
+        {
+            stored.response = Some((status, body));
+        }
+    }
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        if let Some((stored_at, scope)) = self.claimed.take() {
+            let mut entries = self
+                .keys
+                .entries
+                .lock()
+                .expect("Idempotency keys lock poisoned");
+            entries.remove(stored_at, &scope);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::{HeaderMap, HeaderValue, StatusCode};
+    use serde_json::json;
+
+    use super::{Claim, IdempotencyKeys, IDEMPOTENCY_KEY};
+
+    #[test]
+    fn test_idempotency_keys_are_scoped_per_endpoint_and_client() {
+        let keys = IdempotencyKeys::new(Duration::from_mins(1), 10);
+
+        let Claim::New(pending) = keys.claim("/fact", "a", "abc", "one".into()) else {
+            panic!("Expected a new key.");
+        };
+        pending.complete(StatusCode::CREATED, json!({ "fact": "one" }));
+
+        assert!(matches!(
+            keys.claim("/fact", "a", "abc", "one".into()),
+            Claim::Replay(StatusCode::CREATED, body) if body == json!({ "fact": "one" })
+        ));
+        assert!(matches!(
+            keys.claim("/fact", "a", "abc", "two".into()),
+            Claim::Reused
+        ));
+        assert!(matches!(
+            keys.claim("/fact/batch", "a", "abc", "one".into()),
+            Claim::New(_)
+        ));
+        assert!(matches!(
+            keys.claim("/fact", "b", "abc", "one".into()),
+            Claim::New(_)
+        ));
+    }
+
+    #[test]
+    fn test_idempotency_keys_in_progress_until_completed_or_dropped() {
+        let keys = IdempotencyKeys::new(Duration::from_mins(1), 10);
+
+        let pending = keys.claim("/fact", "a", "abc", "one".into());
+        assert!(matches!(
+            keys.claim("/fact", "a", "abc", "one".into()),
+            Claim::InProgress
+        ));
+        drop(pending);
+
+        assert!(matches!(
+            keys.claim("/fact", "a", "abc", "one".into()),
+            Claim::New(_)
+        ));
+    }
+
+    #[test]
+    fn test_idempotency_keys_expire_and_evict_the_oldest() {
+        let keys = IdempotencyKeys::new(Duration::ZERO, 10);
+        if let Claim::New(pending) = keys.claim("/fact", "a", "abc", "one".into()) {
+            pending.complete(StatusCode::CREATED, json!({}));
+        }
+        assert!(matches!(
+            keys.claim("/fact", "a", "abc", "one".into()),
+            Claim::New(_)
+        ));
+
+        let keys = IdempotencyKeys::new(Duration::from_mins(1), 1);
+        for key in ["abc", "def"] {
+            if let Claim::New(pending) = keys.claim("/fact", "a", key, "one".into()) {
+                pending.complete(StatusCode::CREATED, json!({}));
+            }
+        }
+        assert!(matches!(
+            keys.claim("/fact", "a", "def", "one".into()),
+            Claim::Replay(..)
+        ));
+        assert!(matches!(
+            keys.claim("/fact", "a", "abc", "one".into()),
+            Claim::New(_)
+        ));
+    }
+
+    #[test]
+    fn test_idempotency_key() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(None, IdempotencyKeys::key(&headers));
+
+        headers.insert(IDEMPOTENCY_KEY, HeaderValue::from_static(" abc "));
+        assert_eq!(Some("abc"), IdempotencyKeys::key(&headers));
+
+        headers.insert(IDEMPOTENCY_KEY, HeaderValue::from_static(""));
+        assert_eq!(None, IdempotencyKeys::key(&headers));
+    }
+}
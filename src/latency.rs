@@ -0,0 +1,113 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::circuit_breaker::{upstream, CircuitBreakers};
+
+/// An exponentially weighted moving average of each upstream's latency, identified by its URL
+/// without the query string, with each new sample weighted by `smoothing`.
+///
+/// A failed call counts as taking `failure_penalty`, so an upstream that keeps failing fast
+/// doesn't look like the fastest one.
+pub struct UpstreamLatencies {
+    smoothing: f64,
+    failure_penalty: Duration,
+    averages: Mutex<HashMap<String, f64>>,
+}
+
+impl UpstreamLatencies {
+    #[must_use]
+    pub fn new(smoothing: f64, failure_penalty: Duration) -> Self {
+        Self {
+            smoothing: smoothing.clamp(0.0, 1.0),
+            failure_penalty,
+            averages: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a failed call to the upstream serving `url` as taking the failure penalty.
+    pub fn record_failure(&self, url: &str) {
+        self.record(url, self.failure_penalty);
+    }
+
+    /// Records how long a successful call to the upstream serving `url` took.
+    pub fn record(&self, url: &str, latency: Duration) {
+        let sample = latency.as_secs_f64() * 1000.0;
+        let mut averages = self.averages.lock().expect("Latencies lock poisoned");
+        averages
+            .entry(upstream(url))
+            .and_modify(|average| *average += self.smoothing * (sample - *average))
+            .or_insert(sample);
+    }
+
+    /// Orders the URLs fastest first, after any not yet measured so each gets measured, with
+    /// those whose circuit is open last. URLs that tie keep their configured order.
+    #[must_use]
+    pub fn order<'a>(&self, urls: &[&'a str], breakers: &CircuitBreakers) -> Vec<&'a str> {
+        let averages = self.averages.lock().expect("Latencies lock poisoned");
+        let mut keyed: Vec<_> = urls
+            .iter()
+            .map(|url| {
+                let open = breakers.get(url).state() == "open";
+                let average = averages.get(&upstream(url)).copied().unwrap_or(0.0);
+                (open, average, *url)
+            })
+            .collect();
+        keyed.sort_by(|(a_open, a, _), (b_open, b, _)| a_open.cmp(b_open).then(a.total_cmp(b)));
+        keyed.into_iter().map(|(_, _, url)| url).collect()
+    }
+
+    /// Returns each upstream's average latency in milliseconds, by upstream.
+    #[must_use]
+    pub fn averages(&self) -> BTreeMap<String, f64> {
+        let averages = self.averages.lock().expect("Latencies lock poisoned");
+        averages
+            .iter()
+            .map(|(upstream, average)| (upstream.clone(), *average))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::UpstreamLatencies;
+    use crate::circuit_breaker::CircuitBreakers;
+
+    #[test]
+    fn test_latencies_order_fastest_first() {
+        let latencies = UpstreamLatencies::new(0.5, Duration::from_secs(10));
+        let breakers = CircuitBreakers::new(1, Duration::from_secs(30));
+        let urls = ["http://slow/fact", "http://fast/fact", "http://new/fact"];
+
+        latencies.record("http://slow/fact", Duration::from_millis(100));
+        latencies.record("http://slow/fact?n=2", Duration::from_millis(300));
+        latencies.record("http://fast/fact", Duration::from_millis(50));
+        assert_eq!(Some(&200.0), latencies.averages().get("http://slow/fact"));
+        assert_eq!(
+            vec!["http://new/fact", "http://fast/fact", "http://slow/fact"],
+            latencies.order(&urls, &breakers)
+        );
+
+        breakers.get("http://new/fact").record_failure();
+        assert_eq!(
+            vec!["http://fast/fact", "http://slow/fact", "http://new/fact"],
+            latencies.order(&urls, &breakers)
+        );
+    }
+
+    #[test]
+    fn test_failing_upstream_ordered_after_working_ones() {
+        let latencies = UpstreamLatencies::new(0.5, Duration::from_secs(10));
+        let breakers = CircuitBreakers::new(5, Duration::from_secs(30));
+        let urls = ["http://failing/fact", "http://working/fact"];
+
+        latencies.record_failure("http://failing/fact");
+        latencies.record("http://working/fact", Duration::from_millis(500));
+        assert_eq!(
+            vec!["http://working/fact", "http://failing/fact"],
+            latencies.order(&urls, &breakers)
+        );
+    }
+}
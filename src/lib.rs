@@ -5,7 +5,32 @@
     clippy::missing_errors_doc
 )]
 
+pub mod access_log;
+pub mod auth;
+pub mod body_log;
+pub mod cache;
+pub mod circuit_breaker;
 pub mod config;
+pub mod fallback;
 pub mod handlers;
+pub mod history;
+pub mod idempotency;
+pub mod latency;
+pub mod metrics;
+pub mod openapi;
+pub mod problem;
+pub mod rate_limit;
+pub mod request_id;
+pub mod selftest;
+pub mod single_flight;
 pub mod startup;
+pub mod state;
 pub mod telemetry;
+pub mod tls;
+pub mod translation;
+pub mod upstream_client;
+pub mod upstream_headers;
+pub mod user_facts;
+pub mod util;
+pub mod warmer;
+pub mod webhook;
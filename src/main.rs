@@ -1,26 +1,76 @@
 use coding_challenge::{
     config::get_config,
-    startup::run,
-    telemetry::{get_subscriber, init_subscriber},
+    selftest::selftest,
+    startup::{bind_listener, run},
+    state::AppState,
+    telemetry::{
+        get_json_subscriber, get_subscriber, get_tracer, get_tracer_provider, init_subscriber,
+        LogFormat,
+    },
 };
-use tokio::net::TcpListener;
 
 #[tokio::main]
 async fn main() {
-    let sub = get_subscriber("coding-challenge".into(), "info".into(), std::io::stdout);
-    init_subscriber(sub);
-
     // halt the program if there are any errors reading config or binding a port
     let conf = get_config().expect("Cannot read config");
-    let addr = format!("{}:{}", conf.application.host, conf.application.port);
-    let listener = TcpListener::bind(&addr)
+
+    let name = "coding-challenge".to_string();
+    let provider = (!conf.telemetry.otlp_endpoint.is_empty()).then(|| {
+        get_tracer_provider(
+            name.clone(),
+            &conf.telemetry.otlp_endpoint,
+            conf.telemetry.sample_ratio,
+            conf.telemetry.error_traces,
+        )
+        .expect("Cannot build OTLP exporter")
+    });
+    let tracer = provider
+        .as_ref()
+        .map(|provider| get_tracer(provider, name.clone()));
+    let log_level = match LogFormat::from_env() {
+        LogFormat::Bunyan => {
+            let (sub, handle) = get_subscriber(name, "info".into(), std::io::stdout, tracer);
+            init_subscriber(sub);
+            handle
+        }
+        LogFormat::Json => {
+            let (sub, handle) = get_json_subscriber("info".into(), std::io::stdout, tracer);
+            init_subscriber(sub);
+            handle
+        }
+    };
+
+    // --selftest checks the service starts and serves a fact, then exits without serving traffic
+    if std::env::args().skip(1).any(|arg| arg == "--selftest") {
+        let checks = selftest(conf).await;
+        for check in &checks {
+            println!("{check}");
+        }
+        let passed = checks.iter().all(|check| check.passed());
+        if let Some(provider) = provider {
+            let _ = provider.shutdown();
+        }
+        std::process::exit(i32::from(!passed));
+    }
+
+    let listener = bind_listener(&conf.application)
         .await
-        .expect("Unable to bind to port");
+        .unwrap_or_else(|e| panic!("{e}"));
+    let addr = listener.local_addr().expect("Unable to read bound address");
 
     tracing::info!("Application starting on: {addr}!");
 
-    run(listener)
+    run(listener, AppState::new(conf).with_log_level(log_level))
         .unwrap_or_else(|e| panic!("Application failed to start: {e}"))
         .await
-        .unwrap()
+        .unwrap();
+
+    tracing::info!("Application stopped!");
+
+    // flush any spans still buffered for export
+    if let Some(provider) = provider {
+        if let Err(e) = provider.shutdown() {
+            tracing::warn!("Failed to flush traces: {e}");
+        }
+    }
 }
@@ -0,0 +1,291 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use prometheus::{
+    core::Collector,
+    proto::{Metric, MetricFamily},
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::state::AppState;
+
+/// The application's Prometheus metrics, held in a per-app registry.
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_latency: HistogramVec,
+    facts: IntCounterVec,
+    fallback_facts: IntCounterVec,
+    unsupported_animals: IntGauge,
+    rejected_fast: IntCounter,
+    cache_lookups: IntCounterVec,
+    upstream_requests: IntCounter,
+    upstream_in_flight: IntGauge,
+    started_at: Instant,
+}
+
+/// Counts one towards a gauge until dropped, such as an upstream request in flight or an
+/// unsupported animal held by the cache.
+pub struct GaugeGuard(IntGauge);
+
+impl GaugeGuard {
+    /// Increments the gauge, to be decremented when the guard is dropped.
+    #[must_use]
+    pub fn new(gauge: IntGauge) -> Self {
+        gauge.inc();
+        Self(gauge)
+    }
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+impl Metrics {
+    #[must_use]
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let http_requests = IntCounterVec::new(
+            Opts::new(
+                "http_requests_total",
+                "Total HTTP requests by route and status class",
+            ),
+            &["route", "status"],
+        )
+        .expect("Invalid http_requests_total metric");
+        let http_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP handler latency in seconds by route",
+            ),
+            &["route"],
+        )
+        .expect("Invalid http_request_duration_seconds metric");
+        let facts = IntCounterVec::new(
+            Opts::new(
+                "animal_facts_total",
+                "Animal fact responses by animal and outcome",
+            ),
+            &["animal", "outcome"],
+        )
+        .expect("Invalid animal_facts_total metric");
+        let fallback_facts = IntCounterVec::new(
+            Opts::new(
+                "fallback_facts_total",
+                "Bundled fallback facts served by animal",
+            ),
+            &["animal"],
+        )
+        .expect("Invalid fallback_facts_total metric");
+        let unsupported_animals = IntGauge::new(
+            "unsupported_animals",
+            "Distinct unsupported animals recently requested",
+        )
+        .expect("Invalid unsupported_animals metric");
+        let rejected_fast = IntCounter::new(
+            "unsupported_animal_cache_hits_total",
+            "Requests for unsupported animals rejected from the cache",
+        )
+        .expect("Invalid unsupported_animal_cache_hits_total metric");
+        let cache_lookups = IntCounterVec::new(
+            Opts::new("fact_cache_lookups_total", "Fact cache lookups by result"),
+            &["result"],
+        )
+        .expect("Invalid fact_cache_lookups_total metric");
+        let upstream_requests = IntCounter::new(
+            "upstream_requests_total",
+            "Facts fetched from the upstream animal APIs",
+        )
+        .expect("Invalid upstream_requests_total metric");
+        let upstream_in_flight = IntGauge::new(
+            "upstream_requests_in_flight",
+            "Facts currently being fetched from the upstream animal APIs",
+        )
+        .expect("Invalid upstream_requests_in_flight metric");
+
+        registry
+            .register(Box::new(http_requests.clone()))
+            .expect("Failed to register http_requests_total");
+        registry
+            .register(Box::new(http_latency.clone()))
+            .expect("Failed to register http_request_duration_seconds");
+        registry
+            .register(Box::new(facts.clone()))
+            .expect("Failed to register animal_facts_total");
+        registry
+            .register(Box::new(fallback_facts.clone()))
+            .expect("Failed to register fallback_facts_total");
+        registry
+            .register(Box::new(unsupported_animals.clone()))
+            .expect("Failed to register unsupported_animals");
+        registry
+            .register(Box::new(rejected_fast.clone()))
+            .expect("Failed to register unsupported_animal_cache_hits_total");
+        registry
+            .register(Box::new(cache_lookups.clone()))
+            .expect("Failed to register fact_cache_lookups_total");
+        registry
+            .register(Box::new(upstream_requests.clone()))
+            .expect("Failed to register upstream_requests_total");
+        registry
+            .register(Box::new(upstream_in_flight.clone()))
+            .expect("Failed to register upstream_requests_in_flight");
+
+        Self {
+            registry,
+            http_requests,
+            http_latency,
+            facts,
+            fallback_facts,
+            unsupported_animals,
+            rejected_fast,
+            cache_lookups,
+            upstream_requests,
+            upstream_in_flight,
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Records a request to a route with the resulting status code and latency.
+    pub fn record_request(&self, route: &str, status: u16, seconds: f64) {
+        let status_class = format!("{}xx", status / 100);
+        self.http_requests
+            .with_label_values(&[route, &status_class])
+            .inc();
+        self.http_latency
+            .with_label_values(&[route])
+            .observe(seconds);
+    }
+
+    /// Records the outcome of serving a fact for an animal.
+    pub fn record_fact(&self, animal: &str, ok: bool) {
+        let outcome = if ok { "ok" } else { "error" };
+        self.facts.with_label_values(&[animal, outcome]).inc();
+    }
+
+    /// Records a bundled fallback fact served for an animal.
+    pub fn record_fallback_fact(&self, animal: &str) {
+        self.fallback_facts.with_label_values(&[animal]).inc();
+    }
+
+    /// Counts an unsupported animal as recently requested until the guard is dropped, when the
+    /// cache no longer holds it.
+    #[must_use]
+    pub fn record_unsupported_animal(&self) -> GaugeGuard {
+        GaugeGuard::new(self.unsupported_animals.clone())
+    }
+
+    /// Records a request rejected from the unsupported animal cache.
+    pub fn record_rejected_from_cache(&self) {
+        self.rejected_fast.inc();
+    }
+
+    /// Records a fact cache lookup: `hit`, `stale` or `miss`.
+    pub fn record_cache_lookup(&self, result: &str) {
+        self.cache_lookups.with_label_values(&[result]).inc();
+    }
+
+    /// Records an upstream fact request, counting it as in flight until the guard is dropped.
+    #[must_use]
+    pub fn start_upstream_request(&self) -> GaugeGuard {
+        self.upstream_requests.inc();
+        GaugeGuard::new(self.upstream_in_flight.clone())
+    }
+
+    /// Summarises the counters since startup, with the current number of upstream requests in
+    /// flight.
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            facts_served: total(&self.facts, "outcome", "ok"),
+            facts_failed: total(&self.facts, "outcome", "error"),
+            facts_fallback: sum(&self.fallback_facts),
+            cache_hits: total(&self.cache_lookups, "result", "hit"),
+            cache_stale_hits: total(&self.cache_lookups, "result", "stale"),
+            cache_misses: total(&self.cache_lookups, "result", "miss"),
+            upstream_requests: self.upstream_requests.get(),
+            upstream_in_flight: self.upstream_in_flight.get(),
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut buf = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buf)
+            .expect("Failed to encode metrics");
+        String::from_utf8(buf).expect("Metrics are not valid UTF-8")
+    }
+}
+
+/// Sums the counters having the label set to the value.
+fn total(counters: &IntCounterVec, label: &str, value: &str) -> u64 {
+    sum_where(counters, |metric| {
+        metric
+            .get_label()
+            .iter()
+            .any(|l| l.get_name() == label && l.get_value() == value)
+    })
+}
+
+/// Sums all the counters.
+fn sum(counters: &IntCounterVec) -> u64 {
+    sum_where(counters, |_| true)
+}
+
+/// Sums the counters matching the predicate.
+// integer counters only ever hold whole, non-negative values
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn sum_where(counters: &IntCounterVec, matches: impl Fn(&Metric) -> bool) -> u64 {
+    counters
+        .collect()
+        .iter()
+        .flat_map(MetricFamily::get_metric)
+        .filter(|metric| matches(metric))
+        .map(|metric| metric.get_counter().get_value())
+        .sum::<f64>() as u64
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The application counters since startup, for the `/stats` endpoint.
+#[derive(Debug, serde::Serialize)]
+pub struct Snapshot {
+    pub uptime_secs: u64,
+    pub facts_served: u64,
+    pub facts_failed: u64,
+    pub facts_fallback: u64,
+    pub cache_hits: u64,
+    pub cache_stale_hits: u64,
+    pub cache_misses: u64,
+    pub upstream_requests: u64,
+    pub upstream_in_flight: i64,
+}
+
+/// Middleware recording request counts and latencies per matched route.
+pub async fn track_metrics(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str)
+        .to_owned();
+    let start = Instant::now();
+    let res = next.run(req).await;
+    state
+        .metrics
+        .record_request(&route, res.status().as_u16(), start.elapsed().as_secs_f64());
+    res
+}
@@ -0,0 +1,90 @@
+// the `OpenApi` derive expands to code tripping this lint
+#![allow(clippy::needless_for_each)]
+
+use std::collections::HashMap;
+
+use utoipa::{OpenApi, ToSchema};
+
+use crate::handlers;
+
+/// The `OpenAPI` document for the service.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Animal Facts API", description = "Returns random animal facts."),
+    paths(
+        handlers::get_animal_fact,
+        handlers::get_animal_fact_by_path,
+        handlers::get_daily_fact,
+        handlers::get_random_fact,
+        handlers::post_fact_batch,
+        handlers::post_user_fact,
+        handlers::get_all_facts,
+        handlers::get_fact_feed,
+        handlers::get_fact_history,
+        handlers::get_fact_stream,
+        handlers::get_stats,
+        handlers::get_version,
+        handlers::health_check::health_check,
+        handlers::healthz
+    ),
+    components(schemas(
+        FactResponse,
+        FactsResponse,
+        handlers::BatchRequest,
+        handlers::UserFactRequest,
+        BatchItem,
+        ErrorResponse,
+        ErrorBody
+    )),
+    tags((name = "facts", description = "Animal facts"))
+)]
+pub struct ApiDoc;
+
+/// A single animal fact.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct FactResponse {
+    #[schema(example = "Three of the 12 dogs on the Titanic survived.")]
+    fact: String,
+    #[schema(example = "dog")]
+    animal: String,
+}
+
+/// Several facts about one animal, returned when `count` is greater than 1.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct FactsResponse {
+    facts: Vec<String>,
+    #[schema(example = "dog")]
+    animal: String,
+}
+
+/// The result for one animal of a batch request: a fact, or an error if it failed.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct BatchItem {
+    #[schema(example = "cat")]
+    animal: String,
+    fact: Option<String>,
+    error: Option<ErrorBody>,
+}
+
+/// An error response.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ErrorResponse {
+    error: ErrorBody,
+}
+
+/// The code and message describing an error.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ErrorBody {
+    #[schema(example = "unsupported_animal")]
+    code: String,
+    #[schema(example = "'dragon' is not a supported animal.")]
+    message: String,
+    /// Every failure of each invalid param, for a `validation_failed` error.
+    #[schema(example = json!({ "count": ["must be between 1 and 10"] }))]
+    errors: Option<HashMap<String, Vec<String>>>,
+}
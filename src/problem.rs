@@ -0,0 +1,174 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::{json, Value};
+
+use crate::config::ErrorFormat;
+use crate::state::AppState;
+
+/// The media type of an RFC 7807 problem document.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// The base of each problem's `type` URI, which is followed by the error code.
+const PROBLEM_TYPE_BASE: &str = "/problems/";
+
+/// The largest error body that is rewritten; error bodies are far smaller than this.
+const MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+/// Middleware rewriting JSON error responses as RFC 7807 problem documents, when configured to or
+/// when the client accepts `application/problem+json`. The request id becomes the `instance`.
+pub async fn problem_details(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if state.config.errors.format != ErrorFormat::Problem && !accepts_problem(req.headers()) {
+        return next.run(req).await;
+    }
+    let instance = req
+        .headers()
+        .get(&state.config.application.request_id_header)
+        .and_then(|id| id.to_str().ok())
+        .map(String::from);
+    let response = next.run(req).await;
+    if !is_json_error(&response) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = to_bytes(body, MAX_ERROR_BODY_BYTES).await else {
+        tracing::warn!("Failed to read error body to rewrite as a problem document");
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    };
+    let problem = serde_json::from_slice::<Value>(&bytes)
+        .ok()
+        .and_then(|value| to_problem(&value, parts.status.as_u16(), instance.as_deref()));
+    let Some(problem) = problem else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+    Response::from_parts(parts, Body::from(problem.to_string()))
+}
+
+/// Checks whether the `Accept` header lists `application/problem+json`.
+fn accepts_problem(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|range| {
+            range
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .eq_ignore_ascii_case(PROBLEM_JSON)
+        })
+}
+
+/// Checks whether the response is an uncompressed JSON error.
+fn is_json_error(response: &Response) -> bool {
+    let headers = response.headers();
+    (response.status().is_client_error() || response.status().is_server_error())
+        && !headers.contains_key(header::CONTENT_ENCODING)
+        && headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"))
+}
+
+/// Converts an `{"error": {"code", "message"}}` body into a problem document.
+fn to_problem(value: &Value, status: u16, instance: Option<&str>) -> Option<Value> {
+    let code = value["error"]["code"].as_str()?;
+    let mut problem = json!({
+        "type": format!("{PROBLEM_TYPE_BASE}{code}"),
+        "title": title(code),
+        "status": status,
+        "detail": value["error"]["message"],
+    });
+    if let Some(instance) = instance {
+        problem["instance"] = json!(instance);
+    }
+    if let Some(errors) = value["error"].get("errors") {
+        problem["errors"] = errors.clone();
+    }
+    if let Some(request_id) = value.get("request_id") {
+        problem["request_id"] = request_id.clone();
+    }
+    Some(problem)
+}
+
+/// A short, human-readable summary of the kind of problem an error code identifies.
+fn title(code: &str) -> &'static str {
+    match code {
+        "validation_failed" => "Invalid request parameters",
+        "unsupported_animal" => "Unsupported animal",
+        "too_many_animals" => "Too many animals",
+        "invalid_body" => "Invalid request body",
+        "not_acceptable" => "Not acceptable",
+        "no_matching_fact" => "No matching fact",
+        "upstream_unavailable" => "Animal API unreachable",
+        "upstream_error" => "Animal API error",
+        "upstream_read_failed" => "Animal API response unreadable",
+        "upstream_invalid_response" => "Invalid animal API response",
+        "upstream_timeout" => "Animal API timed out",
+        "upstream_connect_failed" => "Animal API connection failed",
+        "circuit_open" => "Animal API temporarily unavailable",
+        "missing_api_key" => "Missing API key",
+        "invalid_api_key" => "Invalid API key",
+        "rate_limited" => "Rate limit exceeded",
+        "overloaded" => "Service overloaded",
+        "request_timeout" => "Request timed out",
+        "invalid_request_id" => "Invalid request id",
+        "invalid_log_level" => "Invalid log level",
+        "log_level_unavailable" => "Log level unavailable",
+        "admin_disabled" => "Admin endpoints disabled",
+        "api_keys_disabled" => "API keys not configured",
+        "idempotency_key_in_use" => "Idempotency key in use",
+        "idempotency_key_reused" => "Idempotency key reused",
+        "internal_error" => "Internal error",
+        _ => "Request failed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, HeaderMap, HeaderValue};
+    use serde_json::json;
+
+    use super::{accepts_problem, to_problem};
+
+    #[test]
+    fn test_accepts_problem() {
+        let mut headers = HeaderMap::new();
+        assert!(!accepts_problem(&headers));
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/json, application/problem+json;q=0.9"),
+        );
+        assert!(accepts_problem(&headers));
+    }
+
+    #[test]
+    fn test_to_problem() {
+        let value = json!({ "error": { "code": "unsupported_animal", "message": "No dragons." } });
+
+        let problem = to_problem(&value, 400, Some("abc-123")).unwrap();
+        assert_eq!(
+            json!({
+                "type": "/problems/unsupported_animal",
+                "title": "Unsupported animal",
+                "status": 400,
+                "detail": "No dragons.",
+                "instance": "abc-123",
+            }),
+            problem
+        );
+        assert_eq!(None, to_problem(&json!({ "status": "down" }), 503, None));
+    }
+}
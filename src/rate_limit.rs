@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+use crate::config::ClientRateLimitSettings;
+use crate::request_id::with_request_id;
+use crate::state::AppState;
+
+/// A token bucket allowing bursts of up to `burst` requests, refilled at `per_second` tokens a
+/// second.
+pub struct TokenBucket {
+    per_second: f64,
+    burst: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    #[must_use]
+    pub fn new(per_second: u32, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            per_second: f64::from(per_second),
+            burst,
+            state: Mutex::new(BucketState {
+                tokens: burst,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token, or returns how long to wait until one is available.
+    pub fn try_acquire(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().expect("Rate limiter lock poisoned");
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.per_second).min(self.burst);
+        state.refilled_at = now;
+
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            Ok(())
+        } else if self.per_second > 0.0 {
+            Err(Duration::from_secs_f64(
+                (1.0 - state.tokens) / self.per_second,
+            ))
+        } else {
+            Err(Duration::MAX)
+        }
+    }
+
+    /// Whether the bucket has refilled completely, so forgetting it changes nothing.
+    fn is_full(&self) -> bool {
+        let state = self.state.lock().expect("Rate limiter lock poisoned");
+        let elapsed = state.refilled_at.elapsed().as_secs_f64();
+        state.tokens + elapsed * self.per_second >= self.burst
+    }
+}
+
+/// A client's token bucket, with when it was last used.
+struct ClientBucket {
+    bucket: Arc<TokenBucket>,
+    used_at: Instant,
+}
+
+/// A token bucket for each client, created on its first request.
+///
+/// Up to `max_clients` buckets are kept; once at capacity, refilled buckets are forgotten first,
+/// then the least recently used one.
+pub struct ClientRateLimiter {
+    per_second: u32,
+    burst: u32,
+    key_header: String,
+    trusted_proxy_hops: usize,
+    max_clients: usize,
+    buckets: Mutex<HashMap<String, ClientBucket>>,
+}
+
+impl ClientRateLimiter {
+    #[must_use]
+    pub fn new(settings: &ClientRateLimitSettings) -> Self {
+        Self {
+            per_second: settings.per_second,
+            burst: settings.burst,
+            key_header: settings.key_header.clone(),
+            trusted_proxy_hops: settings.trusted_proxy_hops,
+            max_clients: settings.max_clients.max(1),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Identifies the client by the address in the key header appended by the outermost trusted
+    /// proxy, `trusted_proxy_hops` from the right, or else by its peer address. Addresses further
+    /// left are set by the client, so can't be trusted.
+    #[must_use]
+    pub fn client_key(&self, headers: &HeaderMap, peer: Option<IpAddr>) -> String {
+        self.trusted_proxy_hops
+            .checked_sub(1)
+            .and_then(|skip| {
+                headers
+                    .get(self.key_header.as_str())?
+                    .to_str()
+                    .ok()?
+                    .rsplit(',')
+                    .nth(skip)
+            })
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(String::from)
+            .or_else(|| peer.map(|ip| ip.to_string()))
+            .unwrap_or_default()
+    }
+
+    /// Takes a token from the client's bucket, or returns how long to wait until one is
+    /// available.
+    pub fn try_acquire(&self, key: &str) -> Result<(), Duration> {
+        let bucket = {
+            let mut buckets = self.buckets.lock().expect("Rate limiter lock poisoned");
+            if !buckets.contains_key(key) && buckets.len() >= self.max_clients {
+                buckets.retain(|_, client| !client.bucket.is_full());
+                if buckets.len() >= self.max_clients {
+                    let lru = buckets
+                        .iter()
+                        .min_by_key(|(_, client)| client.used_at)
+                        .map(|(key, _)| key.clone());
+                    if let Some(lru) = lru {
+                        buckets.remove(&lru);
+                    }
+                }
+            }
+            let client = buckets
+                .entry(key.to_string())
+                .or_insert_with(|| ClientBucket {
+                    bucket: Arc::new(TokenBucket::new(self.per_second, self.burst)),
+                    used_at: Instant::now(),
+                });
+            client.used_at = Instant::now();
+            client.bucket.clone()
+        };
+        bucket.try_acquire()
+    }
+}
+
+/// Middleware rejecting requests with 429 Too Many Requests once the client's rate limit or the
+/// rate limit shared by all clients is exceeded.
+pub async fn rate_limit(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if let Some(limiter) = &state.client_rate_limiter {
+        let peer = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let key = limiter.client_key(req.headers(), peer);
+        if let Err(wait) = limiter.try_acquire(&key) {
+            tracing::warn!("Rate limit exceeded for client {key}");
+            return too_many_requests(wait);
+        }
+    }
+    let Some(bucket) = &state.rate_limiter else {
+        return next.run(req).await;
+    };
+    match bucket.try_acquire() {
+        Ok(()) => next.run(req).await,
+        Err(wait) => too_many_requests(wait),
+    }
+}
+
+/// A 429 Too Many Requests response asking the client to retry once `wait` has passed.
+fn too_many_requests(wait: Duration) -> Response {
+    let retry_after = wait.as_secs_f64().ceil().min(f64::from(u32::MAX)).max(1.0);
+    let value = json!({
+        "error": {
+            "code": "rate_limited",
+            "message": "Too many requests, please retry later.",
+        }
+    });
+    tracing::warn!("Rate limit exceeded, retry after {retry_after}s");
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after.to_string())],
+        Json(with_request_id(value)),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use axum::http::{HeaderMap, HeaderValue};
+
+    use super::{ClientRateLimiter, TokenBucket};
+    use crate::config::ClientRateLimitSettings;
+
+    #[test]
+    fn test_bucket_allows_burst_then_limits() {
+        let bucket = TokenBucket::new(1, 2);
+
+        assert!(bucket.try_acquire().is_ok());
+        assert!(bucket.try_acquire().is_ok());
+        assert!(bucket.try_acquire().is_err());
+    }
+
+    #[test]
+    fn test_client_key_takes_address_appended_by_trusted_proxy() {
+        let limiter = ClientRateLimiter::new(&ClientRateLimitSettings::default());
+        let peer = Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let mut headers = HeaderMap::new();
+        assert_eq!("127.0.0.1", limiter.client_key(&headers, peer));
+
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("spoofed, 203.0.113.7, 10.0.0.1"),
+        );
+        assert_eq!("10.0.0.1", limiter.client_key(&headers, peer));
+
+        let behind_two = ClientRateLimiter::new(&ClientRateLimitSettings {
+            trusted_proxy_hops: 2,
+            ..ClientRateLimitSettings::default()
+        });
+        assert_eq!("203.0.113.7", behind_two.client_key(&headers, peer));
+
+        let no_proxy = ClientRateLimiter::new(&ClientRateLimitSettings {
+            trusted_proxy_hops: 0,
+            ..ClientRateLimitSettings::default()
+        });
+        assert_eq!("127.0.0.1", no_proxy.client_key(&headers, peer));
+    }
+
+    #[test]
+    fn test_least_recently_used_client_is_forgotten_at_capacity() {
+        let limiter = ClientRateLimiter::new(&ClientRateLimitSettings {
+            per_second: 0,
+            burst: 1,
+            max_clients: 2,
+            ..ClientRateLimitSettings::default()
+        });
+
+        assert!(limiter.try_acquire("a").is_ok());
+        assert!(limiter.try_acquire("b").is_ok());
+        assert!(limiter.try_acquire("a").is_err());
+        assert!(limiter.try_acquire("c").is_ok());
+
+        // "b" was forgotten, while "a" is still limited
+        assert!(limiter.try_acquire("a").is_err());
+        assert!(limiter.try_acquire("b").is_ok());
+    }
+
+    #[test]
+    fn test_clients_are_limited_independently() {
+        let limiter = ClientRateLimiter::new(&ClientRateLimitSettings {
+            per_second: 0,
+            burst: 1,
+            ..ClientRateLimitSettings::default()
+        });
+
+        assert!(limiter.try_acquire("a").is_ok());
+        assert!(limiter.try_acquire("a").is_err());
+        assert!(limiter.try_acquire("b").is_ok());
+    }
+}
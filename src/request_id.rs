@@ -0,0 +1,86 @@
+use axum::{
+    extract::{Request, State},
+    http::HeaderName,
+    middleware::Next,
+    response::Response,
+};
+use serde_json::{json, Value};
+use tower_http::request_id::RequestId;
+
+/// The longest request id reused from a request.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    /// The id of the request being handled, while it is to be echoed in response bodies.
+    static REQUEST_ID: String;
+}
+
+/// Middleware making the request's id available to the JSON bodies built while handling it,
+/// when enabled, so clients can trace a response without reading its headers.
+pub async fn request_id_scope(State(enabled): State<bool>, req: Request, next: Next) -> Response {
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .filter(|_| enabled)
+        .map(String::from);
+    match request_id {
+        Some(request_id) => REQUEST_ID.scope(request_id, next.run(req)).await,
+        None => next.run(req).await,
+    }
+}
+
+/// Middleware dropping a request id that is too long or has characters other than ASCII letters,
+/// digits and `-_.:+/=`, so a new one is generated in its place, rather than being logged and
+/// echoed back as it is.
+pub async fn drop_invalid_request_id(
+    State(header): State<HeaderName>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let valid = req
+        .headers()
+        .get(&header)
+        .is_none_or(|id| is_valid_request_id(id.as_bytes()));
+    if !valid {
+        tracing::debug!("Replacing an invalid request id");
+        req.headers_mut().remove(&header);
+    }
+    next.run(req).await
+}
+
+/// Whether a request id is one to reuse.
+fn is_valid_request_id(id: &[u8]) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .iter()
+            .all(|c| c.is_ascii_alphanumeric() || b"-_.:+/=".contains(c))
+}
+
+/// Adds the id of the request being handled to a JSON object body as `request_id`, when request
+/// ids are echoed in bodies. Other bodies, such as bare facts, are returned as they are.
+#[must_use]
+pub fn with_request_id(mut value: Value) -> Value {
+    if let Value::Object(object) = &mut value {
+        if let Ok(request_id) = REQUEST_ID.try_with(Clone::clone) {
+            object.insert("request_id".into(), json!(request_id));
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_valid_request_id;
+
+    #[test]
+    fn test_is_valid_request_id() {
+        assert!(is_valid_request_id(b"0b5c0a3e-4d6e-4f0a-9f1e-3c2d1b0a9f8e"));
+        assert!(is_valid_request_id(b"trace:abc_123.4+/="));
+        assert!(!is_valid_request_id(b""));
+        assert!(!is_valid_request_id(b"has space"));
+        assert!(!is_valid_request_id(b"line\nbreak"));
+        assert!(!is_valid_request_id(&[b'a'; 129]));
+    }
+}
@@ -0,0 +1,98 @@
+use std::fmt::{Display, Formatter};
+use std::net::{Ipv4Addr, SocketAddr};
+
+use reqwest::Client;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+use crate::config::Settings;
+use crate::startup::run_until;
+use crate::state::AppState;
+
+/// The paths requested by the self-test, in order.
+const CHECKED_PATHS: [&str; 2] = ["/health-check", "/v1/fact?animal=cat"];
+
+/// The outcome of requesting one path during the self-test.
+pub struct Check {
+    pub path: &'static str,
+    pub status: Option<u16>,
+    pub detail: String,
+}
+
+impl Check {
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.status
+            .is_some_and(|status| (200..300).contains(&status))
+    }
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let outcome = if self.passed() { "PASS" } else { "FAIL" };
+        match self.status {
+            Some(status) => write!(f, "{outcome} {} {status} {}", self.path, self.detail),
+            None => write!(f, "{outcome} {} {}", self.path, self.detail),
+        }
+    }
+}
+
+/// Starts the service on a loopback port, requests the health check and a cat fact from it, then
+/// stops it, returning the outcome of each request.
+///
+/// The service is served over plain HTTP and the webhook is disabled, whatever the config says.
+/// The first configured API key, if any, is sent with each request.
+pub async fn selftest(mut settings: Settings) -> Vec<Check> {
+    settings.tls.cert_path.clear();
+    settings.tls.key_path.clear();
+    settings.webhook.url.clear();
+    let api_key = settings.auth.api_keys.first().cloned();
+
+    let listener = match TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).await {
+        Ok(listener) => listener,
+        Err(err) => return vec![failed(CHECKED_PATHS[0], &err)],
+    };
+    let addr = match listener.local_addr() {
+        Ok(addr) => addr,
+        Err(err) => return vec![failed(CHECKED_PATHS[0], &err)],
+    };
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let server = match run_until(listener, AppState::new(settings), async {
+        let _ = stop_rx.await;
+    }) {
+        Ok(server) => tokio::spawn(server),
+        Err(err) => return vec![failed(CHECKED_PATHS[0], &err)],
+    };
+
+    let client = Client::new();
+    let mut checks = vec![];
+    for path in CHECKED_PATHS {
+        let mut req = client.get(format!("http://{addr}{path}"));
+        if let Some(key) = &api_key {
+            req = req.header("X-API-Key", key);
+        }
+        let check = match req.send().await {
+            Ok(res) => Check {
+                path,
+                status: Some(res.status().as_u16()),
+                detail: res.text().await.unwrap_or_default(),
+            },
+            Err(err) => failed(path, &err),
+        };
+        checks.push(check);
+    }
+
+    drop(client);
+    let _ = stop_tx.send(());
+    let _ = server.await;
+    checks
+}
+
+/// A check that failed without getting a response.
+fn failed(path: &'static str, err: &impl Display) -> Check {
+    Check {
+        path,
+        status: None,
+        detail: err.to_string(),
+    }
+}
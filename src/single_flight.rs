@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+use futures::future::{BoxFuture, FutureExt, Shared};
+
+/// Coalesces concurrent calls with the same key into one, so identical requests arriving together
+/// share a single upstream call and all get its result.
+///
+/// A call is only shared while it is in flight; once it completes the next call with its key
+/// starts afresh.
+pub struct SingleFlight<K, V> {
+    calls: Mutex<HashMap<K, Shared<BoxFuture<'static, V>>>>,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self {
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K, V> SingleFlight<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Returns the result of the call in flight for the key, or makes the call with `call` when
+    /// there is none.
+    pub async fn run<F>(&self, key: K, call: impl FnOnce() -> F) -> V
+    where
+        F: Future<Output = V> + Send + 'static,
+    {
+        let shared = {
+            let mut calls = self.calls.lock().expect("Single flight lock poisoned");
+            calls
+                .entry(key.clone())
+                .or_insert_with(|| call().boxed().shared())
+                .clone()
+        };
+        let value = shared.await;
+        let mut calls = self.calls.lock().expect("Single flight lock poisoned");
+        if calls.get(&key).is_some_and(|call| call.peek().is_some()) {
+            calls.remove(&key);
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures::future::join_all;
+
+    use super::SingleFlight;
+
+    #[tokio::test]
+    async fn test_concurrent_calls_with_the_same_key_are_shared() {
+        let flights = SingleFlight::default();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let call = |key| {
+            let calls = calls.clone();
+            flights.run(key, move || async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                calls.fetch_add(1, Ordering::SeqCst) + 1
+            })
+        };
+        let results = join_all([call("cat"), call("cat"), call("dog")]).await;
+
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+        assert_eq!(results[0], results[1]);
+        assert_ne!(results[0], results[2]);
+
+        // a completed call isn't shared with later ones
+        assert_eq!(3, call("cat").await);
+    }
+}
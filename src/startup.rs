@@ -1,59 +1,750 @@
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::{pin, Pin};
+use std::time::Duration;
+
 use tokio::net::TcpListener;
+use tokio::sync::oneshot;
 
-use axum::http::Method;
-use axum::routing::IntoMakeService;
-use axum::{http::Request, routing::get, serve, serve::Serve, Router};
-use reqwest::Client;
-use tower::ServiceBuilder;
-use tower_http::cors::{Any, CorsLayer};
+use axum::body::Body;
+use axum::error_handling::HandleErrorLayer;
+use axum::extract::State;
+use axum::http::{header, HeaderName, HeaderValue, Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::IntoResponse;
+use axum::BoxError;
+use axum::{
+    http::Request,
+    routing::{any, get, post, put, MethodRouter},
+    Router,
+};
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use serde_json::json;
+use socket2::{Domain, Protocol, Socket, Type};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::timeout::error::Elapsed;
+use tower::{Service, ServiceBuilder};
+use tower_http::compression::{
+    predicate::{NotForContentType, SizeAbove},
+    CompressionLayer, Predicate,
+};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::set_header::SetResponseHeaderLayer;
 use tower_http::{
     request_id::{MakeRequestId, RequestId},
-    trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer},
+    trace::{DefaultOnResponse, MakeSpan, TraceLayer},
     ServiceBuilderExt,
 };
-use tracing::Level;
+use tracing::Span;
 use uuid::Uuid;
 
-use crate::handlers::{get_animal_fact, health_check};
+use crate::access_log::{access_log, AccessLog};
+use crate::auth::{require_admin_key, require_api_key, require_configured_api_key};
+use crate::body_log::log_bodies;
+use crate::config::{ApplicationSettings, CorsSettings, ResponseHeaderSettings, ServerSettings};
+use crate::handlers::{
+    get_all_facts, get_animal_fact, get_animal_fact_by_path, get_animals, get_daily_fact,
+    get_fact_feed, get_fact_history, get_fact_stream, get_graphiql, get_metrics, get_openapi,
+    get_random_fact, get_stats, get_swagger_ui, get_version, head_animal_fact,
+    head_animal_fact_by_path, health_check, healthz, post_cache_flush, post_fact_batch,
+    post_graphql, post_user_fact, put_log_level, readiness_check, GRAPHQL_PATH, OPENAPI_PATH,
+};
+use crate::metrics::track_metrics;
+use crate::problem::problem_details;
+use crate::rate_limit::rate_limit;
+use crate::request_id::{drop_invalid_request_id, request_id_scope, with_request_id};
+use crate::state::AppState;
+use crate::tls::{load_tls_config, TlsError};
+use crate::warmer::spawn_cache_warmer;
+use crate::webhook::{daily_webhook_time, spawn_daily_webhook};
+
+/// The prefix of the canonical, versioned API routes.
+pub const API_V1_PREFIX: &str = "/v1";
+
+/// The default back-off suggested to clients on 429 and 503 responses, in seconds.
+const RETRY_AFTER_SECS: u32 = 1;
 
-pub type App = Serve<IntoMakeService<Router>, Router>;
+/// The length of the queue of connections waiting to be accepted.
+const LISTEN_BACKLOG: i32 = 1024;
 
+/// The running server, resolving once it has shut down.
+pub type App = Pin<Box<dyn Future<Output = std::io::Result<()>> + Send>>;
+
+/// Why the server's listener couldn't be bound.
+#[derive(Debug, thiserror::Error)]
+pub enum BindError {
+    #[error("Invalid bind address '{0}': {1}")]
+    InvalidAddress(String, #[source] io::Error),
+
+    #[error("Failed to {action} for {addr}: {source}")]
+    Socket {
+        action: &'static str,
+        addr: SocketAddr,
+        #[source]
+        source: io::Error,
+    },
+}
+
+/// Generates a UUID request id for requests without one, unless request ids are strict, when
+/// requests must bring their own.
 #[derive(Clone)]
-struct MakeRequestUuid;
+struct MakeRequestUuid {
+    strict: bool,
+}
 
 impl MakeRequestId for MakeRequestUuid {
     fn make_request_id<B>(&mut self, _: &Request<B>) -> Option<RequestId> {
+        if self.strict {
+            return None;
+        }
         let request_id = Uuid::new_v4().to_string();
 
         Some(RequestId::new(request_id.parse().unwrap()))
     }
 }
 
-pub fn run(listener: TcpListener) -> hyper::Result<App> {
-    let client = Client::new();
-    let app = Router::new()
-        .route("/health-check", get(health_check))
-        .route("/fact", get(get_animal_fact))
+/// Opens the span for each request, recording its request id as a field of its own so that it
+/// appears on every log line emitted while handling the request.
+#[derive(Clone)]
+struct MakeRequestSpan {
+    request_id_header: HeaderName,
+}
+
+impl<B> MakeSpan<B> for MakeRequestSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        let request_id = request
+            .headers()
+            .get(&self.request_id_header)
+            .and_then(|id| id.to_str().ok())
+            .unwrap_or_default();
+
+        tracing::info_span!(
+            "request",
+            method = %request.method(),
+            uri = %request.uri(),
+            version = ?request.version(),
+            headers = ?request.headers(),
+            request_id,
+        )
+    }
+}
+
+/// Binds the server's listener to the configured host and port, setting the configured socket
+/// options first. A host name, such as `localhost`, is bound at the first address it resolves to.
+pub async fn bind_listener(settings: &ApplicationSettings) -> Result<TcpListener, BindError> {
+    let invalid = |e| BindError::InvalidAddress(settings.host.clone(), e);
+    let addr = tokio::net::lookup_host((settings.host.as_str(), settings.port))
+        .await
+        .map_err(invalid)?
+        .next()
+        .ok_or_else(|| invalid(io::Error::new(io::ErrorKind::NotFound, "no address found")))?;
+    let socket_err = |action| {
+        move |source| BindError::Socket {
+            action,
+            addr,
+            source,
+        }
+    };
+
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))
+        .map_err(socket_err("create socket"))?;
+    socket
+        .set_reuse_address(settings.reuse_address)
+        .map_err(socket_err("set SO_REUSEADDR"))?;
+    if settings.reuse_port {
+        set_reuse_port(&socket).map_err(socket_err("set SO_REUSEPORT"))?;
+    }
+    socket
+        .set_nonblocking(true)
+        .map_err(socket_err("make socket non-blocking"))?;
+    socket.bind(&addr.into()).map_err(socket_err("bind"))?;
+    socket
+        .listen(LISTEN_BACKLOG)
+        .map_err(socket_err("listen"))?;
+    TcpListener::from_std(socket.into()).map_err(socket_err("register listener"))
+}
+
+#[cfg(unix)]
+fn set_reuse_port(socket: &Socket) -> io::Result<()> {
+    socket.set_reuse_port(true)
+}
+
+#[cfg(not(unix))]
+fn set_reuse_port(_: &Socket) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "SO_REUSEPORT is only supported on Unix",
+    ))
+}
+
+/// Runs the server until a SIGINT or SIGTERM is received.
+pub fn run(listener: TcpListener, state: AppState) -> Result<App, TlsError> {
+    run_until(listener, state, shutdown_signal())
+}
+
+/// Runs the server until `shutdown` resolves, then lets in-flight requests drain for up to the
+/// configured grace period. HTTPS is served when TLS is configured, otherwise plain HTTP. The
+/// daily webhook and the cache warmer, when configured, run alongside the server.
+///
+/// # Panics
+///
+/// Panics if the daily webhook is misconfigured.
+pub fn run_until<F>(listener: TcpListener, state: AppState, shutdown: F) -> Result<App, TlsError>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let grace_period = Duration::from_secs(state.config.application.shutdown_grace_secs);
+    let tls = load_tls_config(&state.config.tls, state.config.server.http2)?;
+    let webhook_at = daily_webhook_time(&state);
+    let app = app(state.clone());
+    let settings = state.config.server.clone();
+    tracing::info!(
+        "Enabled features: [{}]",
+        state.config.features.enabled().join(", ")
+    );
+
+    let (draining_tx, draining_rx) = oneshot::channel();
+    let shutdown = async move {
+        shutdown.await;
+        tracing::info!("Shutdown started, draining in-flight requests");
+        let _ = draining_tx.send(());
+    };
+    let server: App = match tls {
+        Some(tls) => Box::pin(serve_tls(listener, app, tls, settings, shutdown)),
+        None => Box::pin(serve_http(listener, app, settings, shutdown)),
+    };
+
+    Ok(Box::pin(async move {
+        let tasks = [
+            webhook_at.map(|at| spawn_daily_webhook(&state, at)),
+            spawn_cache_warmer(&state),
+        ];
+        let grace_period_elapsed = async {
+            // the sender is only dropped without sending once the server has already stopped
+            if draining_rx.await.is_err() {
+                std::future::pending::<()>().await;
+            }
+            tokio::time::sleep(grace_period).await;
+        };
+        let res = tokio::select! {
+            res = server => {
+                tracing::info!("Draining complete, server stopped");
+                res
+            }
+            () = grace_period_elapsed => {
+                tracing::warn!("Grace period elapsed, dropping remaining connections");
+                Ok(())
+            }
+        };
+        for task in tasks.into_iter().flatten() {
+            task.abort();
+        }
+        res
+    }))
+}
+
+/// Serves HTTPS with the connection settings until `shutdown` resolves, then stops accepting
+/// connections and waits for in-flight requests to finish.
+async fn serve_tls(
+    listener: TcpListener,
+    app: Router,
+    tls: RustlsConfig,
+    settings: ServerSettings,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()> {
+    let handle = Handle::new();
+    let mut server = axum_server::from_tcp_rustls(listener.into_std()?, tls).handle(handle.clone());
+    *server.http_builder() = http_builder(&settings);
+    let server = server.serve(app.into_make_service_with_connect_info::<SocketAddr>());
+    let mut server = pin!(server);
+    tokio::select! {
+        res = &mut server => return res,
+        () = shutdown => handle.graceful_shutdown(None),
+    }
+    server.await
+}
+
+/// Serves plain HTTP with the connection settings until `shutdown` resolves, then stops accepting
+/// connections and waits for in-flight requests to finish.
+async fn serve_http(
+    listener: TcpListener,
+    app: Router,
+    settings: ServerSettings,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()> {
+    let builder = http_builder(&settings);
+    let graceful = GracefulShutdown::new();
+    let mut make_service = app.into_make_service_with_connect_info::<SocketAddr>();
+    let mut shutdown = pin!(shutdown);
+    loop {
+        let (stream, remote) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    tracing::warn!("Failed to accept a connection: {e}");
+                    continue;
+                }
+            },
+            () = &mut shutdown => break,
+        };
+        let service = make_service
+            .call(remote)
+            .await
+            .unwrap_or_else(|e| match e {});
+        let conn = builder
+            .serve_connection(TokioIo::new(stream), TowerToHyperService::new(service))
+            .into_owned();
+        let conn = graceful.watch(conn);
+        tokio::spawn(async move {
+            if let Err(e) = conn.await {
+                tracing::debug!("Connection from {remote} failed: {e}");
+            }
+        });
+    }
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
+
+/// Builds the connection settings shared by plain HTTP and HTTPS.
+fn http_builder(settings: &ServerSettings) -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder.http1().keep_alive(settings.keep_alive);
+    if settings.keep_alive_interval_secs > 0 {
+        builder
+            .http2()
+            .timer(TokioTimer::new())
+            .keep_alive_interval(Duration::from_secs(settings.keep_alive_interval_secs))
+            .keep_alive_timeout(Duration::from_secs(settings.keep_alive_timeout_secs));
+    }
+    if settings.http2 {
+        builder
+    } else {
+        builder.http1_only()
+    }
+}
+
+/// Middleware rejecting requests without a UUID in the request id header, when request ids are
+/// strict and so the header is given.
+async fn require_request_id(
+    State(header): State<Option<HeaderName>>,
+    req: Request<Body>,
+    next: Next,
+) -> axum::response::Response {
+    let Some(header) = header else {
+        return next.run(req).await;
+    };
+    let valid = req
+        .headers()
+        .get(&header)
+        .and_then(|id| id.to_str().ok())
+        .is_some_and(|id| Uuid::parse_str(id).is_ok());
+    if valid {
+        return next.run(req).await;
+    }
+    let value = json!({
+        "error": {
+            "code": "invalid_request_id",
+            "message": format!("A UUID request id is required in the {header} header."),
+        }
+    });
+    (StatusCode::BAD_REQUEST, axum::Json(with_request_id(value))).into_response()
+}
+
+/// Builds the application's routes and middleware.
+#[allow(clippy::too_many_lines)]
+fn app(state: AppState) -> Router {
+    let settings = state.config.clone();
+    let request_id_header: HeaderName = settings
+        .application
+        .request_id_header
+        .parse()
+        .unwrap_or_else(|e| {
+            panic!(
+                "Invalid request id header '{}': {e}",
+                settings.application.request_id_header
+            )
+        });
+    let strict_request_id = settings.application.strict_request_id;
+    let compress_when = SizeAbove::new(settings.compression.min_size_bytes)
+        .and(NotForContentType::GRPC)
+        .and(NotForContentType::IMAGES)
+        .and(NotForContentType::SSE);
+    // probes are kept out of the compression layer, which drops `content-length`
+    let probes = Router::new()
+        .route("/health-check", get(health_check).head(health_check))
+        .route("/ready", get(readiness_check))
+        .route("/healthz", get(healthz));
+    let api = api_router(&state);
+    let admin = Router::new()
+        .route("/admin/log-level", put(put_log_level))
+        .route("/admin/cache/flush", post(post_cache_flush))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_admin_key,
+        ));
+    let router = Router::new()
+        .nest(API_V1_PREFIX, api.clone())
+        .merge(api.layer(middleware::from_fn(deprecated_alias)))
+        .merge(admin)
+        .route("/metrics", get(get_metrics))
+        .route("/stats", get(get_stats))
+        .route("/version", get(get_version))
+        .route(
+            GRAPHQL_PATH,
+            feature(
+                settings.features.graphql,
+                with_timeout(
+                    protect(&state, post(post_graphql)),
+                    settings.limits.route_timeout_ms,
+                )
+                .get(get_graphiql),
+            ),
+        )
+        .route(OPENAPI_PATH, get(get_openapi))
+        .route("/swagger-ui", get(get_swagger_ui))
+        // bodies are logged before they are compressed
+        .layer(middleware::from_fn_with_state(
+            settings.logging.clone(),
+            log_bodies,
+        ))
+        // probes are merged afterwards so they still answer while the service is saturated
         .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods([Method::GET]),
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_overload))
+                .load_shed()
+                .layer(GlobalConcurrencyLimitLayer::new(
+                    settings.limits.max_concurrent_requests,
+                ))
+                .layer(RequestBodyLimitLayer::new(settings.limits.max_body_bytes)),
         )
+        .layer(middleware::map_response(ensure_retry_after))
+        .layer(middleware::from_fn_with_state(
+            strict_request_id.then(|| request_id_header.clone()),
+            require_request_id,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            problem_details,
+        ))
+        // outside the error rewriting layers, which only read uncompressed bodies
+        .layer(CompressionLayer::new().compress_when(compress_when))
+        .merge(probes)
+        .route_layer(middleware::from_fn_with_state(state.clone(), track_metrics))
+        .layer(cors_layer(&settings.cors))
+        // inside the request id layers, so the id is set before the line is written
+        .layer(middleware::from_fn_with_state(
+            AccessLog::from_settings(&settings.logging, request_id_header.clone())
+                .unwrap_or_else(|e| panic!("Cannot open the access log: {e}")),
+            access_log,
+        ))
+        // inside the request id layers, so the id is set for the bodies built in the scope
+        .layer(middleware::from_fn_with_state(
+            settings.application.request_id_in_body,
+            request_id_scope,
+        ))
         .layer(
             ServiceBuilder::new()
-                .set_x_request_id(MakeRequestUuid)
+                .set_request_id(
+                    request_id_header.clone(),
+                    MakeRequestUuid {
+                        strict: strict_request_id,
+                    },
+                )
                 .layer(
                     TraceLayer::new_for_http()
-                        .make_span_with(
-                            DefaultMakeSpan::new()
-                                .include_headers(true)
-                                .level(Level::INFO),
-                        )
+                        .make_span_with(MakeRequestSpan {
+                            request_id_header: request_id_header.clone(),
+                        })
                         .on_response(DefaultOnResponse::new().include_headers(true)),
                 )
-                .propagate_x_request_id(),
+                .propagate_request_id(request_id_header.clone()),
+        )
+        // outside the request id layers, so an invalid id is replaced by a generated one
+        .layer(middleware::from_fn_with_state(
+            request_id_header,
+            drop_invalid_request_id,
+        ))
+        .with_state(state);
+    with_response_headers(router, &settings.response_headers)
+}
+
+/// The security headers added to responses that don't set them, unless disabled.
+const SECURITY_HEADERS: [(HeaderName, &str); 3] = [
+    (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+    (header::X_FRAME_OPTIONS, "DENY"),
+    (header::REFERRER_POLICY, "no-referrer"),
+];
+
+/// Sets the configured static headers on every response, then the security headers on those
+/// still without them, so a configured header takes precedence over a security default.
+fn with_response_headers(mut router: Router, settings: &ResponseHeaderSettings) -> Router {
+    for (name, value) in &settings.headers {
+        let name: HeaderName = name
+            .parse()
+            .unwrap_or_else(|e| panic!("Invalid response header name '{name}': {e}"));
+        let value = HeaderValue::from_str(value)
+            .unwrap_or_else(|e| panic!("Invalid value for response header '{name}': {e}"));
+        router = router.layer(SetResponseHeaderLayer::overriding(name, value));
+    }
+    if settings.security_headers {
+        for (name, value) in SECURITY_HEADERS {
+            router = router.layer(SetResponseHeaderLayer::if_not_present(
+                name,
+                HeaderValue::from_static(value),
+            ));
+        }
+    }
+    router
+}
+
+/// Builds the versioned API routes, which are also served unprefixed during the deprecation
+/// window. Fact routes are rate limited and require an API key when keys are configured, and
+/// every route but the long-lived fact stream is bounded by a timeout.
+fn api_router(state: &AppState) -> Router<AppState> {
+    let limits = &state.config.limits;
+    let features = &state.config.features;
+    let protect = |route| protect(state, route);
+    let submit = if features.submissions {
+        post(post_user_fact)
+            .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                require_configured_api_key,
+            ))
+    } else {
+        post(feature_disabled)
+    };
+    Router::new()
+        .route(
+            "/fact",
+            with_timeout(
+                protect(get(get_animal_fact).head(head_animal_fact)).merge(submit),
+                limits.fact_timeout_ms,
+            ),
+        )
+        .route(
+            "/fact/daily",
+            with_timeout(protect(get(get_daily_fact)), limits.route_timeout_ms),
+        )
+        .route(
+            "/fact/random",
+            with_timeout(protect(get(get_random_fact)), limits.route_timeout_ms),
         )
-        .with_state(client);
+        .route(
+            "/fact/batch",
+            feature(
+                features.batch,
+                with_timeout(protect(post(post_fact_batch)), limits.route_timeout_ms),
+            ),
+        )
+        .route(
+            "/fact/feed",
+            feature(
+                features.feed,
+                with_timeout(protect(get(get_fact_feed)), limits.route_timeout_ms),
+            ),
+        )
+        .route(
+            "/fact/history",
+            feature(
+                features.history,
+                with_timeout(protect(get(get_fact_history)), limits.route_timeout_ms),
+            ),
+        )
+        .route(
+            "/fact/stream",
+            feature(features.stream, protect(get(get_fact_stream))),
+        )
+        // the fixed routes above take precedence over the animal path segment, even when disabled
+        .route(
+            "/fact/:animal",
+            with_timeout(
+                protect(get(get_animal_fact_by_path).head(head_animal_fact_by_path)),
+                limits.fact_timeout_ms,
+            ),
+        )
+        .route(
+            "/facts/all",
+            with_timeout(protect(get(get_all_facts)), limits.route_timeout_ms),
+        )
+        .route(
+            "/animals",
+            with_timeout(get(get_animals), limits.route_timeout_ms),
+        )
+}
+
+/// Serves the route when its feature is enabled, otherwise answers it with a 404 as if it didn't
+/// exist. Disabled routes are still registered, so they aren't taken for an animal path segment.
+fn feature(enabled: bool, route: MethodRouter<AppState>) -> MethodRouter<AppState> {
+    if enabled {
+        route
+    } else {
+        any(feature_disabled)
+    }
+}
+
+/// Answers a request to a disabled feature's route with a 404, as if it didn't exist.
+async fn feature_disabled() -> axum::response::Response {
+    let value = json!({
+        "error": {
+            "code": "not_found",
+            "message": "This endpoint is disabled.",
+        }
+    });
+    (StatusCode::NOT_FOUND, axum::Json(with_request_id(value))).into_response()
+}
+
+/// Rate limits the route and requires an API key for it, when configured.
+fn protect(state: &AppState, route: MethodRouter<AppState>) -> MethodRouter<AppState> {
+    route
+        .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_api_key,
+        ))
+}
+
+/// Fails requests to the route with a 504 once they have run for `timeout_ms`, unless it is 0.
+fn with_timeout(route: MethodRouter<AppState>, timeout_ms: u64) -> MethodRouter<AppState> {
+    if timeout_ms == 0 {
+        return route;
+    }
+    route.layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(handle_timeout))
+            .timeout(Duration::from_millis(timeout_ms)),
+    )
+}
+
+/// Marks responses to the unprefixed API aliases as deprecated, linking to the canonical route.
+async fn deprecated_alias(request: Request<Body>, next: Next) -> axum::response::Response {
+    let successor = format!(
+        "<{API_V1_PREFIX}{}>; rel=\"successor-version\"",
+        request.uri().path()
+    );
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(header::LINK, link);
+    }
+    response
+}
+
+/// Adds a `Retry-After` to 429 and 503 responses that don't already carry one, so clients always
+/// know when to back off.
+async fn ensure_retry_after(mut response: axum::response::Response) -> axum::response::Response {
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+        response
+            .headers_mut()
+            .entry(header::RETRY_AFTER)
+            .or_insert(HeaderValue::from(RETRY_AFTER_SECS));
+    }
+    response
+}
 
-    Ok(serve(listener, app.into_make_service()))
+/// Sheds requests arriving while the concurrency limit is reached with a 503.
+async fn handle_overload(err: BoxError) -> (StatusCode, axum::Json<serde_json::Value>) {
+    tracing::warn!("Shedding request: {err}");
+    let value = json!({
+        "error": {
+            "code": "overloaded",
+            "message": "Too many concurrent requests, try again later.",
+        }
+    });
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        axum::Json(with_request_id(value)),
+    )
+}
+
+/// Fails a request that ran past its route's timeout with a 504.
+async fn handle_timeout(err: BoxError) -> (StatusCode, axum::Json<serde_json::Value>) {
+    if !err.is::<Elapsed>() {
+        tracing::error!("Request failed: {err}");
+        let value = json!({ "error": { "code": "internal_error", "message": "Internal error." } });
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            axum::Json(with_request_id(value)),
+        );
+    }
+    tracing::warn!("Request timed out");
+    let value = json!({
+        "error": {
+            "code": "request_timeout",
+            "message": "The request took too long to complete.",
+        }
+    });
+    (
+        StatusCode::GATEWAY_TIMEOUT,
+        axum::Json(with_request_id(value)),
+    )
+}
+
+/// Builds the CORS layer from config, allowing any origin when no origins are listed.
+fn cors_layer(cors: &CorsSettings) -> CorsLayer {
+    let allow_origin = if cors.allowed_origins.is_empty() {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(cors.allowed_origins.iter().map(|origin| {
+            HeaderValue::from_str(origin)
+                .unwrap_or_else(|e| panic!("Invalid CORS origin '{origin}': {e}"))
+        }))
+    };
+    let methods: Vec<Method> = cors
+        .allowed_methods
+        .iter()
+        .map(|method| {
+            method
+                .parse()
+                .unwrap_or_else(|e| panic!("Invalid CORS method '{method}': {e}"))
+        })
+        .collect();
+    let headers: Vec<HeaderName> = cors
+        .allowed_headers
+        .iter()
+        .map(|header| {
+            header
+                .parse()
+                .unwrap_or_else(|e| panic!("Invalid CORS header '{header}': {e}"))
+        })
+        .collect();
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(methods)
+        .allow_headers(headers)
+}
+
+/// Resolves when a Ctrl+C (SIGINT) or, on Unix, a SIGTERM is received.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {},
+        () = terminate => {},
+    }
 }
@@ -0,0 +1,293 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::{redirect, Client, NoProxy, Proxy};
+use tokio::sync::Semaphore;
+
+use crate::cache::{FactStore, MemoryStore, RejectedAnimals};
+use crate::circuit_breaker::CircuitBreakers;
+use crate::config::{ApiSettings, CacheBackend, ProxySettings, Selection, Settings};
+use crate::handlers::{Animal, AnimalWeights, ConfiguredAnimal, ErrorKind, Fact, HealthReports};
+use crate::history::{FactHistory, FeedDates};
+use crate::idempotency::IdempotencyKeys;
+use crate::latency::UpstreamLatencies;
+use crate::metrics::Metrics;
+use crate::rate_limit::{ClientRateLimiter, TokenBucket};
+use crate::single_flight::SingleFlight;
+use crate::telemetry::LogLevelHandle;
+use crate::upstream_client::UpstreamClient;
+use crate::upstream_headers::UpstreamHeaders;
+use crate::user_facts::{MemoryUserFacts, UserFactStore};
+
+/// The most redirects followed for an upstream request, as with the default redirect policy.
+const MAX_REDIRECTS: usize = 10;
+
+/// The most feed items whose first listing is remembered.
+const FEED_DATES_CAPACITY: usize = 1000;
+
+/// The upstream fetches made on cache misses that are in flight, by animal and selection.
+pub type FactFetches = SingleFlight<(&'static str, Selection), Result<Fact, ErrorKind>>;
+
+/// The state shared by all handlers and middleware.
+#[derive(Clone)]
+pub struct AppState {
+    pub client: Arc<UpstreamClient>,
+    /// The headers configured for each upstream origin, sent with every request to it.
+    pub upstream_headers: Arc<UpstreamHeaders>,
+    pub upstream_permits: Arc<Semaphore>,
+    pub config: Arc<Settings>,
+    pub cache: Arc<dyn FactStore<Fact>>,
+    pub in_flight: Arc<FactFetches>,
+    pub user_facts: Arc<dyn UserFactStore>,
+    /// The animals supported through `api.animals`, on top of the compiled ones.
+    pub configured_animals: Arc<[ConfiguredAnimal]>,
+    /// The weights random animals are chosen with, one for each supported animal.
+    pub animal_weights: Arc<AnimalWeights>,
+    pub idempotency_keys: Arc<IdempotencyKeys>,
+    pub rejected: Arc<RejectedAnimals>,
+    pub breakers: Arc<CircuitBreakers>,
+    pub latencies: Arc<UpstreamLatencies>,
+    pub health_reports: Arc<HealthReports>,
+    pub history: Arc<FactHistory>,
+    pub feed_dates: Arc<FeedDates>,
+    pub metrics: Arc<Metrics>,
+    pub rate_limiter: Option<Arc<TokenBucket>>,
+    pub client_rate_limiter: Option<Arc<ClientRateLimiter>>,
+    pub api_keys: Arc<HashSet<String>>,
+    pub admin_api_keys: Arc<HashSet<String>>,
+    pub log_level: Option<LogLevelHandle>,
+}
+
+impl AppState {
+    /// Builds the application state from the loaded config.
+    #[must_use]
+    pub fn new(settings: Settings) -> Self {
+        let client = UpstreamClient::new(&settings.api)
+            .unwrap_or_else(|e| panic!("Failed to build HTTP client: {e}"));
+        let upstream_headers = UpstreamHeaders::resolve(&settings.api.headers)
+            .unwrap_or_else(|e| panic!("Invalid upstream headers: {e}"));
+        let cache = fact_store(&settings);
+        let rate_limiter = settings.rate_limit.enabled.then(|| {
+            Arc::new(TokenBucket::new(
+                settings.rate_limit.per_second,
+                settings.rate_limit.burst,
+            ))
+        });
+        let per_client = &settings.rate_limit.per_client;
+        let client_rate_limiter = per_client
+            .enabled
+            .then(|| Arc::new(ClientRateLimiter::new(per_client)));
+        let rejected = RejectedAnimals::new(
+            Duration::from_secs(settings.cache.rejected_ttl_secs),
+            settings.cache.rejected_capacity,
+        );
+        let breakers = CircuitBreakers::new(
+            settings.circuit_breaker.failure_threshold,
+            Duration::from_secs(settings.circuit_breaker.cooldown_secs),
+        );
+        let latencies = UpstreamLatencies::new(
+            settings.api.latency_smoothing,
+            Duration::from_millis(settings.api.timeout_ms),
+        );
+        let configured_animals = settings
+            .api
+            .animals
+            .keys()
+            .map(|name| ConfiguredAnimal::new(name))
+            .collect::<Arc<[_]>>();
+        let animal_weights = AnimalWeights::new(
+            &Animal::supported_with(&configured_animals),
+            &settings.facts.animal_weights,
+        )
+        .unwrap_or_else(|e| panic!("Invalid animal weights: {e}"));
+        let user_facts = MemoryUserFacts::new(settings.user_facts.capacity);
+        let idempotency_keys = IdempotencyKeys::new(
+            Duration::from_secs(settings.idempotency.ttl_secs),
+            settings.idempotency.capacity,
+        );
+        let history = FactHistory::new(settings.history.capacity);
+        let api_keys = settings.auth.api_keys.iter().cloned().collect();
+        let admin_api_keys = settings.auth.admin_api_keys.iter().cloned().collect();
+
+        let upstream_permits = Semaphore::new(settings.limits.max_concurrent_upstream_calls.max(1));
+
+        Self {
+            client: Arc::new(client),
+            upstream_headers: Arc::new(upstream_headers),
+            upstream_permits: Arc::new(upstream_permits),
+            config: Arc::new(settings),
+            cache,
+            in_flight: Arc::default(),
+            user_facts: Arc::new(user_facts),
+            configured_animals,
+            animal_weights: Arc::new(animal_weights),
+            idempotency_keys: Arc::new(idempotency_keys),
+            rejected: Arc::new(rejected),
+            breakers: Arc::new(breakers),
+            latencies: Arc::new(latencies),
+            health_reports: Arc::default(),
+            history: Arc::new(history),
+            feed_dates: Arc::new(FeedDates::new(FEED_DATES_CAPACITY)),
+            metrics: Arc::new(Metrics::new()),
+            rate_limiter,
+            client_rate_limiter,
+            api_keys: Arc::new(api_keys),
+            admin_api_keys: Arc::new(admin_api_keys),
+            log_level: None,
+        }
+    }
+
+    /// Lets the log level be changed through the admin API with the subscriber's handle.
+    #[must_use]
+    pub fn with_log_level(mut self, handle: LogLevelHandle) -> Self {
+        self.log_level = Some(handle);
+        self
+    }
+}
+
+/// Builds the client for calling the upstream APIs, with its timeouts, connection pool,
+/// `User-Agent` and proxy taken from the config.
+///
+/// # Errors
+///
+/// Returns an error if the proxy URL is malformed or the client's TLS backend can't be
+/// initialised.
+pub fn http_client(api: &ApiSettings) -> reqwest::Result<Client> {
+    let mut builder = Client::builder()
+        .timeout(Duration::from_millis(api.timeout_ms))
+        .connect_timeout(Duration::from_millis(api.connect_timeout_ms))
+        .pool_max_idle_per_host(api.pool_max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(api.pool_idle_timeout_secs))
+        .user_agent(&api.user_agent);
+    if !api.proxy.url.is_empty() {
+        builder = builder.proxy(proxy(&api.proxy)?);
+    }
+    if !api.headers.is_empty() {
+        builder = builder.redirect(redirect::Policy::custom(same_origin_redirect));
+    }
+    builder.build()
+}
+
+/// Follows a redirect only if it stays on the same origin, so the headers configured for an
+/// upstream are never sent anywhere else. A redirect elsewhere is returned as the response.
+fn same_origin_redirect(attempt: redirect::Attempt) -> redirect::Action {
+    if attempt.previous().len() > MAX_REDIRECTS {
+        return attempt.error("too many redirects");
+    }
+    let same_origin = attempt
+        .previous()
+        .last()
+        .is_some_and(|previous| previous.origin() == attempt.url().origin());
+    if same_origin {
+        attempt.follow()
+    } else {
+        attempt.stop()
+    }
+}
+
+/// Builds the proxy for all upstream requests from the config.
+fn proxy(settings: &ProxySettings) -> reqwest::Result<Proxy> {
+    let mut proxy = Proxy::all(&settings.url)?;
+    if !settings.username.is_empty() {
+        proxy = proxy.basic_auth(&settings.username, &settings.password);
+    }
+    Ok(proxy.no_proxy(NoProxy::from_string(&settings.no_proxy.join(","))))
+}
+
+/// Builds the fact store selected by the cache backend setting.
+fn fact_store(settings: &Settings) -> Arc<dyn FactStore<Fact>> {
+    let ttl = Duration::from_secs(settings.cache.ttl_secs);
+    let stale_ttl = Duration::from_secs(settings.cache.stale_ttl_secs);
+    let capacity = settings.cache.capacity;
+    match settings.cache.backend {
+        CacheBackend::Memory => Arc::new(MemoryStore::new(ttl, capacity).with_stale_ttl(stale_ttl)),
+        #[cfg(feature = "redis")]
+        CacheBackend::Redis => Arc::new(
+            crate::cache::RedisStore::new(&settings.cache.redis, ttl, capacity)
+                .unwrap_or_else(|e| panic!("Invalid Redis URL '{}': {e}", settings.cache.redis.url))
+                .with_stale_ttl(stale_ttl),
+        ),
+        #[cfg(not(feature = "redis"))]
+        CacheBackend::Redis => {
+            panic!("The redis cache backend requires building with the `redis` feature")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::config::{ApiSettings, ProxySettings};
+
+    use super::http_client;
+
+    /// API settings sending upstream requests through the proxy at `url`.
+    fn proxied(url: String) -> ApiSettings {
+        ApiSettings {
+            proxy: ProxySettings {
+                url,
+                username: "user".into(),
+                password: "secret".into(),
+                no_proxy: vec!["localhost".into(), ".internal".into()],
+            },
+            ..ApiSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_http_client_builds_with_custom_pool_settings() {
+        let api = ApiSettings {
+            pool_max_idle_per_host: 4,
+            pool_idle_timeout_secs: 5,
+            ..ApiSettings::default()
+        };
+
+        assert!(http_client(&api).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_http_client_sends_requests_through_the_proxy() {
+        let proxy = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/fact"))
+            .and(header("proxy-authorization", "Basic dXNlcjpzZWNyZXQ="))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&proxy)
+            .await;
+
+        let client = http_client(&proxied(proxy.uri())).unwrap();
+        let res = client
+            .get("http://facts.example/fact")
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+    }
+
+    #[test]
+    fn test_http_client_fails_with_malformed_proxy_url() {
+        assert!(http_client(&proxied("http://[::1".into())).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_client_sends_default_user_agent() {
+        let mock_server = MockServer::start().await;
+
+        let user_agent = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+        Mock::given(method("GET"))
+            .and(header("user-agent", user_agent))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = http_client(&ApiSettings::default()).unwrap();
+        let res = client.get(mock_server.uri()).send().await.unwrap();
+        assert!(res.status().is_success());
+    }
+}
@@ -1,28 +1,502 @@
-use tracing::{subscriber::set_global_default, Subscriber};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use opentelemetry::trace::{
+    Link, SamplingDecision, SamplingResult, SpanContext, SpanId, SpanKind, Status, TraceId,
+    TracerProvider as _,
+};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{
+    BatchSpanProcessor, Sampler, SdkTracerProvider, ShouldSample, Span, SpanData, SpanProcessor,
+    Tracer,
+};
+use opentelemetry_sdk::Resource;
+use tracing::{level_filters::LevelFilter, subscriber::set_global_default, Subscriber};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
-use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt, EnvFilter, Registry};
+use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt, reload, EnvFilter, Registry};
+
+/// The most unsampled traces held at once while waiting to see whether they fail. Should more
+/// be in flight, as when spans outlive their request, the oldest held trace is dropped.
+const MAX_HELD_TRACES: usize = 10_000;
+
+/// The env var used to select the log format.
+pub const LOG_FORMAT_VAR: &str = "LOG_FORMAT";
+
+/// The supported log output formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    #[default]
+    Bunyan,
+    Json,
+}
+
+impl LogFormat {
+    /// Reads the log format from `LOG_FORMAT`, falling back to Bunyan when unset or unknown.
+    #[must_use]
+    pub fn from_env() -> Self {
+        match std::env::var(LOG_FORMAT_VAR) {
+            Ok(format) if format.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Bunyan,
+        }
+    }
+}
+
+/// Changes the log filter of a running subscriber.
+#[derive(Clone)]
+pub struct LogLevelHandle(reload::Handle<EnvFilter, Registry>);
+
+impl LogLevelHandle {
+    /// Returns the active log filter.
+    #[must_use]
+    pub fn current(&self) -> String {
+        self.0.with_current(ToString::to_string).unwrap_or_default()
+    }
+
+    /// Changes the default level of the log filter to `level`, keeping its directives for
+    /// specific targets and spans, and returns the previous filter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the subscriber the filter belongs to has been dropped.
+    pub fn set(&self, level: LevelFilter) -> Result<String, reload::Error> {
+        let previous = self.current();
+        let directives = previous
+            .split(',')
+            .filter(|directive| !directive.is_empty() && directive.parse::<LevelFilter>().is_err())
+            .map(ToString::to_string)
+            .chain([level.to_string()])
+            .collect::<Vec<_>>();
+        self.0.reload(EnvFilter::new(directives.join(",")))?;
+        Ok(previous)
+    }
+}
+
+/// Samples a ratio of traces by their id. When `record_unsampled` is set, the rest are still
+/// recorded so [`ErrorSampling`] can export those that fail.
+#[derive(Clone, Debug)]
+struct RatioSampler {
+    sampler: Sampler,
+    record_unsampled: bool,
+}
+
+impl ShouldSample for RatioSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        let mut result = self.sampler.should_sample(
+            parent_context,
+            trace_id,
+            name,
+            span_kind,
+            attributes,
+            links,
+        );
+        if self.record_unsampled && result.decision == SamplingDecision::Drop {
+            result.decision = SamplingDecision::RecordOnly;
+        }
+        result
+    }
+}
+
+/// Returns the sampler keeping `ratio`, from 0 to 1, of traces, and recording the rest when
+/// `record_unsampled` is set.
+fn sampler(ratio: f64, record_unsampled: bool) -> RatioSampler {
+    RatioSampler {
+        sampler: Sampler::TraceIdRatioBased(ratio.clamp(0.0, 1.0)),
+        record_unsampled,
+    }
+}
+
+/// Passes sampled spans on to the inner processor, holding back the spans of unsampled traces
+/// until their local root ends. They are then passed on, marked sampled, if any of them ended in
+/// an error, and dropped otherwise.
+#[derive(Debug)]
+struct ErrorSampling<P> {
+    inner: P,
+    held: Mutex<HeldTraces>,
+}
+
+/// The spans held back for each unsampled trace, along with the order the traces were first
+/// held in, so the oldest can be dropped when at capacity.
+#[derive(Debug, Default)]
+struct HeldTraces {
+    traces: HashMap<TraceId, (u64, Vec<SpanData>)>,
+    order: BTreeMap<u64, TraceId>,
+    next: u64,
+}
+
+impl HeldTraces {
+    /// Holds the span back with the rest of its trace, dropping the oldest held trace first
+    /// when the span starts a new one and there are already `capacity` held.
+    fn hold(&mut self, span: SpanData, capacity: usize) {
+        let trace_id = span.span_context.trace_id();
+        if let Some((_, spans)) = self.traces.get_mut(&trace_id) {
+            spans.push(span);
+            return;
+        }
+        if self.traces.len() >= capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.traces.remove(&oldest);
+            }
+        }
+        self.order.insert(self.next, trace_id);
+        self.traces.insert(trace_id, (self.next, vec![span]));
+        self.next += 1;
+    }
+
+    /// Stops holding the trace, returning the spans held back for it.
+    fn release(&mut self, trace_id: TraceId) -> Vec<SpanData> {
+        let Some((order, spans)) = self.traces.remove(&trace_id) else {
+            return Vec::new();
+        };
+        self.order.remove(&order);
+        spans
+    }
+}
+
+impl<P> ErrorSampling<P> {
+    fn new(inner: P) -> Self {
+        Self {
+            inner,
+            held: Mutex::new(HeldTraces::default()),
+        }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for ErrorSampling<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        if span.span_context.is_sampled() {
+            self.inner.on_end(span);
+            return;
+        }
+        let mut held = self.held.lock().unwrap();
+        if span.parent_span_id != SpanId::INVALID && !span.parent_span_is_remote {
+            held.hold(span, MAX_HELD_TRACES);
+            return;
+        }
+        let mut trace = held.release(span.span_context.trace_id());
+        drop(held);
+        trace.push(span);
+        if trace
+            .iter()
+            .any(|span| matches!(span.status, Status::Error { .. }))
+        {
+            for span in trace {
+                self.inner.on_end(into_sampled(span));
+            }
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        *self.held.lock().unwrap() = HeldTraces::default();
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Marks the span sampled, so it is exported.
+fn into_sampled(mut span: SpanData) -> SpanData {
+    let cx = &span.span_context;
+    span.span_context = SpanContext::new(
+        cx.trace_id(),
+        cx.span_id(),
+        cx.trace_flags().with_sampled(true),
+        cx.is_remote(),
+        cx.trace_state().clone(),
+    );
+    span
+}
+
+/// Builds a tracer provider exporting spans in batches to the OTLP collector at `endpoint`.
+///
+/// Only `sample_ratio`, from 0 to 1, of traces are exported. With `error_traces` set, so is every
+/// trace ending in an error, such as a request failing with a server error; the rest are then
+/// still recorded until they end, to see whether they fail.
+///
+/// # Errors
+///
+/// Returns an error if the exporter cannot be built, e.g. because the endpoint is invalid.
+pub fn get_tracer_provider(
+    name: String,
+    endpoint: &str,
+    sample_ratio: f64,
+    error_traces: bool,
+) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let exporter = SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+    let processor = BatchSpanProcessor::builder(exporter).build();
+
+    let builder = SdkTracerProvider::builder().with_sampler(sampler(sample_ratio, error_traces));
+    let builder = if error_traces {
+        builder.with_span_processor(ErrorSampling::new(processor))
+    } else {
+        builder.with_span_processor(processor)
+    };
+    Ok(builder
+        .with_resource(Resource::builder().with_service_name(name).build())
+        .build())
+}
+
+/// Gets a tracer from `provider` to pass to a subscriber.
+#[must_use]
+pub fn get_tracer(provider: &SdkTracerProvider, name: String) -> Tracer {
+    provider.tracer(name)
+}
 
+/// Builds a subscriber emitting Bunyan-formatted JSON, returning it with a handle for changing
+/// its log filter.
 pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
     sink: Sink,
-) -> impl Subscriber + Send + Sync
+    tracer: Option<Tracer>,
+) -> (impl Subscriber + Send + Sync, LogLevelHandle)
 where
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
 {
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
+    let (env_filter, handle) = reload::Layer::new(env_filter);
 
     let formatting_layer = BunyanFormattingLayer::new(name, sink);
 
-    Registry::default()
+    let sub = Registry::default()
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)));
+    (sub, LogLevelHandle(handle))
+}
+
+/// Builds a subscriber emitting one JSON object per line, including the fields of the current
+/// span (such as the request id) and its parents, returning it with a handle for changing its
+/// log filter.
+pub fn get_json_subscriber<Sink>(
+    env_filter: String,
+    sink: Sink,
+    tracer: Option<Tracer>,
+) -> (impl Subscriber + Send + Sync, LogLevelHandle)
+where
+    Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let env_filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
+    let (env_filter, handle) = reload::Layer::new(env_filter);
+
+    let formatting_layer = tracing_subscriber::fmt::layer()
+        .json()
+        .with_current_span(true)
+        .with_span_list(true)
+        .with_writer(sink);
+
+    let sub = Registry::default()
+        .with(env_filter)
+        .with(formatting_layer)
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)));
+    (sub, LogLevelHandle(handle))
 }
 
 pub fn init_subscriber(sub: impl Subscriber + Send + Sync) {
     LogTracer::init().expect("Failed to set logger");
     set_global_default(sub).expect("Failed to set subscriber");
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use opentelemetry::trace::{SamplingDecision, SpanKind, TraceId};
+    use opentelemetry_sdk::error::OTelSdkResult;
+    use opentelemetry_sdk::trace::{
+        SdkTracerProvider, ShouldSample, Span, SpanData, SpanProcessor,
+    };
+    use serde_json::Value;
+    use tracing::level_filters::LevelFilter;
+    use tracing_subscriber::{layer::SubscriberExt, Registry};
+
+    use super::{
+        get_json_subscriber, get_subscriber, get_tracer, get_tracer_provider, sampler,
+        ErrorSampling, HeldTraces,
+    };
+    use crate::util::testing::LogBuffer;
+
+    /// Keeps the sampled spans it is passed, as an exporter would.
+    #[derive(Clone, Debug, Default)]
+    struct Recorder(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanProcessor for Recorder {
+        fn on_start(&self, _span: &mut Span, _cx: &opentelemetry::Context) {}
+
+        fn on_end(&self, span: SpanData) {
+            if span.span_context.is_sampled() {
+                self.0.lock().unwrap().push(span);
+            }
+        }
+
+        fn force_flush(&self) -> OTelSdkResult {
+            Ok(())
+        }
+
+        fn shutdown_with_timeout(&self, _timeout: std::time::Duration) -> OTelSdkResult {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sampler() {
+        let decision = |ratio| {
+            sampler(ratio, true)
+                .should_sample(
+                    None,
+                    TraceId::from(u128::MAX),
+                    "request",
+                    &SpanKind::Server,
+                    &[],
+                    &[],
+                )
+                .decision
+        };
+        assert_eq!(SamplingDecision::RecordAndSample, decision(1.0));
+        assert_eq!(SamplingDecision::RecordAndSample, decision(2.0));
+        assert_eq!(SamplingDecision::RecordOnly, decision(0.0));
+        assert_eq!(SamplingDecision::RecordOnly, decision(-1.0));
+
+        let dropped = sampler(0.0, false).should_sample(
+            None,
+            TraceId::from(u128::MAX),
+            "request",
+            &SpanKind::Server,
+            &[],
+            &[],
+        );
+        assert_eq!(SamplingDecision::Drop, dropped.decision);
+    }
+
+    #[test]
+    fn test_error_traces_are_always_sampled() {
+        let recorder = Recorder::default();
+        let provider = SdkTracerProvider::builder()
+            .with_sampler(sampler(0.0, true))
+            .with_span_processor(ErrorSampling::new(recorder.clone()))
+            .build();
+        let tracer = get_tracer(&provider, "test".into());
+        let sub = Registry::default().with(tracing_opentelemetry::layer().with_tracer(tracer));
+
+        tracing::subscriber::with_default(sub, || {
+            tracing::info_span!("ok request").in_scope(|| {
+                tracing::info_span!("ok upstream call").in_scope(|| tracing::info!("called"));
+            });
+            tracing::info_span!("failed request").in_scope(|| {
+                tracing::info_span!("failed upstream call").in_scope(|| tracing::info!("called"));
+                tracing::error!("request failed");
+            });
+        });
+
+        let names: Vec<_> = recorder
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|span| span.name.to_string())
+            .collect();
+        assert_eq!(vec!["failed upstream call", "failed request"], names);
+    }
+
+    #[test]
+    fn test_held_traces_drop_the_oldest_at_capacity() {
+        let recorder = Recorder::default();
+        let provider = SdkTracerProvider::builder()
+            .with_sampler(sampler(1.0, true))
+            .with_span_processor(recorder.clone())
+            .build();
+        let tracer = get_tracer(&provider, "test".into());
+        let sub = Registry::default().with(tracing_opentelemetry::layer().with_tracer(tracer));
+        tracing::subscriber::with_default(sub, || {
+            for _ in 0..3 {
+                tracing::info_span!("request").in_scope(|| tracing::info!("handled"));
+            }
+        });
+        let spans = recorder.0.lock().unwrap().clone();
+        let trace_ids: Vec<_> = spans
+            .iter()
+            .map(|span| span.span_context.trace_id())
+            .collect();
+
+        let mut held = HeldTraces::default();
+        for span in spans {
+            held.hold(span, 2);
+        }
+
+        assert!(held.release(trace_ids[0]).is_empty());
+        assert_eq!(1, held.release(trace_ids[1]).len());
+        assert_eq!(1, held.release(trace_ids[2]).len());
+    }
+
+    #[test]
+    fn test_json_subscriber_emits_json_lines_with_request_id() {
+        let buffer = LogBuffer::default();
+        let (sub, _) = get_json_subscriber("info".into(), buffer.clone(), None);
+
+        tracing::subscriber::with_default(sub, || {
+            let span = tracing::info_span!("request", request_id = "abc-123");
+            let _guard = span.enter();
+            tracing::info!("handling request");
+        });
+
+        let output = buffer.contents();
+        let line = output.lines().next().expect("No log line written");
+        let json: Value = serde_json::from_str(line).expect("Log line is not JSON");
+        assert_eq!(json["fields"]["message"], "handling request");
+        assert_eq!(json["span"]["request_id"], "abc-123");
+    }
+
+    #[test]
+    fn test_log_level_change_keeps_target_directives() {
+        let (_sub, handle) =
+            get_subscriber("test".into(), "hyper=warn,info".into(), std::io::sink, None);
+
+        let previous = handle.set(LevelFilter::DEBUG).unwrap();
+        assert!(previous.contains("info"));
+
+        let current = handle.current();
+        assert!(current.contains("hyper=warn"));
+        assert!(current.contains("debug"));
+        assert!(!current.contains("info"));
+    }
+
+    #[tokio::test]
+    async fn test_otlp_layer_builds_with_dummy_endpoint() {
+        let provider = get_tracer_provider("test".into(), "http://localhost:4317", 1.0, true)
+            .expect("Failed to build tracer provider");
+        let tracer = get_tracer(&provider, "test".into());
+        let (sub, _) = get_subscriber("test".into(), "info".into(), std::io::sink, Some(tracer));
+        drop(sub);
+
+        provider
+            .shutdown()
+            .expect("Failed to shut down tracer provider");
+    }
+}
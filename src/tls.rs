@@ -0,0 +1,69 @@
+use std::io;
+use std::sync::Arc;
+
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::ServerConfig;
+
+use crate::config::TlsSettings;
+
+/// Why the TLS certificate and key couldn't be loaded.
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    #[error("Both tls.cert_path and tls.key_path must be set to serve HTTPS")]
+    Incomplete,
+
+    #[error("Failed to read TLS {kind} file '{path}': {source}")]
+    Read {
+        kind: &'static str,
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("No PEM certificates found in '{0}'")]
+    NoCertificates(String),
+
+    #[error("No PEM private key found in '{0}'")]
+    NoPrivateKey(String),
+
+    #[error("Invalid TLS certificate or key: {0}")]
+    Config(#[from] rustls::Error),
+}
+
+/// Loads the PEM certificate chain and private key to serve HTTPS with, or returns `None` when
+/// TLS isn't configured and plain HTTP should be served. HTTP/2 is offered to clients when `http2`
+/// is set, otherwise only HTTP/1.1.
+pub fn load_tls_config(tls: &TlsSettings, http2: bool) -> Result<Option<RustlsConfig>, TlsError> {
+    match (tls.cert_path.is_empty(), tls.key_path.is_empty()) {
+        (true, true) => return Ok(None),
+        (false, false) => {}
+        _ => return Err(TlsError::Incomplete),
+    }
+    let read_err = |kind, path: &str| {
+        let path = path.to_string();
+        move |source| TlsError::Read { kind, path, source }
+    };
+
+    let cert = std::fs::read(&tls.cert_path).map_err(read_err("certificate", &tls.cert_path))?;
+    let certs = rustls_pemfile::certs(&mut cert.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(read_err("certificate", &tls.cert_path))?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificates(tls.cert_path.clone()));
+    }
+
+    let key = std::fs::read(&tls.key_path).map_err(read_err("key", &tls.key_path))?;
+    let key = rustls_pemfile::private_key(&mut key.as_slice())
+        .map_err(read_err("key", &tls.key_path))?
+        .ok_or_else(|| TlsError::NoPrivateKey(tls.key_path.clone()))?;
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    config.alpn_protocols = if http2 {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    } else {
+        vec![b"http/1.1".to_vec()]
+    };
+    Ok(Some(RustlsConfig::from_config(Arc::new(config))))
+}
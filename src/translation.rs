@@ -0,0 +1,70 @@
+use reqwest::Client;
+use serde_json::json;
+
+/// The language upstream animal facts are written in.
+pub const SOURCE_LANG: &str = "en";
+
+/// The translation API return type.
+#[derive(serde::Deserialize)]
+struct Translation {
+    #[serde(rename = "translatedText")]
+    translated_text: String,
+}
+
+/// Translates English text into `lang` using a LibreTranslate-compatible API at `url`.
+#[tracing::instrument(name = "Translating a fact", skip(client, text))]
+pub async fn translate(
+    client: &Client,
+    url: &str,
+    text: &str,
+    lang: &str,
+) -> Result<String, reqwest::Error> {
+    let body = json!({ "q": text, "source": SOURCE_LANG, "target": lang, "format": "text" });
+    let res = client
+        .post(url)
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Translation>()
+        .await?;
+    Ok(res.translated_text)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::translate;
+
+    #[tokio::test]
+    async fn test_translate() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/translate"))
+            .and(body_partial_json(
+                serde_json::json!({ "q": "fact", "target": "es" }),
+            ))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(r#"{"translatedText": "hecho"}"#, "application/json"),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let res = translate(
+            &Client::new(),
+            &format!("{}/translate", mock_server.uri()),
+            "fact",
+            "es",
+        )
+        .await
+        .expect("Failed to translate.");
+
+        assert_eq!("hecho", res);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use reqwest::{Client, Url};
+
+use crate::config::ApiSettings;
+use crate::handlers::ErrorKind;
+use crate::state::http_client;
+
+/// The client calling the upstream APIs, rebuilt after `rebuild_after` consecutive connection
+/// failures to the same host in case its connection pool or the addresses it resolved have gone
+/// bad.
+///
+/// Failures are counted per host, so calls to healthy hosts don't reset the count of one that is
+/// down, and calls to one that is down don't add up to rebuilding the client on their own behalf.
+/// A threshold of 0 disables rebuilding.
+pub struct UpstreamClient {
+    client: RwLock<Client>,
+    api: ApiSettings,
+    rebuild_after: u32,
+    connect_failures: Mutex<HashMap<String, u32>>,
+    rebuilds: AtomicU64,
+}
+
+impl UpstreamClient {
+    /// Builds the client from the API settings, rebuilding it after their
+    /// `client_rebuild_threshold` of consecutive connection failures.
+    pub fn new(api: &ApiSettings) -> reqwest::Result<Self> {
+        Ok(Self {
+            client: RwLock::new(http_client(api)?),
+            api: api.clone(),
+            rebuild_after: api.client_rebuild_threshold,
+            connect_failures: Mutex::default(),
+            rebuilds: AtomicU64::new(0),
+        })
+    }
+
+    /// Returns the current client. Calls already made with a client replaced since carry on with
+    /// it.
+    #[must_use]
+    pub fn current(&self) -> Client {
+        self.client
+            .read()
+            .expect("Upstream client lock poisoned")
+            .clone()
+    }
+
+    /// How many times the client has been rebuilt.
+    #[must_use]
+    pub fn rebuilds(&self) -> u64 {
+        self.rebuilds.load(Ordering::Relaxed)
+    }
+
+    /// Records the outcome of an upstream call to the URL, rebuilding the client once enough
+    /// calls in a row to its host have failed to connect. Any other outcome means the host was
+    /// reached, so its count starts over.
+    pub fn record<T>(&self, url: &str, res: &Result<T, ErrorKind>) {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| url.to_string());
+        {
+            let mut connect_failures = self
+                .connect_failures
+                .lock()
+                .expect("Upstream client lock poisoned");
+            if !matches!(res, Err(ErrorKind::Connect(_))) {
+                connect_failures.remove(&host);
+                return;
+            }
+            if self.rebuild_after == 0 {
+                return;
+            }
+            let failures = connect_failures.entry(host.clone()).or_default();
+            *failures += 1;
+            if *failures < self.rebuild_after {
+                return;
+            }
+            connect_failures.remove(&host);
+        }
+        match http_client(&self.api) {
+            Ok(client) => {
+                *self.client.write().expect("Upstream client lock poisoned") = client;
+                self.rebuilds.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(
+                    "{} consecutive connection failures to {host}, rebuilt the HTTP client",
+                    self.rebuild_after
+                );
+            }
+            Err(err) => tracing::error!("Failed to rebuild the HTTP client: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UpstreamClient;
+    use crate::config::ApiSettings;
+    use crate::handlers::ErrorKind;
+
+    const URL: &str = "http://localhost/facts";
+
+    fn connect_failure() -> Result<(), ErrorKind> {
+        Err(ErrorKind::Connect("connection refused".into()))
+    }
+
+    #[test]
+    fn test_client_rebuilt_after_consecutive_connect_failures() {
+        let client = UpstreamClient::new(&ApiSettings {
+            client_rebuild_threshold: 3,
+            ..ApiSettings::default()
+        })
+        .unwrap();
+
+        client.record(URL, &connect_failure());
+        client.record(URL, &connect_failure());
+        client.record(URL, &Err::<(), _>(ErrorKind::ApiResponse(500)));
+        client.record(URL, &connect_failure());
+        client.record(URL, &connect_failure());
+        assert_eq!(0, client.rebuilds());
+
+        client.record(URL, &connect_failure());
+        assert_eq!(1, client.rebuilds());
+
+        for _ in 0..3 {
+            client.record(URL, &connect_failure());
+        }
+        assert_eq!(2, client.rebuilds());
+
+        let disabled = UpstreamClient::new(&ApiSettings {
+            client_rebuild_threshold: 0,
+            ..ApiSettings::default()
+        })
+        .unwrap();
+        for _ in 0..10 {
+            disabled.record(URL, &connect_failure());
+        }
+        assert_eq!(0, disabled.rebuilds());
+    }
+
+    #[test]
+    fn test_connect_failures_counted_per_host() {
+        let client = UpstreamClient::new(&ApiSettings {
+            client_rebuild_threshold: 2,
+            ..ApiSettings::default()
+        })
+        .unwrap();
+
+        // calls reaching another host don't reset the count of one that is down
+        client.record(URL, &connect_failure());
+        client.record("http://127.0.0.1/facts", &Ok(()));
+        client.record(URL, &connect_failure());
+        assert_eq!(1, client.rebuilds());
+
+        // and failures to different hosts don't add up
+        client.record(URL, &connect_failure());
+        client.record("http://127.0.0.1/facts", &connect_failure());
+        assert_eq!(1, client.rebuilds());
+    }
+}
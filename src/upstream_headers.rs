@@ -0,0 +1,144 @@
+use std::collections::{BTreeMap, HashMap};
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Url;
+
+use crate::config::HeaderValueSource;
+
+/// Why the configured upstream headers couldn't be resolved. Header values are left out, as they
+/// are often secrets.
+#[derive(Debug, thiserror::Error)]
+pub enum UpstreamHeaderError {
+    #[error("'{0}' is not a valid header name")]
+    InvalidName(String),
+
+    #[error("'{0}' is not an origin such as https://api.example.com")]
+    InvalidOrigin(String),
+
+    #[error("The value of the {1} header for {0} is not a valid header value")]
+    InvalidValue(String, String),
+
+    #[error("The environment variable {0} is not set")]
+    MissingEnv(String),
+
+    #[error("Failed to read {0}: {1}")]
+    File(String, #[source] std::io::Error),
+}
+
+/// The headers sent with every request to an upstream origin, keyed by origin so they are never
+/// sent to the same host over another scheme or port, with their values marked sensitive so they
+/// are never logged.
+#[derive(Default)]
+pub struct UpstreamHeaders(HashMap<String, HeaderMap>);
+
+impl UpstreamHeaders {
+    /// Resolves the configured headers, reading values from the environment or files as given.
+    pub fn resolve(
+        config: &BTreeMap<String, BTreeMap<String, HeaderValueSource>>,
+    ) -> Result<Self, UpstreamHeaderError> {
+        let mut origins = HashMap::new();
+        for (origin, headers) in config {
+            let mut map = HeaderMap::new();
+            for (name, source) in headers {
+                let header: HeaderName = name
+                    .parse()
+                    .map_err(|_| UpstreamHeaderError::InvalidName(name.clone()))?;
+                let mut value = HeaderValue::from_str(&source.resolve()?)
+                    .map_err(|_| UpstreamHeaderError::InvalidValue(origin.clone(), name.clone()))?;
+                value.set_sensitive(true);
+                map.insert(header, value);
+            }
+            let origin = match Url::parse(origin).map(|url| url.origin()) {
+                Ok(parsed) if parsed.is_tuple() => parsed.ascii_serialization(),
+                _ => return Err(UpstreamHeaderError::InvalidOrigin(origin.clone())),
+            };
+            origins.insert(origin, map);
+        }
+        Ok(Self(origins))
+    }
+
+    /// Returns the headers to send with a request to the URL, if its origin has any.
+    #[must_use]
+    pub fn for_url(&self, url: &str) -> Option<&HeaderMap> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let url = Url::parse(url).ok()?;
+        self.0.get(&url.origin().ascii_serialization())
+    }
+}
+
+impl HeaderValueSource {
+    /// Returns the header value, reading it from the environment variable or file it is in. A
+    /// file's trailing newline is dropped.
+    fn resolve(&self) -> Result<String, UpstreamHeaderError> {
+        match self {
+            Self::Value(value) => Ok(value.clone()),
+            Self::Env { env } => {
+                std::env::var(env).map_err(|_| UpstreamHeaderError::MissingEnv(env.clone()))
+            }
+            Self::File { file } => std::fs::read_to_string(file)
+                .map(|value| value.trim_end_matches(['\r', '\n']).to_string())
+                .map_err(|err| UpstreamHeaderError::File(file.clone(), err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{UpstreamHeaderError, UpstreamHeaders};
+    use crate::config::HeaderValueSource;
+
+    #[test]
+    fn test_resolve_upstream_headers() {
+        std::env::set_var("UPSTREAM_HEADERS_TEST_KEY", "from-env");
+        let config = BTreeMap::from([(
+            "https://API.example.com".to_string(),
+            BTreeMap::from([
+                (
+                    "x-api-key".to_string(),
+                    HeaderValueSource::Env {
+                        env: "UPSTREAM_HEADERS_TEST_KEY".into(),
+                    },
+                ),
+                (
+                    "x-plan".to_string(),
+                    HeaderValueSource::Value("paid".into()),
+                ),
+            ]),
+        )]);
+
+        let headers = UpstreamHeaders::resolve(&config).unwrap();
+        let sent = headers.for_url("https://api.example.com/fact?n=1").unwrap();
+        assert_eq!("from-env", sent["x-api-key"]);
+        assert!(sent["x-api-key"].is_sensitive());
+        assert_eq!("paid", sent["x-plan"]);
+        assert!(headers.for_url("https://other.example.com/fact").is_none());
+        assert!(headers.for_url("http://api.example.com/fact").is_none());
+        assert!(headers
+            .for_url("https://api.example.com:8443/fact")
+            .is_none());
+
+        let bare_host = BTreeMap::from([("api.example.com".to_string(), BTreeMap::new())]);
+        assert!(matches!(
+            UpstreamHeaders::resolve(&bare_host),
+            Err(UpstreamHeaderError::InvalidOrigin(_))
+        ));
+
+        let missing = BTreeMap::from([(
+            "https://api.example.com".to_string(),
+            BTreeMap::from([(
+                "x-api-key".to_string(),
+                HeaderValueSource::Env {
+                    env: "UPSTREAM_HEADERS_TEST_MISSING".into(),
+                },
+            )]),
+        )]);
+        assert!(matches!(
+            UpstreamHeaders::resolve(&missing),
+            Err(UpstreamHeaderError::MissingEnv(_))
+        ));
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use rand::{prelude::IteratorRandom, rngs::StdRng};
+
+/// Where facts contributed by users are kept.
+#[async_trait]
+pub trait UserFactStore: Send + Sync {
+    /// Stores a fact contributed for the animal.
+    async fn add(&self, animal: &'static str, fact: String);
+
+    /// Returns a random fact contributed for the animal that `matches`, if there is any.
+    async fn random(
+        &self,
+        animal: &str,
+        matches: &(dyn for<'f> Fn(&'f str) -> bool + Sync),
+        rng: &mut StdRng,
+    ) -> Option<String>;
+}
+
+/// An in-memory store of contributed facts, holding up to `capacity` facts per animal and
+/// dropping the oldest when full.
+pub struct MemoryUserFacts {
+    capacity: usize,
+    facts: RwLock<HashMap<&'static str, VecDeque<String>>>,
+}
+
+impl MemoryUserFacts {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            facts: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl UserFactStore for MemoryUserFacts {
+    async fn add(&self, animal: &'static str, fact: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut facts = self.facts.write().expect("User facts lock poisoned");
+        let facts = facts.entry(animal).or_default();
+        if facts.len() >= self.capacity {
+            facts.pop_front();
+        }
+        facts.push_back(fact);
+    }
+
+    async fn random(
+        &self,
+        animal: &str,
+        matches: &(dyn for<'f> Fn(&'f str) -> bool + Sync),
+        rng: &mut StdRng,
+    ) -> Option<String> {
+        let facts = self.facts.read().expect("User facts lock poisoned");
+        let fact = facts
+            .get(animal)?
+            .iter()
+            .filter(|fact| matches(fact.as_str()))
+            .choose(rng)
+            .cloned();
+        fact
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{MemoryUserFacts, UserFactStore};
+
+    #[tokio::test]
+    async fn test_user_facts_drop_the_oldest_when_full() {
+        let store = MemoryUserFacts::new(2);
+        let mut rng = StdRng::seed_from_u64(7);
+
+        store.add("cat", "one".into()).await;
+        store.add("cat", "two".into()).await;
+        store.add("cat", "three".into()).await;
+
+        for _ in 0..10 {
+            let fact = store.random("cat", &|_| true, &mut rng).await;
+            assert!(matches!(fact.as_deref(), Some("two" | "three")));
+        }
+        assert_eq!(
+            Some("three".into()),
+            store.random("cat", &|fact| fact != "two", &mut rng).await
+        );
+        assert_eq!(None, store.random("dog", &|_| true, &mut rng).await);
+    }
+}
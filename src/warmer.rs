@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use futures::future::join_all;
+use rand::{rngs::StdRng, SeedableRng};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::Instrument;
+
+use crate::handlers::{cache_fact, fetch_fact, Animal};
+use crate::state::AppState;
+
+/// Spawns the task filling the fact cache on startup and then every configured interval, or
+/// returns `None` when the warmer or the cache is disabled.
+#[must_use]
+pub fn spawn_cache_warmer(state: &AppState) -> Option<JoinHandle<()>> {
+    let settings = &state.config.warmer;
+    if !settings.enabled || state.config.cache.capacity == 0 {
+        return None;
+    }
+    let mut ticks = interval(Duration::from_secs(settings.interval_secs.max(1)));
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let state = state.clone();
+    let task = async move {
+        loop {
+            ticks.tick().await;
+            warm_cache(&state).await;
+        }
+    };
+    Some(tokio::spawn(
+        task.instrument(tracing::info_span!("Cache warmer")),
+    ))
+}
+
+/// Fetches enough facts for every animal to fill its cache, returning how many were cached.
+///
+/// One fact is fetched per cache slot that is empty or whose fact expires before the next run,
+/// so hits are served from a varied set of facts from the start without refetching fresh ones.
+/// The calls share the upstream concurrency limit with requests.
+pub async fn warm_cache(state: &AppState) -> usize {
+    let capacity = state.config.cache.capacity;
+    let selection = state.config.facts.selection;
+    let within = Duration::from_secs(state.config.warmer.interval_secs.max(1));
+    let mut fetches = vec![];
+    for animal in Animal::supported(state) {
+        let fresh = state.cache.fresh_count(animal.as_str(), within).await;
+        fetches.extend((fresh..capacity).map(|_| animal.clone()));
+    }
+    let results = join_all(fetches.into_iter().map(|animal| async move {
+        match fetch_fact(state, &animal, selection, &mut StdRng::from_entropy()).await {
+            Ok(fact) => cache_fact(state, &animal, &fact).await,
+            Err(err) => {
+                tracing::warn!("Failed to warm the {} cache: {err}", animal.as_str());
+                false
+            }
+        }
+    }))
+    .await;
+    let cached = results.into_iter().filter(|cached| *cached).count();
+    tracing::info!("Warmed the cache with {cached} facts");
+    cached
+}
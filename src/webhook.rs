@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use serde_json::json;
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+use crate::handlers::{daily_animal, daily_fact, ErrorKind};
+use crate::state::AppState;
+
+/// The format of the configured time of day.
+const TIME_FORMAT: &str = "%H:%M";
+
+/// Why the fact of the day couldn't be posted to the webhook.
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("Failed to get the fact of the day: {0}")]
+    Fact(#[from] ErrorKind),
+
+    #[error("Failed to post to the webhook: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("The webhook returned error code: {0}")]
+    Status(u16),
+}
+
+/// Checks the webhook settings, returning the time of day to post at, or `None` when the webhook
+/// is disabled.
+///
+/// # Panics
+///
+/// Panics if the time or the animal is invalid, so a misconfigured webhook stops the service
+/// from starting instead of never posting.
+#[must_use]
+pub fn daily_webhook_time(state: &AppState) -> Option<NaiveTime> {
+    let settings = &state.config.webhook;
+    if settings.url.is_empty() {
+        return None;
+    }
+    let at = NaiveTime::parse_from_str(&settings.time, TIME_FORMAT)
+        .unwrap_or_else(|_| panic!("Invalid webhook time '{}', expected HH:MM", settings.time));
+    if let Err(err) = daily_animal(state, &settings.animal, Utc::now().date_naive()) {
+        panic!("Invalid webhook animal '{}': {err}", settings.animal);
+    }
+    Some(at)
+}
+
+/// Spawns the task posting the fact of the day to the configured webhook each day at `at`.
+#[must_use]
+pub fn spawn_daily_webhook(state: &AppState, at: NaiveTime) -> JoinHandle<()> {
+    let state = state.clone();
+    let task = async move {
+        loop {
+            let now = Utc::now();
+            let next = next_run(now, at);
+            tracing::info!("Next fact of the day will be posted to the webhook at {next}");
+            tokio::time::sleep((next - now).to_std().unwrap_or_default()).await;
+            if let Err(err) = push_daily_fact(&state, next.date_naive()).await {
+                tracing::error!("Failed to post the fact of the day to the webhook: {err}");
+            }
+        }
+    };
+    tokio::spawn(task.instrument(tracing::info_span!("Daily webhook")))
+}
+
+/// Returns the next time after `now` that falls at the time of day `at`.
+fn next_run(now: DateTime<Utc>, at: NaiveTime) -> DateTime<Utc> {
+    let today = now.date_naive().and_time(at).and_utc();
+    if today > now {
+        today
+    } else {
+        today + chrono::Duration::days(1)
+    }
+}
+
+/// Posts the fact of the day for the configured animal on `date` to the webhook, retrying
+/// failed posts with exponential backoff.
+pub async fn push_daily_fact(state: &AppState, date: NaiveDate) -> Result<(), WebhookError> {
+    let settings = &state.config.webhook;
+    let animal = daily_animal(state, &settings.animal, date)?;
+    let fact = daily_fact(state, &animal, date).await?;
+    let body = json!({ "animal": animal.as_str(), "fact": fact });
+
+    let mut attempt = 0;
+    loop {
+        let res = state
+            .client
+            .current()
+            .post(&settings.url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(WebhookError::from)
+            .and_then(|res| match res.status() {
+                status if status.is_success() => Ok(()),
+                status => Err(WebhookError::Status(status.as_u16())),
+            });
+        match res {
+            Ok(()) => {
+                tracing::info!(
+                    "Posted the {} fact of the day to the webhook",
+                    animal.as_str()
+                );
+                return Ok(());
+            }
+            Err(err) if attempt < settings.max_retries => {
+                tracing::warn!("Posting to the webhook failed, retrying: {err}");
+            }
+            Err(err) => return Err(err),
+        }
+        let delay = settings.retry_delay_ms.saturating_mul(1 << attempt.min(16));
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveTime, TimeZone, Utc};
+
+    use super::next_run;
+
+    #[test]
+    fn test_next_run() {
+        let at = NaiveTime::from_hms_opt(9, 0, 0).unwrap();
+
+        let before = Utc.with_ymd_and_hms(2024, 1, 1, 8, 0, 0).unwrap();
+        assert_eq!(
+            Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap(),
+            next_run(before, at)
+        );
+
+        let after = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+        assert_eq!(
+            Utc.with_ymd_and_hms(2024, 1, 2, 9, 0, 0).unwrap(),
+            next_run(after, at)
+        );
+    }
+}
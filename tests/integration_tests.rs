@@ -1,22 +1,42 @@
 #![warn(clippy::pedantic)]
 
-use coding_challenge::telemetry::{get_subscriber, init_subscriber};
+use chrono::NaiveDate;
+use coding_challenge::config::{
+    get_config, AnimalApiSettings, ErrorDetail, HeaderValueSource, ProviderOrder, Selection,
+    Settings,
+};
+use coding_challenge::fallback::fallback_facts;
+use coding_challenge::selftest::{selftest, Check};
+use coding_challenge::state::AppState;
+use coding_challenge::telemetry::{get_subscriber, init_subscriber, LogLevelHandle};
+use coding_challenge::webhook::{daily_webhook_time, push_daily_fact};
 use once_cell::sync::Lazy;
 use reqwest::Client;
+use serde_json::Value;
+use socket2::{Domain, Socket, Type};
+use std::collections::BTreeMap;
 use std::future::IntoFuture;
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+use wiremock::matchers::{any, body_json, body_partial_json, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
-static TRACING: Lazy<()> = Lazy::new(|| {
+#[allow(clippy::non_std_lazy_statics)]
+static TRACING: Lazy<LogLevelHandle> = Lazy::new(|| {
     let name = "test".to_string();
     let level = "debug".to_string();
 
     if std::env::var("TEST_LOG").is_ok() {
-        let sub = get_subscriber(name, level, std::io::stdout);
+        let (sub, handle) = get_subscriber(name, level, std::io::stdout, None);
         init_subscriber(sub);
+        handle
     } else {
-        let sub = get_subscriber(name, level, std::io::sink);
+        let (sub, handle) = get_subscriber(name, level, std::io::sink, None);
         init_subscriber(sub);
+        handle
     }
 });
 
@@ -25,14 +45,31 @@ struct TestApp {
 }
 
 async fn spawn_app() -> TestApp {
-    Lazy::force(&TRACING);
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawns the app with the loaded config adjusted by `configure`.
+async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
+    spawn_app_with_log_level(Lazy::force(&TRACING).clone(), configure).await
+}
+
+/// Spawns the app with the loaded config adjusted by `configure`, changing the log level through
+/// `log_level`.
+async fn spawn_app_with_log_level(
+    log_level: LogLevelHandle,
+    configure: impl FnOnce(&mut Settings),
+) -> TestApp {
+    let mut settings = get_config().expect("Failed to read config");
+    configure(&mut settings);
 
     let listener = TcpListener::bind("127.0.0.1:0")
         .await
         .expect("Failed to bind to random port");
     let addr = listener.local_addr().unwrap();
 
-    let server = coding_challenge::startup::run(listener).expect("Failed to bind to address");
+    let state = AppState::new(settings).with_log_level(log_level);
+    let server =
+        coding_challenge::startup::run(listener, state).expect("Failed to bind to address");
 
     tokio::spawn(server.into_future());
 
@@ -67,5 +104,4301 @@ async fn get_animal_fact_fails_when_no_param() {
         .await
         .expect("Failed to execute request.");
 
+    assert_eq!(400, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("validation_failed", body["error"]["code"]);
+}
+
+#[tokio::test]
+async fn get_animal_fact_reports_every_invalid_param() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .get(format!(
+            "http://{addr}/fact?animal=cat&count=50&lang=english&min_len=20&max_len=10"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    let errors = &body["error"]["errors"];
+    assert_eq!("must be between 1 and 10", errors["count"][0]);
+    assert_eq!("must be a 2-letter language code", errors["lang"][0]);
+    assert_eq!("min_len must not exceed max_len", errors["min_len"][0]);
+}
+
+#[tokio::test]
+async fn get_animal_fact_reports_malformed_params_as_field_errors() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/fact?animal=cat&count=abc"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("validation_failed", body["error"]["code"]);
+    let errors = &body["error"]["errors"];
+    assert!(errors["count"][0].is_string());
+    assert!(errors["animal"].is_null());
+}
+
+#[tokio::test]
+async fn get_animal_fact_uses_default_animal_when_no_param() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.facts.default_animal = "cat".into();
+    })
+    .await;
+
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{addr}/fact"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("cat", body["animal"]);
+
+    // an empty param isn't replaced by the default
+    let res = client
+        .get(format!("http://{addr}/fact?animal="))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(400, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn head_animal_fact_succeeds_without_calling_upstream() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+
+    let client = Client::new();
+
+    let res = client
+        .head(format!("http://{addr}/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(res.status().is_success());
+    assert_eq!(Some(0), res.content_length());
+    assert_eq!("application/json", res.headers()["content-type"]);
+
+    let res = client
+        .head(format!("http://{addr}/fact?animal=dragon"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(400, res.status().as_u16());
+
+    let res = client
+        .head(format!("http://{addr}/health-check"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(res.status().is_success());
+}
+
+#[tokio::test]
+async fn get_animal_fact_uses_configured_api_url() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "mocked cat fact"}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{addr}/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("mocked cat fact", body["fact"]);
+    assert_eq!("cat", body["animal"]);
+}
+
+#[tokio::test]
+async fn get_animal_fact_sends_configured_user_agent() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .and(header("user-agent", "facts-test/1.0"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.api.cat_fallback_urls = vec![];
+        settings.api.user_agent = "facts-test/1.0".into();
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+}
+
+#[tokio::test]
+async fn get_animal_fact_returns_504_when_upstream_times_out() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/facts"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"facts": ["fact"]}"#, "application/json")
+                .set_delay(Duration::from_secs(5)),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.dog_url = format!("{}/api/facts", mock_server.uri());
+        settings.api.dog_fallback_urls = vec![];
+        settings.api.cat_fallback_urls = vec![];
+        settings.api.timeout_ms = 100;
+    })
+    .await;
+
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{addr}/fact?animal=dog"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(504, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn get_animal_fact_serves_cached_fact() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "cached cat fact"}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.cache.capacity = 1;
+    })
+    .await;
+
+    let client = Client::new();
+
+    for _ in 0..2 {
+        let res = client
+            .get(format!("http://{addr}/fact?animal=cat"))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert!(res.status().is_success());
+        let body: Value = res.json().await.expect("Failed to parse response.");
+        assert_eq!("cached cat fact", body["fact"]);
+    }
+}
+
+#[tokio::test]
+async fn get_animal_fact_serves_stale_fact_while_refreshing() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "old fact"}"#, "application/json"),
+        )
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "new fact"}"#, "application/json")
+                .set_delay(Duration::from_millis(200)),
+        )
+        // requests finding the fact stale share one refresh
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.cache.ttl_secs = 1;
+        settings.cache.stale_ttl_secs = 60;
+        settings.cache.capacity = 1;
+    })
+    .await;
+
+    let client = Client::new();
+    let get_fact = || async {
+        let res = client
+            .get(format!("http://{addr}/v1/fact?animal=cat"))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        let body: Value = res.json().await.expect("Failed to parse response.");
+        body["fact"].clone()
+    };
+
+    assert_eq!("old fact", get_fact().await);
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    // the stale fact is served without waiting for the slow refresh
+    let started = Instant::now();
+    let facts = futures::future::join_all([get_fact(), get_fact(), get_fact()]).await;
+    assert!(facts.iter().all(|fact| fact == "old fact"));
+    assert!(started.elapsed() < Duration::from_millis(200));
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert_eq!("new fact", get_fact().await);
+}
+
+#[tokio::test]
+async fn fact_history_lists_served_facts_newest_first() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/facts"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"facts": ["dog fact"]}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.api.dog_url = format!("{}/api/facts", mock_server.uri());
+    })
+    .await;
+
+    let client = Client::new();
+    for (animal, id) in [("cat", "first-request"), ("dog", "second-request")] {
+        let res = client
+            .get(format!("http://{addr}/v1/fact?animal={animal}"))
+            .header("x-request-id", id)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(200, res.status().as_u16());
+    }
+
+    let res = client
+        .get(format!("http://{addr}/v1/fact/history"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    let facts = body["facts"].as_array().unwrap();
+    assert_eq!(2, facts.len());
+    assert_eq!("dog", facts[0]["animal"]);
+    assert_eq!("dog fact", facts[0]["fact"]);
+    assert_eq!("second-request", facts[0]["request_id"]);
+    assert_eq!("cat", facts[1]["animal"]);
+    assert_eq!("cat fact", facts[1]["fact"]);
+    assert_eq!("first-request", facts[1]["request_id"]);
+    assert!(facts[1]["served_at"].is_string());
+}
+
+#[tokio::test]
+async fn get_animal_fact_fails_fast_when_upstream_cannot_be_connected_to() {
+    // an upstream whose accept queue is full, so connecting to it hangs like an unroutable host
+    let upstream = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+    upstream
+        .bind(&SocketAddr::from(([127, 0, 0, 1], 0)).into())
+        .unwrap();
+    upstream.listen(0).unwrap();
+    let upstream_addr = upstream.local_addr().unwrap().as_socket().unwrap();
+    let _queued: Vec<_> = (0..4)
+        .filter_map(|_| {
+            std::net::TcpStream::connect_timeout(&upstream_addr, Duration::from_millis(100)).ok()
+        })
+        .collect();
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("http://{upstream_addr}/facts/random");
+        settings.api.cat_fallback_urls.clear();
+        settings.api.timeout_ms = 10_000;
+        settings.api.connect_timeout_ms = 200;
+        settings.retry.max_retries = 0;
+    })
+    .await;
+
+    let started = Instant::now();
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(502, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("upstream_connect_failed", body["error"]["code"]);
+}
+
+#[tokio::test]
+async fn get_animal_fact_sends_configured_upstream_headers() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .and(header("authorization", "Bearer file-token"))
+        .and(header("x-plan", "paid"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let token = std::env::temp_dir().join(format!("coding-challenge-token-{}", Uuid::new_v4()));
+    std::fs::write(&token, "Bearer file-token\n").unwrap();
+    let headers = BTreeMap::from([
+        (
+            "authorization".to_string(),
+            HeaderValueSource::File {
+                file: token.display().to_string(),
+            },
+        ),
+        (
+            "x-plan".to_string(),
+            HeaderValueSource::Value("paid".into()),
+        ),
+    ]);
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.api.headers = BTreeMap::from([(mock_server.uri(), headers)]);
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let _ = std::fs::remove_file(&token);
+
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("cat fact", body["fact"]);
+}
+
+#[tokio::test]
+async fn upstream_headers_are_not_sent_across_a_redirect_to_another_origin() {
+    let mock_server = MockServer::start().await;
+    let other_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(302)
+                .insert_header("location", format!("{}/steal", other_server.uri()).as_str()),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&other_server)
+        .await;
+
+    let headers = BTreeMap::from([(
+        "x-api-key".to_string(),
+        HeaderValueSource::Value("secret".into()),
+    )]);
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.api.cat_fallback_urls.clear();
+        settings.api.headers = BTreeMap::from([(mock_server.uri(), headers)]);
+        settings.retry.max_retries = 0;
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
     assert!(!res.status().is_success());
 }
+
+#[tokio::test]
+async fn get_animal_fact_with_count_of_one_returns_single_fact() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/facts"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"facts": ["dog fact"]}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.dog_url = format!("{}/api/facts", mock_server.uri());
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/fact?animal=dog&count=1"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("dog fact", body["fact"]);
+    assert_eq!("dog", body["animal"]);
+    assert!(body.get("facts").is_none());
+}
+
+#[tokio::test]
+async fn get_animal_fact_with_count_asks_each_dog_api_with_its_count_param() {
+    let mock_server = MockServer::start().await;
+    let fallback_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/facts"))
+        .and(query_param("number", "2"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1..)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v2/facts"))
+        .and(query_param("limit", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            r#"{"data": [{"attributes": {"body": "first"}}, {"attributes": {"body": "second"}}]}"#,
+            "application/json",
+        ))
+        .expect(1)
+        .mount(&fallback_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.dog_url = format!("{}/api/facts", mock_server.uri());
+        settings.api.dog_fallback_urls = vec![format!("{}/api/v2/facts", fallback_server.uri())];
+        settings.api.count_params = BTreeMap::from([(fallback_server.uri(), "limit".into())]);
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/fact?animal=dog&count=2"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!(2, body["facts"].as_array().map_or(0, Vec::len));
+}
+
+#[tokio::test]
+async fn get_animal_fact_with_count_returns_multiple_facts() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .expect(3)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/fact?animal=cat&count=3"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("cat", body["animal"]);
+    assert_eq!(
+        3,
+        body["facts"]
+            .as_array()
+            .expect("Expected a facts array.")
+            .len()
+    );
+}
+
+#[tokio::test]
+async fn get_animal_fact_fails_when_count_out_of_range() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/fact?animal=cat&count=11"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn get_animals_lists_supported_animals() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/animals"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    let animals = body["animals"]
+        .as_array()
+        .expect("Expected an animals array.");
+    for animal in ["cat", "dog", "any"] {
+        assert!(animals.iter().any(|a| a == animal), "{animal} not listed");
+    }
+}
+
+#[tokio::test]
+async fn server_stops_when_shutdown_is_triggered() {
+    Lazy::force(&TRACING);
+
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind to random port");
+    let addr = listener.local_addr().unwrap();
+    let settings = get_config().expect("Failed to read config");
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let state = AppState::new(settings);
+    let server = coding_challenge::startup::run_until(listener, state, async {
+        let _ = shutdown_rx.await;
+    })
+    .expect("Failed to bind to address");
+    let handle = tokio::spawn(server.into_future());
+
+    let res = Client::new()
+        .get(format!("http://{addr}/health-check"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(res.status().is_success());
+
+    shutdown_tx.send(()).expect("Failed to trigger shutdown");
+    tokio::time::timeout(Duration::from_secs(5), handle)
+        .await
+        .expect("Server did not shut down in time")
+        .expect("Server task panicked")
+        .expect("Server returned an error");
+}
+
+#[tokio::test]
+async fn metrics_report_fact_requests() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{addr}/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(res.status().is_success());
+
+    let res = client
+        .get(format!("http://{addr}/metrics"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(res.status().is_success());
+
+    let body = res.text().await.expect("Failed to read response.");
+    assert!(body.contains(r#"http_requests_total{route="/fact",status="2xx"} 1"#));
+    assert!(body.contains(r#"animal_facts_total{animal="cat",outcome="ok"} 1"#));
+    assert!(body.contains("http_request_duration_seconds_bucket"));
+}
+
+#[tokio::test]
+async fn stats_reflect_fact_requests() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.cache.capacity = 1;
+    })
+    .await;
+
+    let client = Client::new();
+    for _ in 0..2 {
+        let res = client
+            .get(format!("http://{addr}/fact?animal=cat"))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert!(res.status().is_success());
+    }
+
+    let res = client
+        .get(format!("http://{addr}/stats"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!(2, body["facts"]["served"]);
+    assert_eq!(1, body["cache"]["misses"]);
+    assert_eq!(1, body["cache"]["hits"]);
+    assert_eq!(1, body["cache"]["size"]);
+    assert_eq!(1, body["upstream"]["requests"]);
+    assert_eq!(0, body["upstream"]["in_flight"]);
+    assert_eq!(0, body["upstream"]["client_rebuilds"]);
+    assert!(body["circuit_breakers"]
+        .as_object()
+        .unwrap()
+        .values()
+        .all(|state| state == "closed"));
+}
+
+#[tokio::test]
+async fn open_circuit_responds_with_the_time_left_as_retry_after() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.api.cat_fallback_urls.clear();
+        settings.retry.max_retries = 0;
+        settings.circuit_breaker.failure_threshold = 1;
+        settings.circuit_breaker.cooldown_secs = 60;
+        settings.cache.capacity = 0;
+    })
+    .await;
+
+    let client = Client::new();
+    let res = client
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(res.status().is_server_error());
+
+    let res = client
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(503, res.status().as_u16());
+    let retry_after: u64 = res.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((50..=60).contains(&retry_after));
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("circuit_open", body["error"]["code"]);
+    assert_eq!(retry_after, body["error"]["retry_after"]);
+}
+
+#[tokio::test]
+async fn readiness_check_returns_503_when_a_dependency_is_down() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/facts"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.api.dog_url = format!("{}/api/facts", mock_server.uri());
+        settings.readiness.dependencies = vec!["cat".into(), "dog".into()];
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/ready"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(503, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("up", body["dependencies"]["cat"]);
+    assert_eq!("down", body["dependencies"]["dog"]);
+}
+
+#[tokio::test]
+async fn healthz_reports_degraded_when_an_upstream_is_down() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/facts"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.api.dog_url = format!("{}/api/facts", mock_server.uri());
+        settings.readiness.dependencies = vec!["cat".into(), "dog".into()];
+    })
+    .await;
+
+    for _ in 0..2 {
+        let res = Client::new()
+            .get(format!("http://{addr}/healthz"))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(503, res.status().as_u16());
+        let body: Value = res.json().await.expect("Failed to parse response.");
+        assert_eq!("degraded", body["status"]);
+        assert_eq!("up", body["components"]["cat_api"]);
+        assert_eq!("down", body["components"]["dog_api"]);
+        assert_eq!("ok", body["components"]["cache"]);
+    }
+}
+
+#[tokio::test]
+async fn get_animal_fact_is_rate_limited() {
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.rate_limit.per_second = 1;
+        settings.rate_limit.burst = 2;
+    })
+    .await;
+
+    let client = Client::new();
+
+    let mut limited = None;
+    for _ in 0..3 {
+        let res = client
+            .get(format!("http://{addr}/fact"))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        if res.status().as_u16() == 429 {
+            limited = Some(res);
+        }
+    }
+
+    let res = limited.expect("Expected a 429 response.");
+    assert!(res.headers().contains_key("retry-after"));
+}
+
+#[tokio::test]
+async fn openapi_spec_describes_fact_path() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/api-docs/openapi.json"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    let fact = &body["paths"]["/v1/fact"]["get"];
+    assert!(fact.is_object());
+    let animal = fact["parameters"]
+        .as_array()
+        .expect("Expected parameters.")
+        .iter()
+        .find(|p| p["name"] == "animal")
+        .expect("Expected an animal parameter.");
+    // it may be left out when a default animal is configured
+    assert_eq!(false, animal["required"]);
+}
+
+#[tokio::test]
+async fn clients_are_rate_limited_by_forwarded_address() {
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.rate_limit.enabled = false;
+        settings.rate_limit.per_client.enabled = true;
+        settings.rate_limit.per_client.per_second = 1;
+        settings.rate_limit.per_client.burst = 2;
+    })
+    .await;
+
+    let client = Client::new();
+    let get = |forwarded_for: &'static str| {
+        client
+            .get(format!("http://{addr}/v1/fact?animal=dragon"))
+            .header("X-Forwarded-For", forwarded_for)
+            .send()
+    };
+
+    for _ in 0..2 {
+        let res = get("203.0.113.1")
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(400, res.status().as_u16());
+    }
+    let res = get("203.0.113.1")
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(429, res.status().as_u16());
+    assert!(res.headers().contains_key("retry-after"));
+
+    // addresses left of the one the proxy appended are the client's own, so don't get a new bucket
+    for spoofed in ["10.0.0.1, 198.51.100.2", "10.0.0.2, 198.51.100.2"] {
+        let res = get(spoofed).await.expect("Failed to execute request.");
+        assert_eq!(400, res.status().as_u16());
+    }
+    let res = get("10.0.0.3, 198.51.100.2")
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(429, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn get_animal_fact_returns_401_without_api_key() {
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.auth.api_keys = vec!["secret".into()];
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(401, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn get_animal_fact_returns_403_with_wrong_api_key() {
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.auth.api_keys = vec!["secret".into()];
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/fact?animal=cat"))
+        .header("X-API-Key", "wrong")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(403, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn get_animal_fact_succeeds_with_valid_api_key() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.auth.api_keys = vec!["secret".into()];
+    })
+    .await;
+
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{addr}/fact?animal=cat"))
+        .header("X-API-Key", "secret")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(res.status().is_success());
+
+    let res = client
+        .get(format!("http://{addr}/health-check"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(res.status().is_success());
+}
+
+#[tokio::test]
+async fn post_graphql_resolves_fact() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+
+    let client = Client::new();
+
+    let res = client
+        .post(format!("http://{addr}/graphql"))
+        .json(&serde_json::json!({ "query": "{ fact(animal: \"cat\") { fact animal } animals }" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.unwrap();
+    assert_eq!("cat fact", body["data"]["fact"]["fact"]);
+    assert_eq!("cat", body["data"]["fact"]["animal"]);
+    assert!(body["data"]["animals"]
+        .as_array()
+        .unwrap()
+        .contains(&Value::from("dog")));
+
+    let res = client
+        .post(format!("http://{addr}/graphql"))
+        .json(&serde_json::json!({ "query": "{ fact(animal: \"dragon\") { fact } }" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(
+        "unsupported_animal",
+        body["errors"][0]["extensions"]["code"]
+    );
+
+    let res = client
+        .get(format!("http://{addr}/graphql"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    assert!(res.text().await.unwrap().contains("GraphiQL"));
+}
+
+#[tokio::test]
+async fn get_animal_fact_serves_fallback_fact_when_upstream_fails() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.retry.max_retries = 0;
+        settings.facts.fallback = true;
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!("no-store", res.headers()["cache-control"]);
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("fallback", body["source"]);
+    assert!(fallback_facts("cat").contains(&body["fact"].as_str().unwrap()));
+
+    // a bare fact would lose its marker, so the envelope is kept
+    let body: Value = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat&envelope=false"))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response.");
+    assert_eq!("fallback", body["source"]);
+
+    let stats: Value = Client::new()
+        .get(format!("http://{addr}/stats"))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response.");
+    assert_eq!(2, stats["facts"]["fallback"]);
+}
+
+#[tokio::test]
+async fn post_user_fact_stores_fact_served_to_later_requests() {
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.auth.api_keys = vec!["secret".into()];
+        settings.user_facts.share = 1.0;
+    })
+    .await;
+
+    let client = Client::new();
+
+    let res = client
+        .post(format!("http://{addr}/v1/fact"))
+        .header("X-API-Key", "secret")
+        .json(&serde_json::json!({ "animal": "cat", "fact": "A group of cats is a clowder." }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(201, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("user", body["source"]);
+
+    let res = client
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .header("X-API-Key", "secret")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("A group of cats is a clowder.", body["fact"]);
+    assert_eq!("user", body["source"]);
+
+    let res = client
+        .post(format!("http://{addr}/graphql"))
+        .header("X-API-Key", "secret")
+        .json(&serde_json::json!({ "query": "{ fact(animal: \"cat\") { fact source } }" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!(
+        "A group of cats is a clowder.",
+        body["data"]["fact"]["fact"]
+    );
+    assert_eq!("user", body["data"]["fact"]["source"]);
+}
+
+#[tokio::test]
+async fn post_user_fact_rejects_too_long_fact() {
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.auth.api_keys = vec!["secret".into()];
+        settings.user_facts.max_len = 10;
+    })
+    .await;
+
+    let res = Client::new()
+        .post(format!("http://{addr}/v1/fact"))
+        .header("X-API-Key", "secret")
+        .json(&serde_json::json!({ "animal": "cat", "fact": "Cats have five toes on each front paw." }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("validation_failed", body["error"]["code"]);
+    assert!(body["error"]["errors"]["fact"].is_array());
+}
+
+#[tokio::test]
+async fn post_user_fact_replays_the_stored_response_for_a_repeated_idempotency_key() {
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.auth.api_keys = vec!["secret".into(), "other".into()];
+        settings.user_facts.share = 1.0;
+    })
+    .await;
+
+    let client = Client::new();
+    let submit = |api_key: &'static str, fact: &'static str| {
+        client
+            .post(format!("http://{addr}/v1/fact"))
+            .header("X-API-Key", api_key)
+            .header("Idempotency-Key", "submission-1")
+            .json(&serde_json::json!({ "animal": "cat", "fact": fact }))
+            .send()
+    };
+    let mut bodies = vec![];
+    for _ in 0..2 {
+        let res = submit("secret", "Cats purr.")
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(201, res.status().as_u16());
+        let replayed = res.headers().get("idempotent-replayed").cloned();
+        let body: Value = res.json().await.expect("Failed to parse response.");
+        bodies.push((replayed, body));
+    }
+
+    assert_eq!(None, bodies[0].0);
+    assert_eq!("true", bodies[1].0.as_ref().unwrap());
+    assert_eq!(bodies[0].1, bodies[1].1);
+    assert_eq!("Cats purr.", bodies[1].1["fact"]);
+
+    // the key can't be reused for a different fact
+    let res = submit("secret", "Cats purr at 25 Hz.")
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(422, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("idempotency_key_reused", body["error"]["code"]);
+
+    // another client's key of the same name is its own
+    let res = submit("other", "Cats purr.")
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(201, res.status().as_u16());
+    assert!(!res.headers().contains_key("idempotent-replayed"));
+
+    // the same fact was stored twice, by each client, so it's the only user fact served
+    for _ in 0..5 {
+        let body: Value = client
+            .get(format!("http://{addr}/v1/fact?animal=cat"))
+            .header("X-API-Key", "secret")
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .json()
+            .await
+            .expect("Failed to parse response.");
+        assert_eq!("Cats purr.", body["fact"]);
+    }
+}
+
+#[tokio::test]
+async fn post_user_fact_requires_api_keys_to_be_configured() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .post(format!("http://{addr}/v1/fact"))
+        .json(&serde_json::json!({ "animal": "cat", "fact": "Cats purr." }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(403, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn get_version_reports_the_crate_version() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/version"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(env!("CARGO_PKG_VERSION"), body["version"]);
+    assert!(body["commit"].is_string());
+}
+
+#[tokio::test]
+async fn put_log_level_changes_the_level_with_admin_api_key() {
+    // a subscriber of its own, so the level of the one shared by the other tests stays put
+    let (_sub, log_level) = get_subscriber("test".into(), "debug".into(), std::io::sink, None);
+    let TestApp { addr } = spawn_app_with_log_level(log_level, |settings| {
+        settings.auth.admin_api_keys = vec!["admin".into()];
+    })
+    .await;
+
+    let client = Client::new();
+    let put_level = |level: &str, key: &str| {
+        client
+            .put(format!("http://{addr}/admin/log-level"))
+            .header("X-API-Key", key)
+            .json(&serde_json::json!({ "level": level }))
+            .send()
+    };
+
+    let res = put_level("info", "admin")
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.unwrap();
+    assert_eq!("info", body["level"]);
+
+    let res = put_level("debug", "admin")
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.unwrap();
+    assert_eq!("info", body["previous"]);
+    assert_eq!("debug", body["level"]);
+
+    let res = put_level("chatty", "admin")
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(400, res.status().as_u16());
+    let body: Value = res.json().await.unwrap();
+    assert_eq!("invalid_log_level", body["error"]["code"]);
+
+    let res = put_level("info", "wrong")
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(403, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn large_responses_are_compressed() {
+    let TestApp { addr } = spawn_app().await;
+
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{addr}/api-docs/openapi.json"))
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(res.status().is_success());
+    assert_eq!(
+        Some("gzip"),
+        res.headers()
+            .get("content-encoding")
+            .and_then(|v| v.to_str().ok())
+    );
+
+    let res = client
+        .get(format!("http://{addr}/animals"))
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(res.status().is_success());
+    assert!(res.headers().get("content-encoding").is_none());
+}
+
+#[tokio::test]
+async fn cors_only_allows_configured_origins() {
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.cors.allowed_origins = vec!["http://allowed.example".into()];
+    })
+    .await;
+
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{addr}/animals"))
+        .header("Origin", "http://evil.example")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(res.headers().get("access-control-allow-origin").is_none());
+
+    let res = client
+        .get(format!("http://{addr}/animals"))
+        .header("Origin", "http://allowed.example")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(
+        Some("http://allowed.example"),
+        res.headers()
+            .get("access-control-allow-origin")
+            .and_then(|v| v.to_str().ok())
+    );
+}
+
+#[tokio::test]
+async fn get_animal_fact_translates_fact() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/translate"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"translatedText": "dato de gato"}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.translation.url = format!("{}/translate", mock_server.uri());
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/fact?animal=cat&lang=es"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("dato de gato", body["fact"]);
+    assert_eq!("es", body["lang"]);
+}
+
+#[tokio::test]
+async fn get_animal_fact_translates_fact_into_several_languages() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+    for (lang, translated) in [("es", "dato de gato"), ("fr", "fait de chat")] {
+        Mock::given(method("POST"))
+            .and(path("/translate"))
+            .and(body_partial_json(serde_json::json!({ "target": lang })))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "translatedText": translated })),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+    }
+    Mock::given(method("POST"))
+        .and(path("/translate"))
+        .and(body_partial_json(serde_json::json!({ "target": "de" })))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.translation.url = format!("{}/translate", mock_server.uri());
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat&langs=es,fr,de"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("cat fact", body["fact"]);
+    assert_eq!(
+        serde_json::json!({ "es": "dato de gato", "fr": "fait de chat", "de": null }),
+        body["translations"]
+    );
+
+    // the translations would be lost from a bare fact, so the envelope is kept
+    let body: Value = Client::new()
+        .get(format!(
+            "http://{addr}/v1/fact?animal=cat&langs=de&envelope=false"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response.");
+    assert_eq!("cat fact", body["fact"]);
+    assert_eq!(Some(&Value::Null), body["translations"].get("de"));
+
+    for query in ["count=2", "all_sources=true"] {
+        let res = Client::new()
+            .get(format!("http://{addr}/v1/fact?animal=cat&langs=es&{query}"))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(400, res.status().as_u16(), "{query}");
+    }
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat,dog&langs=es"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(400, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn get_animal_fact_falls_back_to_english_when_translation_fails() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/translate"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.translation.url = format!("{}/translate", mock_server.uri());
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/fact?animal=cat&lang=es"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("cat fact", body["fact"]);
+    assert_eq!("en", body["lang"]);
+}
+
+#[tokio::test]
+async fn get_animal_fact_returns_404_when_no_fact_within_max_len() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            r#"{"text": "a cat fact that is far too long"}"#,
+            "application/json",
+        ))
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.filter.max_attempts = 2;
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/fact?animal=cat&max_len=10"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(404, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("no_matching_fact", body["error"]["code"]);
+}
+
+#[tokio::test]
+async fn post_fact_batch_returns_per_item_results_in_order() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+
+    let res = Client::new()
+        .post(format!("http://{addr}/fact/batch"))
+        .json(&serde_json::json!({ "animals": ["cat", "dragon", "kitty"] }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    let items = body.as_array().expect("Response is not an array");
+    assert_eq!(3, items.len());
+    assert_eq!("cat fact", items[0]["fact"]);
+    assert_eq!("dragon", items[1]["animal"]);
+    assert_eq!("unsupported_animal", items[1]["error"]["code"]);
+    assert_eq!("cat", items[2]["animal"]);
+    assert_eq!("cat fact", items[2]["fact"]);
+}
+
+#[tokio::test]
+async fn get_all_facts_returns_a_fact_or_error_per_animal() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/cat"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/dog"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"facts": ["dog fact"]}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/bird"))
+        .respond_with(ResponseTemplate::new(400))
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/cat", mock_server.uri());
+        settings.api.dog_url = format!("{}/dog", mock_server.uri());
+        settings.api.bird_url = format!("{}/bird", mock_server.uri());
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/facts/all"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("cat fact", body["cat"]);
+    assert_eq!("dog fact", body["dog"]);
+    assert_eq!("upstream_error", body["bird"]["error"]["code"]);
+}
+
+#[tokio::test]
+async fn post_fact_batch_returns_400_for_invalid_body() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .post(format!("http://{addr}/fact/batch"))
+        .header("content-type", "application/json")
+        .body("{\"animals\": [")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("invalid_body", body["error"]["code"]);
+}
+
+#[tokio::test]
+async fn post_fact_batch_returns_field_errors_for_invalid_animals() {
+    let TestApp { addr } = spawn_app().await;
+
+    let client = Client::new();
+    for (body, expected) in [
+        (serde_json::json!({ "animal": "cat" }), vec!["is required"]),
+        (
+            serde_json::json!({ "animals": [] }),
+            vec!["must list at least one animal"],
+        ),
+        (
+            serde_json::json!({ "animals": ["cat", 1, null] }),
+            vec!["item 1 must be a string", "item 2 must be a string"],
+        ),
+        (
+            serde_json::json!({ "animals": "cat" }),
+            vec!["must be an array of animal names"],
+        ),
+    ] {
+        let res = client
+            .post(format!("http://{addr}/v1/fact/batch"))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(400, res.status().as_u16());
+        let body: Value = res.json().await.expect("Failed to parse response.");
+        assert_eq!("validation_failed", body["error"]["code"]);
+        assert_eq!(
+            serde_json::json!(expected),
+            body["error"]["errors"]["animals"]
+        );
+    }
+}
+
+#[tokio::test]
+async fn get_daily_fact_is_stable_for_a_date() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "first cat fact"}"#, "application/json"),
+        )
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "second cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+
+    let client = Client::new();
+    let mut bodies = vec![];
+    for _ in 0..2 {
+        let res = client
+            .get(format!(
+                "http://{addr}/fact/daily?animal=cat&date=2024-01-01"
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(200, res.status().as_u16());
+        bodies.push(
+            res.json::<Value>()
+                .await
+                .expect("Failed to parse response."),
+        );
+    }
+
+    assert_eq!(bodies[0], bodies[1]);
+    assert_eq!("first cat fact", bodies[0]["fact"]);
+}
+
+#[tokio::test]
+async fn excess_concurrent_requests_are_shed_with_503() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "cat fact"}"#, "application/json")
+                .set_delay(Duration::from_millis(500)),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.limits.max_concurrent_requests = 1;
+    })
+    .await;
+
+    let client = Client::new();
+    let url = format!("http://{addr}/fact?animal=cat");
+    let (first, second) = tokio::join!(client.get(&url).send(), async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        client.get(&url).send().await
+    });
+
+    assert_eq!(
+        200,
+        first.expect("Failed to execute request.").status().as_u16()
+    );
+    let second = second.expect("Failed to execute request.");
+    assert_eq!(503, second.status().as_u16());
+    let body: Value = second.json().await.expect("Failed to parse response.");
+    assert_eq!("overloaded", body["error"]["code"]);
+}
+
+#[tokio::test]
+async fn get_animal_fact_is_served_under_v1_and_unprefixed() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    assert!(res.headers().get("deprecation").is_none());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("cat fact", body["fact"]);
+
+    let res = client
+        .get(format!("http://{addr}/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!("true", res.headers()["deprecation"]);
+    assert_eq!(
+        r#"</v1/fact>; rel="successor-version""#,
+        res.headers()["link"]
+    );
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("cat fact", body["fact"]);
+}
+
+/// Reads the `max-age` of a response's `Cache-Control` header.
+fn max_age(res: &reqwest::Response) -> Option<u64> {
+    res.headers()
+        .get("cache-control")?
+        .to_str()
+        .ok()?
+        .split(',')
+        .find_map(|directive| directive.trim().strip_prefix("max-age="))?
+        .parse()
+        .ok()
+}
+
+#[tokio::test]
+async fn get_daily_fact_is_cacheable_until_the_end_of_the_day() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact/daily?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let max_age = max_age(&res).expect("Missing max-age");
+    assert!(max_age > 0 && max_age <= 24 * 60 * 60, "{max_age}");
+}
+
+#[tokio::test]
+async fn get_daily_fact_for_a_future_date_is_not_cached_past_today() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+    let client = Client::new();
+    let today = chrono::Utc::now().date_naive();
+
+    let tomorrow = today + chrono::Duration::days(1);
+    let res = client
+        .get(format!(
+            "http://{addr}/v1/fact/daily?animal=cat&date={tomorrow}"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let max_age = max_age(&res).expect("Missing max-age");
+    assert!(max_age <= 24 * 60 * 60, "{max_age}");
+
+    let next_year = today + chrono::Duration::days(365);
+    let res = client
+        .get(format!(
+            "http://{addr}/v1/fact/daily?animal=cat&date={next_year}"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!("no-store", res.headers()["cache-control"]);
+}
+
+#[tokio::test]
+async fn cached_facts_are_cacheable_for_their_remaining_ttl() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.cache.capacity = 1;
+        settings.cache.ttl_secs = 60;
+    })
+    .await;
+    let client = Client::new();
+    let url = format!("http://{addr}/v1/fact?animal=cat");
+
+    // fetched from upstream, then served from the cache
+    let res = client
+        .get(&url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!("no-store", res.headers()["cache-control"]);
+    let res = client
+        .get(&url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let max_age = max_age(&res).expect("Missing max-age");
+    assert!(max_age > 0 && max_age <= 60, "{max_age}");
+    assert!(res.headers()["cache-control"]
+        .to_str()
+        .unwrap()
+        .starts_with("public"));
+    assert!(vary(&res).starts_with(&["accept".into()]));
+}
+
+#[tokio::test]
+async fn cached_facts_are_private_and_vary_by_api_key_when_keys_are_configured() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.cache.capacity = 1;
+        settings.cache.ttl_secs = 60;
+        settings.auth.api_keys = vec!["secret".into()];
+    })
+    .await;
+    let client = Client::new();
+    let url = format!("http://{addr}/v1/fact?animal=cat");
+
+    for _ in 0..2 {
+        let res = client
+            .get(&url)
+            .header("X-API-Key", "secret")
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert!(vary(&res).starts_with(&["accept".into(), "x-api-key".into()]));
+        if let Some(max_age) = max_age(&res) {
+            assert_eq!(
+                format!("private, max-age={max_age}"),
+                res.headers()["cache-control"]
+            );
+        }
+    }
+}
+
+/// Reads the header names listed in a response's `Vary` headers.
+fn vary(res: &reqwest::Response) -> Vec<String> {
+    res.headers()
+        .get_all("vary")
+        .iter()
+        .flat_map(|vary| vary.to_str().unwrap().split(','))
+        .map(|name| name.trim().to_lowercase())
+        .collect()
+}
+
+#[tokio::test]
+async fn get_daily_fact_returns_304_when_not_modified_since() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+    let client = Client::new();
+    let url = format!("http://{addr}/v1/fact/daily?animal=cat&date=2024-01-01");
+
+    let res = client
+        .get(&url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let last_modified = res.headers()["last-modified"].clone();
+    assert_eq!("Mon, 01 Jan 2024 00:00:00 GMT", last_modified);
+
+    let res = client
+        .get(&url)
+        .header("if-modified-since", last_modified)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(304, res.status().as_u16());
+
+    let res = client
+        .get(&url)
+        .header("if-modified-since", "Sun, 31 Dec 2023 00:00:00 GMT")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn get_daily_fact_returns_304_when_etag_matches() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+    let client = Client::new();
+    let url = format!("http://{addr}/v1/fact/daily?animal=cat&date=2024-01-01");
+
+    let res = client
+        .get(&url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let etag = res.headers()["etag"].clone();
+
+    let res = client
+        .get(&url)
+        .header("if-none-match", etag)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(304, res.status().as_u16());
+    assert!(res.text().await.expect("Failed to read body.").is_empty());
+}
+
+#[tokio::test]
+async fn get_animal_fact_returns_plain_text_when_accepted() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .header("accept", "text/plain")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    assert!(res.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    assert_eq!("cat fact", res.text().await.expect("Failed to read body."));
+}
+
+#[tokio::test]
+async fn get_animal_fact_returns_406_for_unacceptable_type() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .header("accept", "image/png")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(406, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("not_acceptable", body["error"]["code"]);
+}
+
+#[tokio::test]
+async fn inbound_request_id_is_reused_on_configured_header() {
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.application.request_id_header = "x-correlation-id".into();
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/health-check"))
+        .header("x-correlation-id", "abc-123")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!("abc-123", res.headers()["x-correlation-id"]);
+    assert!(res.headers().get("x-request-id").is_none());
+}
+
+#[tokio::test]
+async fn request_id_is_echoed_in_json_bodies_when_enabled() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.application.request_id_in_body = true;
+        settings.auth.api_keys = vec!["secret".into()];
+    })
+    .await;
+
+    let client = Client::new();
+    for (query, key, status) in [
+        ("animal=cat", "secret", 200),
+        ("animal=dragon", "secret", 400),
+        ("animal=cat", "wrong", 403),
+    ] {
+        let res = client
+            .get(format!("http://{addr}/v1/fact?{query}"))
+            .header("X-API-Key", key)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(status, res.status().as_u16());
+        let id = res.headers()["x-request-id"].to_str().unwrap().to_string();
+        let body: Value = res.json().await.expect("Failed to parse response.");
+        assert_eq!(id, body["request_id"]);
+    }
+}
+
+#[tokio::test]
+async fn missing_request_id_is_generated_unless_strict() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/animals"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let id = res.headers()["x-request-id"].to_str().unwrap();
+    assert!(Uuid::parse_str(id).is_ok());
+}
+
+#[tokio::test]
+async fn invalid_request_id_is_replaced_by_a_generated_one() {
+    let TestApp { addr } = spawn_app().await;
+
+    let client = Client::new();
+    let long_id = "a".repeat(129);
+    for id in ["has spaces", "<script>", long_id.as_str()] {
+        let res = client
+            .get(format!("http://{addr}/v1/animals"))
+            .header("x-request-id", id)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(200, res.status().as_u16());
+        let echoed = res.headers()["x-request-id"].to_str().unwrap();
+        assert!(Uuid::parse_str(echoed).is_ok(), "{echoed}");
+    }
+
+    let res = client
+        .get(format!("http://{addr}/v1/animals"))
+        .header("x-request-id", "trace-abc_123")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!("trace-abc_123", res.headers()["x-request-id"]);
+}
+
+#[tokio::test]
+async fn strict_request_id_rejects_missing_or_malformed_ids() {
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.application.strict_request_id = true;
+    })
+    .await;
+
+    let client = Client::new();
+    for id in [None, Some("abc-123")] {
+        let mut req = client.get(format!("http://{addr}/v1/animals"));
+        if let Some(id) = id {
+            req = req.header("x-request-id", id);
+        }
+        let res = req.send().await.expect("Failed to execute request.");
+        assert_eq!(400, res.status().as_u16());
+        let body: Value = res.json().await.expect("Failed to parse response.");
+        assert_eq!("invalid_request_id", body["error"]["code"]);
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let res = client
+        .get(format!("http://{addr}/v1/animals"))
+        .header("x-request-id", &id)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!(id, res.headers()["x-request-id"]);
+
+    // the probes still answer without one
+    let res = client
+        .get(format!("http://{addr}/health-check"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn get_animal_fact_passes_on_upstream_429_with_retry_after() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(ResponseTemplate::new(429))
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.api.cat_fallback_urls = vec![];
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(429, res.status().as_u16());
+    assert!(res.headers().contains_key("retry-after"));
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("upstream_error", body["error"]["code"]);
+}
+
+#[tokio::test]
+async fn get_animal_fact_with_same_seed_picks_same_animal() {
+    let mock_server = MockServer::start().await;
+
+    for (route, body) in [
+        ("/cat", r#"{"text": "cat fact"}"#),
+        ("/dog", r#"{"facts": ["dog fact"]}"#),
+        ("/bird", r#"{"fact": "bird fact"}"#),
+    ] {
+        Mock::given(method("GET"))
+            .and(path(route))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/json"))
+            .mount(&mock_server)
+            .await;
+    }
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/cat", mock_server.uri());
+        settings.api.dog_url = format!("{}/dog", mock_server.uri());
+        settings.api.dog_fallback_urls = vec![];
+        settings.api.cat_fallback_urls = vec![];
+        settings.api.bird_url = format!("{}/bird", mock_server.uri());
+    })
+    .await;
+    let client = Client::new();
+
+    for seed in 0..5 {
+        let mut animals = vec![];
+        for _ in 0..2 {
+            let res = client
+                .get(format!("http://{addr}/v1/fact?animal=any&seed={seed}"))
+                .send()
+                .await
+                .expect("Failed to execute request.");
+            assert_eq!(200, res.status().as_u16());
+            let body: Value = res.json().await.expect("Failed to parse response.");
+            animals.push(body["animal"].clone());
+        }
+        assert_eq!(animals[0], animals[1]);
+    }
+}
+
+#[tokio::test]
+async fn repeated_unsupported_animal_is_rejected_from_cache() {
+    let TestApp { addr } = spawn_app().await;
+    let client = Client::new();
+
+    for _ in 0..2 {
+        let res = client
+            .get(format!("http://{addr}/v1/fact?animal=dragon"))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(400, res.status().as_u16());
+        let body: Value = res.json().await.expect("Failed to parse response.");
+        assert_eq!("unsupported_animal", body["error"]["code"]);
+    }
+
+    let body = client
+        .get(format!("http://{addr}/metrics"))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .expect("Failed to read response.");
+    assert!(body.contains("unsupported_animals 1"));
+    assert!(body.contains("unsupported_animal_cache_hits_total 1"));
+}
+
+#[tokio::test]
+async fn get_animal_fact_returns_a_fact_per_comma_separated_animal() {
+    let mock_server = MockServer::start().await;
+
+    for (route, body) in [
+        ("/cat", r#"{"text": "cat fact"}"#),
+        ("/dog", r#"{"facts": ["dog fact"]}"#),
+    ] {
+        Mock::given(method("GET"))
+            .and(path(route))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/json"))
+            .mount(&mock_server)
+            .await;
+    }
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/cat", mock_server.uri());
+        settings.api.dog_url = format!("{}/dog", mock_server.uri());
+        settings.api.dog_fallback_urls = vec![];
+        settings.api.cat_fallback_urls = vec![];
+    })
+    .await;
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{addr}/v1/fact?animal=cat,dog,CAT"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    let results = body["results"].as_array().expect("Missing results");
+    assert_eq!(2, results.len());
+    assert_eq!("cat fact", results[0]["fact"]);
+    assert_eq!("dog", results[1]["animal"]);
+    assert_eq!("dog fact", results[1]["fact"]);
+
+    let res = client
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("cat fact", body["fact"]);
+    assert!(body.get("results").is_none());
+}
+
+#[tokio::test]
+async fn post_fact_batch_rejects_too_many_animals() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .expect(0)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.batch.max_batch_size = 3;
+    })
+    .await;
+
+    let res = Client::new()
+        .post(format!("http://{addr}/v1/fact/batch"))
+        .json(&serde_json::json!({ "animals": ["cat", "cat", "cat", "cat"] }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("too_many_animals", body["error"]["code"]);
+    assert_eq!(
+        "At most 3 animals may be requested at once.",
+        body["error"]["message"]
+    );
+}
+
+#[tokio::test]
+async fn get_animal_fact_rejects_commas_without_animals() {
+    let TestApp { addr } = spawn_app().await;
+
+    for animal in [",", " , ,"] {
+        let res = Client::new()
+            .get(format!("http://{addr}/v1/fact"))
+            .query(&[("animal", animal)])
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(400, res.status().as_u16());
+        let body: Value = res.json().await.expect("Failed to parse response.");
+        assert_eq!("unsupported_animal", body["error"]["code"]);
+    }
+}
+
+#[tokio::test]
+async fn get_animal_fact_rejects_too_many_animals() {
+    let TestApp { addr } = spawn_app_with(|settings| settings.batch.max_batch_size = 5).await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat,dog,bird,a,b,c"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("too_many_animals", body["error"]["code"]);
+}
+
+#[tokio::test]
+async fn get_animal_fact_refetches_until_min_len_is_met() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "short"}"#, "application/json"),
+        )
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "a much longer cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.filter.max_attempts = 2;
+    })
+    .await;
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{addr}/v1/fact?animal=cat&min_len=10"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("a much longer cat fact", body["fact"]);
+
+    let res = client
+        .get(format!("http://{addr}/v1/fact?animal=cat&min_len=100"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(404, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("no_matching_fact", body["error"]["code"]);
+}
+
+#[tokio::test]
+async fn get_animal_fact_refetches_until_fact_contains_keyword() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/facts"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"facts": ["Dogs can smell fear."]}"#, "application/json"),
+        )
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/facts"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            r#"{"facts": ["A dog's Tail shows how it feels."]}"#,
+            "application/json",
+        ))
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.dog_url = format!("{}/api/facts", mock_server.uri());
+        settings.filter.max_attempts = 2;
+    })
+    .await;
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{addr}/v1/fact?animal=dog&contains=tail"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("A dog's Tail shows how it feels.", body["fact"]);
+
+    let res = client
+        .get(format!(
+            "http://{addr}/v1/fact?animal=dog&contains=whiskers"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(404, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("no_matching_fact", body["error"]["code"]);
+
+    let res = client
+        .get(format!(
+            "http://{addr}/v1/fact?animal=dog&contains={}",
+            "a".repeat(65)
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(400, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!(
+        "must be between 1 and 64 characters",
+        body["error"]["errors"]["contains"][0]
+    );
+}
+
+#[tokio::test]
+async fn get_animal_fact_rejects_min_len_above_max_len() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .get(format!(
+            "http://{addr}/v1/fact?animal=cat&min_len=20&max_len=10"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("validation_failed", body["error"]["code"]);
+}
+
+#[tokio::test]
+async fn get_animal_fact_skips_facts_with_banned_words() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "Cats are Darn clever."}"#, "application/json"),
+        )
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "Cats sleep a lot."}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.filter.max_attempts = 2;
+        settings.filter.banned_words = vec!["darn".into()];
+    })
+    .await;
+
+    let client = Client::new();
+    let res = client
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("Cats sleep a lot.", body["fact"]);
+
+    // only the fact without a banned word was cached
+    let res = client
+        .get(format!("http://{addr}/stats"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!(1, body["cache"]["size"]);
+}
+
+#[tokio::test]
+async fn get_animal_fact_includes_source_when_requested() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            r#"{"_id": "abc123", "text": "cat fact"}"#,
+            "application/json",
+        ))
+        .mount(&mock_server)
+        .await;
+
+    let cat_url = format!("{}/facts/random", mock_server.uri());
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = cat_url.clone();
+    })
+    .await;
+    let client = Client::new();
+
+    let res = client
+        .get(format!(
+            "http://{addr}/v1/fact?animal=cat&include_source=true"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!(cat_url, body["source"]["url"]);
+    assert_eq!("abc123", body["source"]["id"]);
+
+    let res = client
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert!(body.get("source").is_none());
+}
+
+#[tokio::test]
+async fn disabled_features_return_404() {
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.features.stream = false;
+        settings.features.submissions = false;
+        settings.auth.api_keys = vec!["secret".into()];
+    })
+    .await;
+
+    let client = Client::new();
+    let res = client
+        .get(format!("http://{addr}/v1/fact/stream?animal=cat"))
+        .header("X-API-Key", "secret")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(404, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("not_found", body["error"]["code"]);
+
+    let res = client
+        .post(format!("http://{addr}/v1/fact"))
+        .header("X-API-Key", "secret")
+        .json(&serde_json::json!({ "animal": "cat", "fact": "A group of cats is a clowder." }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(404, res.status().as_u16());
+
+    // the other endpoints are still served
+    let res = client
+        .get(format!("http://{addr}/v1/animals"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn get_fact_stream_sends_facts_until_client_disconnects() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "streamed cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+
+    let mut res = Client::new()
+        .get(format!(
+            "http://{addr}/v1/fact/stream?animal=cat&interval=1s"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    assert!(res.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/event-stream"));
+
+    let mut received = String::new();
+    while received.matches("event: fact").count() < 2 {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), res.chunk())
+            .await
+            .expect("Timed out waiting for an event")
+            .expect("Failed to read the stream")
+            .expect("Stream ended early");
+        received.push_str(&String::from_utf8_lossy(&chunk));
+    }
+    assert!(received.contains(r#"data: {"animal":"cat","fact":"streamed cat fact"}"#));
+    drop(res);
+
+    // once the client is gone no more facts are fetched
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let fetched = mock_server.received_requests().await.unwrap().len();
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(
+        fetched,
+        mock_server.received_requests().await.unwrap().len()
+    );
+}
+
+#[tokio::test]
+async fn get_fact_stream_rejects_out_of_range_interval() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .get(format!(
+            "http://{addr}/v1/fact/stream?animal=cat&interval=10ms"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("validation_failed", body["error"]["code"]);
+}
+
+#[cfg(feature = "redis")]
+#[tokio::test]
+async fn get_animal_fact_treats_unavailable_redis_as_a_cache_miss() {
+    use coding_challenge::config::CacheBackend;
+
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "uncached cat fact"}"#, "application/json"),
+        )
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.cache.backend = CacheBackend::Redis;
+        // nothing listens on port 1, so every connection attempt is refused
+        settings.cache.redis.url = "redis://127.0.0.1:1".into();
+        settings.cache.capacity = 1;
+    })
+    .await;
+    let client = Client::new();
+
+    for _ in 0..2 {
+        let res = client
+            .get(format!("http://{addr}/v1/fact?animal=cat"))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(200, res.status().as_u16());
+        let body: Value = res.json().await.expect("Failed to parse response.");
+        assert_eq!("uncached cat fact", body["fact"]);
+    }
+}
+
+#[tokio::test]
+async fn errors_are_problem_documents_when_accepted() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=dragon"))
+        .header("accept", "application/problem+json")
+        .header("x-request-id", "problem-request")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+    assert_eq!("application/problem+json", res.headers()["content-type"]);
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("/problems/unsupported_animal", body["type"]);
+    assert_eq!("Unsupported animal", body["title"]);
+    assert_eq!(400, body["status"]);
+    assert_eq!("'dragon' is not a supported animal.", body["detail"]);
+    assert_eq!("problem-request", body["instance"]);
+}
+
+#[tokio::test]
+async fn compressed_errors_are_problem_documents() {
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.compression.min_size_bytes = 0;
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=dragon"))
+        .header("accept", "application/problem+json")
+        .header("accept-encoding", "gzip")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+    assert_eq!("gzip", res.headers()["content-encoding"]);
+    assert_eq!("application/problem+json", res.headers()["content-type"]);
+}
+
+#[tokio::test]
+async fn errors_are_problem_documents_when_configured() {
+    use coding_challenge::config::ErrorFormat;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.errors.format = ErrorFormat::Problem;
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+    assert_eq!("application/problem+json", res.headers()["content-type"]);
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("/problems/validation_failed", body["type"]);
+    assert!(body["instance"].is_string());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn listeners_with_reuse_port_share_a_port() {
+    use coding_challenge::startup::bind_listener;
+
+    let mut settings = get_config().expect("Failed to read config").application;
+    settings.host = "127.0.0.1".into();
+    settings.port = 0;
+    settings.reuse_port = true;
+
+    let first = bind_listener(&settings)
+        .await
+        .expect("Failed to bind the first listener");
+    settings.port = first.local_addr().unwrap().port();
+    let second = bind_listener(&settings)
+        .await
+        .expect("Failed to bind the second listener");
+
+    assert_eq!(first.local_addr().unwrap(), second.local_addr().unwrap());
+}
+
+#[tokio::test]
+async fn bind_listener_rejects_invalid_host() {
+    use coding_challenge::startup::bind_listener;
+
+    let mut settings = get_config().expect("Failed to read config").application;
+    settings.host = "not an address".into();
+
+    let err = bind_listener(&settings)
+        .await
+        .expect_err("Expected an invalid address");
+    assert!(err
+        .to_string()
+        .contains("Invalid bind address 'not an address'"));
+}
+
+#[tokio::test]
+async fn bind_listener_resolves_host_names() {
+    use coding_challenge::startup::bind_listener;
+
+    let mut settings = get_config().expect("Failed to read config").application;
+    settings.host = "localhost".into();
+    settings.port = 0;
+
+    let listener = bind_listener(&settings)
+        .await
+        .expect("Failed to bind to localhost");
+    assert!(listener.local_addr().unwrap().ip().is_loopback());
+}
+
+#[tokio::test]
+async fn get_animal_fact_returns_502_when_upstream_returns_html() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw("<html><body>Maintenance</body></html>", "text/html"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.api.cat_fallback_urls = vec![];
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(502, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("upstream_invalid_response", body["error"]["code"]);
+}
+
+#[tokio::test]
+async fn get_animal_fact_selects_fact_from_multi_fact_response() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/facts"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            r#"{"facts": ["short", "the longest fact", "mid fact"]}"#,
+            "application/json",
+        ))
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.dog_url = format!("{}/api/facts", mock_server.uri());
+        settings.cache.capacity = 0;
+        settings.facts.selection = Selection::Longest;
+    })
+    .await;
+
+    let client = Client::new();
+    let get = |query: &'static str| {
+        let client = client.clone();
+        async move {
+            let res = client
+                .get(format!("http://{addr}/v1/fact?animal=dog{query}"))
+                .send()
+                .await
+                .expect("Failed to execute request.");
+            assert_eq!(200, res.status().as_u16());
+            let body: Value = res.json().await.expect("Failed to parse response.");
+            body["fact"].as_str().unwrap().to_string()
+        }
+    };
+
+    assert_eq!("the longest fact", get("").await);
+    assert_eq!("short", get("&selection=first").await);
+    assert_eq!("the longest fact", get("&selection=longest").await);
+    let fact = get("&selection=random").await;
+    assert!(["short", "the longest fact", "mid fact"].contains(&fact.as_str()));
+}
+
+#[tokio::test]
+async fn get_animal_fact_selection_bypasses_the_cache() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/facts"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            r#"{"facts": ["short", "the longest fact", "mid fact"]}"#,
+            "application/json",
+        ))
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.dog_url = format!("{}/api/facts", mock_server.uri());
+        settings.cache.capacity = 10;
+        settings.facts.selection = Selection::Longest;
+    })
+    .await;
+
+    let client = Client::new();
+    let get = |query: &'static str| {
+        let client = client.clone();
+        async move {
+            let res = client
+                .get(format!("http://{addr}/v1/fact?animal=dog{query}"))
+                .send()
+                .await
+                .expect("Failed to execute request.");
+            assert_eq!(200, res.status().as_u16());
+            let body: Value = res.json().await.expect("Failed to parse response.");
+            body["fact"].as_str().unwrap().to_string()
+        }
+    };
+
+    // the first request caches the longest fact, which later ones must not be served
+    assert_eq!("the longest fact", get("").await);
+    assert_eq!("short", get("&selection=first").await);
+    let seeded = get("&selection=random&seed=7").await;
+    for _ in 0..3 {
+        assert_eq!(seeded, get("&selection=random&seed=7").await);
+    }
+    assert_eq!("the longest fact", get("").await);
+}
+
+#[tokio::test]
+async fn get_animal_fact_rejects_unknown_selection() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .get(format!(
+            "http://{addr}/v1/fact?animal=dog&selection=shortest"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn webhook_posts_the_fact_of_the_day_retrying_failures() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/webhook"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/webhook"))
+        .and(body_json(
+            serde_json::json!({ "animal": "cat", "fact": "cat fact" }),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let mut settings = get_config().expect("Failed to read config");
+    settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    settings.webhook.url = format!("{}/webhook", mock_server.uri());
+    settings.webhook.animal = "cat".into();
+    settings.webhook.retry_delay_ms = 1;
+    let state = AppState::new(settings);
+
+    let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+    push_daily_fact(&state, date)
+        .await
+        .expect("Failed to post to the webhook.");
+}
+
+#[test]
+#[should_panic(expected = "Invalid webhook time '9am'")]
+fn webhook_with_invalid_time_fails_at_startup() {
+    let mut settings = get_config().expect("Failed to read config");
+    settings.webhook.url = "http://127.0.0.1:1/webhook".into();
+    settings.webhook.time = "9am".into();
+
+    let _ = daily_webhook_time(&AppState::new(settings));
+}
+
+#[test]
+#[should_panic(expected = "Invalid webhook animal 'unicorn'")]
+fn webhook_with_invalid_animal_fails_at_startup() {
+    let mut settings = get_config().expect("Failed to read config");
+    settings.webhook.url = "http://127.0.0.1:1/webhook".into();
+    settings.webhook.animal = "unicorn".into();
+
+    let _ = daily_webhook_time(&AppState::new(settings));
+}
+
+#[test]
+#[should_panic(expected = "Invalid animal weights: 'unicorn' is not a supported animal")]
+fn invalid_animal_weights_fail_at_startup() {
+    let mut settings = get_config().expect("Failed to read config");
+    settings.facts.animal_weights = [("unicorn".to_string(), 1.0)].into();
+
+    let _ = AppState::new(settings);
+}
+
+#[tokio::test]
+async fn get_animal_fact_by_path_returns_a_fact() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact/cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!(
+        serde_json::json!({ "fact": "cat fact", "animal": "cat" }),
+        body
+    );
+}
+
+#[tokio::test]
+async fn padded_and_mixed_case_animals_are_normalized() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    })
+    .await;
+
+    for url in [
+        format!("http://{addr}/v1/fact?animal=%20cat%20"),
+        format!("http://{addr}/v1/fact?animal=%20%20KiTTen%09"),
+        format!("http://{addr}/v1/fact/%20CAT%20"),
+    ] {
+        let res = Client::new()
+            .get(&url)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(200, res.status().as_u16(), "{url}");
+        let body: Value = res.json().await.expect("Failed to parse response.");
+        assert_eq!("cat", body["animal"], "{url}");
+    }
+}
+
+#[tokio::test]
+async fn padded_animals_are_rejected_when_normalization_is_off() {
+    let TestApp { addr } = spawn_app_with(|settings| settings.facts.normalize_animal = false).await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=%20cat%20"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn configured_response_headers_are_set_on_fact_responses() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        let headers = &mut settings.response_headers.headers;
+        headers.insert("Cache-Control".into(), "no-store".into());
+        headers.insert("Server".into(), "animal-facts".into());
+        headers.insert("X-Frame-Options".into(), "SAMEORIGIN".into());
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let headers = res.headers();
+    assert_eq!("no-store", headers["cache-control"]);
+    assert_eq!("animal-facts", headers["server"]);
+    assert_eq!("SAMEORIGIN", headers["x-frame-options"]);
+    assert_eq!("nosniff", headers["x-content-type-options"]);
+    assert_eq!("no-referrer", headers["referrer-policy"]);
+}
+
+#[tokio::test]
+async fn security_headers_can_be_disabled() {
+    let TestApp { addr } =
+        spawn_app_with(|settings| settings.response_headers.security_headers = false).await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/health-check"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.headers().get("x-content-type-options").is_none());
+}
+
+#[tokio::test]
+async fn get_animal_fact_by_path_returns_400_for_unknown_animal() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact/dragon"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("unsupported_animal", body["error"]["code"]);
+}
+
+#[tokio::test]
+async fn fixed_fact_routes_take_precedence_over_animal_path() {
+    let TestApp { addr } = spawn_app().await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact/daily?date=yesterday"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("validation_failed", body["error"]["code"]);
+    assert!(body["error"]["errors"]["date"].is_array());
+}
+
+#[tokio::test]
+async fn selftest_passes_against_a_working_upstream() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let mut settings = get_config().expect("Failed to read config");
+    settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+
+    let checks = selftest(settings).await;
+
+    assert_eq!(2, checks.len());
+    assert!(checks.iter().all(Check::passed));
+    assert!(checks[1].detail.contains("cat fact"));
+}
+
+#[tokio::test]
+async fn selftest_fails_when_upstream_fails() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&mock_server)
+        .await;
+
+    let mut settings = get_config().expect("Failed to read config");
+    settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+    settings.api.cat_fallback_urls = vec![];
+
+    let checks = selftest(settings).await;
+
+    assert!(checks[0].passed());
+    assert!(!checks[1].passed());
+    assert_eq!(Some(404), checks[1].status);
+}
+
+#[tokio::test]
+async fn get_animal_fact_returns_bare_fact_without_envelope() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.cache.capacity = 0;
+    })
+    .await;
+
+    let client = Client::new();
+    let get = |query: &'static str| {
+        let client = client.clone();
+        async move {
+            let res = client
+                .get(format!("http://{addr}/v1/fact?{query}"))
+                .send()
+                .await
+                .expect("Failed to execute request.");
+            assert_eq!(200, res.status().as_u16());
+            res.json::<Value>()
+                .await
+                .expect("Failed to parse response.")
+        }
+    };
+
+    let body = get("animal=cat").await;
+    assert_eq!(
+        serde_json::json!({ "fact": "cat fact", "animal": "cat" }),
+        body
+    );
+    assert_eq!("cat fact", get("animal=cat&envelope=false").await);
+    assert_eq!(
+        serde_json::json!(["cat fact", "cat fact"]),
+        get("animal=cat&count=2&envelope=false").await
+    );
+}
+
+#[tokio::test]
+async fn envelope_can_be_disabled_by_config_and_requested_per_request() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/cat"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/dog"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"facts": ["dog fact"]}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/bird"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"fact": "bird fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/cat", mock_server.uri());
+        settings.api.dog_url = format!("{}/dog", mock_server.uri());
+        settings.api.bird_url = format!("{}/bird", mock_server.uri());
+        settings.facts.envelope = false;
+    })
+    .await;
+
+    let client = Client::new();
+    let res = client
+        .get(format!(
+            "http://{addr}/v1/fact/daily?animal=cat&date=2024-01-01"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("cat fact", body);
+
+    let res = client
+        .get(format!("http://{addr}/v1/fact/random"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert!(body.as_str().is_some_and(|fact| fact.ends_with(" fact")));
+
+    let res = client
+        .get(format!(
+            "http://{addr}/v1/fact/daily?animal=cat&envelope=true"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("cat fact", body["fact"]);
+    assert_eq!("cat", body["animal"]);
+}
+
+#[tokio::test]
+async fn get_random_fact_returns_a_fact_about_a_supported_animal() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/cat"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/dog"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"facts": ["dog fact"]}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/bird"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"fact": "bird fact"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/cat", mock_server.uri());
+        settings.api.dog_url = format!("{}/dog", mock_server.uri());
+        settings.api.bird_url = format!("{}/bird", mock_server.uri());
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact/random"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!("no-store", res.headers()["cache-control"]);
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    let animal = body["animal"].as_str().expect("Missing animal");
+    assert!(["cat", "dog", "bird"].contains(&animal));
+    assert_eq!(format!("{animal} fact"), body["fact"]);
+}
+
+/// Writes a self-signed certificate for `localhost` and its key to a temporary directory,
+/// returning the certificate PEM and the paths of both files.
+fn write_self_signed_cert() -> (String, String, String) {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])
+        .expect("Failed to generate certificate");
+    let cert_pem = cert
+        .serialize_pem()
+        .expect("Failed to serialize certificate");
+    let dir = std::env::temp_dir().join(format!("coding-challenge-tls-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).expect("Failed to create certificate directory");
+    let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+    std::fs::write(&cert_path, &cert_pem).expect("Failed to write certificate");
+    std::fs::write(&key_path, cert.serialize_private_key_pem()).expect("Failed to write key");
+    (
+        cert_pem,
+        cert_path.display().to_string(),
+        key_path.display().to_string(),
+    )
+}
+
+#[tokio::test]
+async fn server_serves_https_when_tls_is_configured() {
+    let (cert_pem, cert_path, key_path) = write_self_signed_cert();
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.tls.cert_path = cert_path;
+        settings.tls.key_path = key_path;
+    })
+    .await;
+
+    let client = Client::builder()
+        .add_root_certificate(
+            reqwest::Certificate::from_pem(cert_pem.as_bytes()).expect("Invalid certificate"),
+        )
+        .resolve("localhost", addr)
+        .build()
+        .expect("Failed to build HTTPS client");
+    let res = client
+        .get(format!("https://localhost:{}/health-check", addr.port()))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+}
+
+#[tokio::test]
+async fn server_serves_http2_when_enabled() {
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.server.http2 = true;
+        settings.server.keep_alive_interval_secs = 30;
+    })
+    .await;
+
+    let client = Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .expect("Failed to build HTTP/2 client");
+    let res = client
+        .get(format!("http://{addr}/health-check"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(res.status().is_success());
+    assert_eq!(reqwest::Version::HTTP_2, res.version());
+}
+
+#[tokio::test]
+async fn server_refuses_http2_prior_knowledge_when_disabled() {
+    let TestApp { addr } = spawn_app_with(|settings| settings.server.http2 = false).await;
+
+    let client = Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .expect("Failed to build HTTP/2 client");
+    let res = client
+        .get(format!("http://{addr}/health-check"))
+        .send()
+        .await;
+
+    assert!(res.is_err());
+}
+
+#[tokio::test]
+async fn server_closes_plain_http_connections_when_keep_alive_is_off() {
+    for (keep_alive, connection) in [(true, None), (false, Some("close"))] {
+        let TestApp { addr } =
+            spawn_app_with(|settings| settings.server.keep_alive = keep_alive).await;
+
+        let res = reqwest::get(format!("http://{addr}/health-check"))
+            .await
+            .expect("Failed to execute request.");
+
+        assert!(res.status().is_success());
+        assert_eq!(
+            connection,
+            res.headers()
+                .get("connection")
+                .map(|value| value.to_str().unwrap())
+        );
+    }
+}
+
+#[tokio::test]
+async fn server_negotiates_http2_over_https_only_when_enabled() {
+    for (http2, version) in [
+        (true, reqwest::Version::HTTP_2),
+        (false, reqwest::Version::HTTP_11),
+    ] {
+        let (cert_pem, cert_path, key_path) = write_self_signed_cert();
+        let TestApp { addr } = spawn_app_with(|settings| {
+            settings.tls.cert_path = cert_path;
+            settings.tls.key_path = key_path;
+            settings.server.http2 = http2;
+            settings.server.keep_alive_interval_secs = 30;
+        })
+        .await;
+
+        let client = Client::builder()
+            .add_root_certificate(
+                reqwest::Certificate::from_pem(cert_pem.as_bytes()).expect("Invalid certificate"),
+            )
+            .resolve("localhost", addr)
+            .build()
+            .expect("Failed to build HTTPS client");
+        let res = client
+            .get(format!("https://localhost:{}/health-check", addr.port()))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert!(res.status().is_success());
+        assert_eq!(version, res.version());
+    }
+}
+
+#[tokio::test]
+async fn server_fails_to_start_with_missing_tls_files() {
+    let mut settings = get_config().expect("Failed to read config");
+    settings.tls.cert_path = "/nonexistent/cert.pem".into();
+    settings.tls.key_path = "/nonexistent/key.pem".into();
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind to random port");
+
+    let Err(err) = coding_challenge::startup::run(listener, AppState::new(settings)) else {
+        panic!("Expected the server to fail to start");
+    };
+    assert!(err
+        .to_string()
+        .contains("Failed to read TLS certificate file '/nonexistent/cert.pem'"));
+}
+
+#[tokio::test]
+async fn get_animal_fact_returns_504_when_route_timeout_is_exceeded() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "slow cat fact"}"#, "application/json")
+                .set_delay(Duration::from_secs(2)),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.limits.fact_timeout_ms = 200;
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(504, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("request_timeout", body["error"]["code"]);
+}
+
+#[tokio::test]
+async fn latency_provider_order_tries_a_failing_upstream_last() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/failing"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/working"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"fact": "working fact"}"#, "application/json")
+                .set_delay(Duration::from_millis(100)),
+        )
+        .expect(3)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/failing", mock_server.uri());
+        settings.api.cat_fallback_urls = vec![format!("{}/working", mock_server.uri())];
+        settings.api.provider_order = ProviderOrder::Latency;
+        settings.retry.max_retries = 0;
+        settings.cache.capacity = 0;
+    })
+    .await;
+
+    let client = Client::new();
+    for _ in 0..3 {
+        let body: Value = client
+            .get(format!("http://{addr}/v1/fact?animal=cat"))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .json()
+            .await
+            .expect("Failed to parse response.");
+        assert_eq!("working fact", body["fact"]);
+    }
+}
+
+#[tokio::test]
+async fn latency_provider_order_prefers_the_fastest_upstream() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/slow"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "slow fact"}"#, "application/json")
+                .set_delay(Duration::from_millis(200)),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/fast"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"fact": "fast fact"}"#, "application/json"),
+        )
+        .expect(4)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/slow", mock_server.uri());
+        settings.api.cat_fallback_urls = vec![format!("{}/fast", mock_server.uri())];
+        settings.api.provider_order = ProviderOrder::Latency;
+        settings.cache.capacity = 0;
+    })
+    .await;
+
+    let client = Client::new();
+    let mut facts = vec![];
+    for _ in 0..5 {
+        let body: Value = client
+            .get(format!("http://{addr}/v1/fact?animal=cat"))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .json()
+            .await
+            .expect("Failed to parse response.");
+        facts.push(body["fact"].as_str().unwrap_or_default().to_string());
+    }
+
+    // each upstream is tried once while unmeasured, then the fast one is preferred
+    assert_eq!(
+        [
+            "slow fact",
+            "fast fact",
+            "fast fact",
+            "fast fact",
+            "fast fact"
+        ],
+        facts.as_slice()
+    );
+    let stats: Value = client
+        .get(format!("http://{addr}/stats"))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response.");
+    let latencies = &stats["upstream"]["latency_ms"];
+    let slow = latencies[format!("{}/slow", mock_server.uri())].as_f64();
+    let fast = latencies[format!("{}/fast", mock_server.uri())].as_f64();
+    assert!(fast < slow, "{latencies}");
+}
+
+#[tokio::test]
+async fn flushing_the_cache_makes_the_next_fact_hit_the_upstream() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/cat"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/cat", mock_server.uri());
+        settings.api.cat_fallback_urls = vec![];
+        settings.cache.capacity = 1;
+        settings.auth.admin_api_keys = vec!["admin".into()];
+    })
+    .await;
+
+    let client = Client::new();
+    let get_fact = || async {
+        let res = client
+            .get(format!("http://{addr}/v1/fact?animal=cat"))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(200, res.status().as_u16());
+    };
+    // the first fact fills the cache and the second is served from it
+    get_fact().await;
+    get_fact().await;
+
+    let res = client
+        .post(format!("http://{addr}/admin/cache/flush?animal=cat"))
+        .header("X-API-Key", "admin")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!(serde_json::json!({ "animal": "cat", "evicted": 1 }), body);
+
+    get_fact().await;
+}
+
+#[tokio::test]
+async fn warmer_fills_the_cache_so_facts_are_served_from_it() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/cat"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/dog"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"facts": ["dog fact"]}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/bird"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"fact": "bird fact"}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/cat", mock_server.uri());
+        settings.api.dog_url = format!("{}/dog", mock_server.uri());
+        settings.api.bird_url = format!("{}/bird", mock_server.uri());
+        settings.cache.capacity = 1;
+        settings.warmer.enabled = true;
+        settings.warmer.interval_secs = 1;
+    })
+    .await;
+
+    let client = Client::new();
+    let mut size = Value::Null;
+    for _ in 0..50 {
+        let stats: Value = client
+            .get(format!("http://{addr}/stats"))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .json()
+            .await
+            .expect("Failed to parse response.");
+        size = stats["cache"]["size"].clone();
+        if size == 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(3, size);
+
+    let res = client
+        .get(format!("http://{addr}/v1/fact?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("cat fact", body["fact"]);
+
+    // the next run leaves the facts still fresh alone, as the upstream expectations check
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+}
+
+/// Requests a bird fact from an upstream returning HTML at the given error detail level, and
+/// returns the error message sent back.
+async fn upstream_contract_error_message(detail: ErrorDetail) -> String {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/bird"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("<html>oops</html>", "text/html"))
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.bird_url = format!("{}/bird", mock_server.uri());
+        settings.errors.detail = detail;
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=bird"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(502, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("upstream_invalid_response", body["error"]["code"]);
+    body["error"]["message"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn full_error_detail_includes_upstream_details() {
+    let message = upstream_contract_error_message(ErrorDetail::Full).await;
+
+    assert!(message.starts_with("The animal API returned an unexpected response: "));
+    assert_ne!("The animal API returned an unexpected response.", message);
+}
+
+#[tokio::test]
+async fn generic_error_detail_hides_upstream_details() {
+    let message = upstream_contract_error_message(ErrorDetail::Generic).await;
+
+    assert_eq!("The animal API returned an unexpected response.", message);
+}
+
+#[tokio::test]
+async fn generic_error_detail_hides_upstream_details_from_graphql() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/bird"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("<html>oops</html>", "text/html"))
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.bird_url = format!("{}/bird", mock_server.uri());
+        settings.errors.detail = ErrorDetail::Generic;
+    })
+    .await;
+
+    let res = Client::new()
+        .post(format!("http://{addr}/graphql"))
+        .json(&serde_json::json!({ "query": "{ fact(animal: \"bird\") { fact } }" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    let error = &body["errors"][0];
+    assert_eq!("upstream_invalid_response", error["extensions"]["code"]);
+    assert_eq!(
+        "The animal API returned an unexpected response.",
+        error["message"]
+    );
+}
+
+#[tokio::test]
+async fn get_fact_feed_returns_an_rss_feed_of_facts() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "Cats & dogs <3 naps"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.feed.items = 2;
+        settings.feed.base_url = "https://facts.example.com/".into();
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact/feed?animal=cat&items=11"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(400, res.status().as_u16());
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact/feed?animal=cat"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!(
+        "application/rss+xml; charset=utf-8",
+        res.headers()["content-type"]
+    );
+    let body = res.text().await.expect("Failed to read response.");
+    let doc = roxmltree::Document::parse(&body).expect("Feed isn't well-formed XML.");
+    let items: Vec<_> = doc
+        .descendants()
+        .filter(|node| node.has_tag_name("item"))
+        .collect();
+    assert_eq!(2, items.len());
+    let link = doc
+        .descendants()
+        .find(|node| node.has_tag_name("link"))
+        .and_then(|node| node.text());
+    assert_eq!(
+        Some("https://facts.example.com/v1/fact/feed?animal=cat"),
+        link
+    );
+    let description = items[0]
+        .children()
+        .find(|node| node.has_tag_name("description"))
+        .and_then(|node| node.text());
+    assert_eq!(Some("Cats & dogs <3 naps"), description);
+    assert!(items[0].children().any(|node| node.has_tag_name("pubDate")));
+}
+
+#[tokio::test]
+async fn configured_animal_is_served_from_its_api() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/fox"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            r#"{"data": {"text": "Foxes use the Earth's magnetic field to hunt."}}"#,
+            "application/json",
+        ))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.animals.insert(
+            "fox".into(),
+            AnimalApiSettings {
+                url: format!("{}/fox", mock_server.uri()),
+                fact_pointer: "/data/text".into(),
+            },
+        );
+    })
+    .await;
+
+    let client = Client::new();
+    let res = client
+        .get(format!("http://{addr}/v1/fact?animal=Fox"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!(
+        "Foxes use the Earth's magnetic field to hunt.",
+        body["fact"]
+    );
+    assert_eq!("fox", body["animal"]);
+
+    let body: Value = client
+        .get(format!("http://{addr}/v1/animals"))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response.");
+    assert_eq!(
+        serde_json::json!(["cat", "dog", "bird", "fox", "any"]),
+        body["animals"]
+    );
+}
+
+#[tokio::test]
+async fn concurrent_cache_misses_share_one_upstream_call() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/facts/random"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"text": "cat fact"}"#, "application/json")
+                .set_delay(Duration::from_millis(200)),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/facts/random", mock_server.uri());
+        settings.user_facts.share = 0.0;
+    })
+    .await;
+
+    let client = Client::new();
+    let requests = (0..5).map(|_| {
+        client
+            .get(format!("http://{addr}/v1/fact?animal=cat"))
+            .send()
+    });
+    let responses = futures::future::join_all(requests).await;
+
+    for res in responses {
+        let res = res.expect("Failed to execute request.");
+        assert_eq!(200, res.status().as_u16());
+        let body: Value = res.json().await.expect("Failed to parse response.");
+        assert_eq!("cat fact", body["fact"]);
+    }
+}
+
+#[tokio::test]
+async fn get_animal_fact_from_all_sources_reports_each_source() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/cat"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cat fact"}"#, "application/json"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/cat-fallback"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/cat", mock_server.uri());
+        settings.api.cat_fallback_urls = vec![format!("{}/cat-fallback", mock_server.uri())];
+        settings.retry.max_retries = 0;
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!("http://{addr}/v1/fact?animal=cat&all_sources=true"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let body: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("cat", body["animal"]);
+    let sources = body["sources"].as_object().unwrap();
+    assert_eq!(2, sources.len());
+    assert_eq!("cat fact", sources["primary"]);
+    assert_eq!("upstream_error", sources["fallback_1"]["error"]["code"]);
+}
+
+#[tokio::test]
+async fn get_animal_fact_from_all_sources_applies_the_filter_and_envelope() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/cat"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(r#"{"text": "cats purr"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/cat-fallback"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"{"fact": "cats sleep"}"#, "application/json"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let TestApp { addr } = spawn_app_with(|settings| {
+        settings.api.cat_url = format!("{}/cat", mock_server.uri());
+        settings.api.cat_fallback_urls = vec![format!("{}/cat-fallback", mock_server.uri())];
+        settings.filter.max_attempts = 2;
+    })
+    .await;
+
+    let res = Client::new()
+        .get(format!(
+            "http://{addr}/v1/fact?animal=cat&all_sources=true&contains=purr&envelope=false"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, res.status().as_u16());
+    let sources: Value = res.json().await.expect("Failed to parse response.");
+    assert_eq!("cats purr", sources["primary"]);
+    assert_eq!("no_matching_fact", sources["fallback_1"]["error"]["code"]);
+}